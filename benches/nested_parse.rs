@@ -0,0 +1,64 @@
+//! 解析大量chunk字段时，猜测并展开嵌套消息的耗时和内存分配次数
+//!
+//! 使用`cargo bench --bench nested_parse`运行，分配次数由计数的全局分配器统计
+
+use protobuf_inspector_rs::parser::Parser;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const ITERATIONS: usize = 500;
+
+fn run(name: &str, payload: &[u8]) {
+    let mut parser = Parser::new();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(parser.parse_message(std::hint::black_box(payload), "root").unwrap());
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!("{:<12} {:>10.2?}/parse {:>8} allocations/parse", name, elapsed / ITERATIONS as u32, allocations / ITERATIONS);
+}
+
+/// `field 1`的chunk字段，长度不超过127
+fn chunk(body: &[u8]) -> Vec<u8> {
+    let mut field = vec![0x0a, body.len() as u8];
+    field.extend_from_slice(body);
+    field
+}
+
+fn main() {
+    // 100个嵌套消息，每个里面是{1: 150, 2: "name", 3: {1: 1}}
+    let message = [&b"\x08\x96\x01\x12\x04name"[..], &chunk(b"\x08\x01")].concat();
+    let nested: Vec<u8> = (0..100).flat_map(|_| chunk(&message)).collect();
+    run("nested", &nested);
+
+    // 100个字符串，开头的几个字节看起来像字段，需要读到后面才能否定
+    let text = b"\x08\x01\x10\x02 not a message, just text";
+    let strings: Vec<u8> = (0..100).flat_map(|_| chunk(text)).collect();
+    run("strings", &strings);
+}
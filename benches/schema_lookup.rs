@@ -0,0 +1,65 @@
+//! 重复解析同一类型消息时的耗时和内存分配次数
+//!
+//! 使用`cargo bench --bench schema_lookup`运行，分配次数由计数的全局分配器统计
+
+use protobuf_inspector_rs::parser::Parser;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const ITERATIONS: usize = 2000;
+
+fn run(name: &str, parser: &mut Parser, payload: &[u8]) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(parser.parse_message(std::hint::black_box(payload), "root").unwrap());
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!("{:<12} {:>10.2?}/parse {:>8} allocations/parse", name, elapsed / ITERATIONS as u32, allocations / ITERATIONS);
+}
+
+fn main() {
+    // 100个varint字段，前一半在schema中有类型和名称
+    let mut payload = Vec::new();
+    for number in 1..=100u32 {
+        let tag = number << 3;
+        if tag < 0x80 {
+            payload.push(tag as u8);
+        } else {
+            payload.extend([(tag as u8) | 0x80, (tag >> 7) as u8]);
+        }
+        payload.push(number as u8);
+    }
+
+    let mut parser = Parser::new();
+    run("no schema", &mut parser, &payload);
+
+    let schema: String = (1..=50).map(|n| format!("{} = [\"uint32\", \"field_{}\"]\n", n, n)).collect();
+    parser.load_schema(&format!("[root]\n{}", schema)).unwrap();
+    run("schema", &mut parser, &payload);
+}
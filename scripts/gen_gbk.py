@@ -0,0 +1,35 @@
+#!/usr/bin/env python3
+"""生成src/gbk.bin：GBK双字节字符的码表，供src/charset.rs使用
+
+首字节0x81-0xFE，尾字节0x40-0xFE，按(首字节, 尾字节)的顺序每项写入2字节小端序的Unicode码位，
+无法解码的组合写入0。码表来自Python自带的`gbk`编码，更新Python版本后可以重新运行：
+
+    python3 scripts/gen_gbk.py src/gbk.bin
+"""
+
+import struct
+import sys
+
+
+def gbk_table():
+    table = bytearray()
+    for lead in range(0x81, 0xFF):
+        for trail in range(0x40, 0xFF):
+            try:
+                text = bytes([lead, trail]).decode("gbk")
+            except UnicodeDecodeError:
+                text = ""
+            code = ord(text) if len(text) == 1 and ord(text) <= 0xFFFF else 0
+            table += struct.pack("<H", code)
+    return bytes(table)
+
+
+def main():
+    if len(sys.argv) != 2:
+        sys.exit("usage: gen_gbk.py OUTPUT")
+    with open(sys.argv[1], "wb") as output:
+        output.write(gbk_table())
+
+
+if __name__ == "__main__":
+    main()
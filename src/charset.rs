@@ -0,0 +1,69 @@
+/// GBK双字节字符的码表：首字节0x81-0xFE，尾字节0x40-0xFE，每项为小端序的Unicode码位，0表示未定义
+///
+/// 由`scripts/gen_gbk.py`使用Python的`gbk`编码生成：依次解码每个(首字节, 尾字节)组合，无法解码的写入0
+static GBK_TABLE: &[u8] = include_bytes!("gbk.bin");
+
+const GBK_TRAIL_COUNT: usize = 0xFF - 0x40;
+
+/// ISO-8859-1（Latin-1），每个字节都直接对应同样码位的字符，不会失败
+pub fn decode_latin1(data: &[u8]) -> String {
+    data.iter().map(|&b| b as char).collect()
+}
+
+/// GBK，ASCII部分单字节，其他字符为双字节。不完整或未定义的字符替换为U+FFFD，
+/// 同时返回是否所有字符都有效
+pub fn decode_gbk(data: &[u8]) -> (String, bool) {
+    let mut result = String::with_capacity(data.len());
+    let mut valid = true;
+    let mut index = 0;
+    while index < data.len() {
+        let lead = data[index];
+        if lead < 0x80 {
+            result.push(lead as char);
+            index += 1;
+            continue;
+        }
+        match data.get(index + 1).and_then(|&trail| gbk_char(lead, trail)) {
+            Some(c) => {
+                result.push(c);
+                index += 2;
+            }
+            // 只跳过首字节，尾字节可能是下一个字符的开头
+            None => {
+                result.push(char::REPLACEMENT_CHARACTER);
+                valid = false;
+                index += 1;
+            }
+        }
+    }
+    (result, valid)
+}
+
+fn gbk_char(lead: u8, trail: u8) -> Option<char> {
+    if !(0x81..=0xFE).contains(&lead) || !(0x40..=0xFE).contains(&trail) {
+        return None;
+    }
+    let index = ((lead - 0x81) as usize * GBK_TRAIL_COUNT + (trail - 0x40) as usize) * 2;
+    let code = u16::from_le_bytes([GBK_TABLE[index], GBK_TABLE[index + 1]]);
+    char::from_u32(code as u32).filter(|_| code != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_latin1() {
+        assert_eq!(decode_latin1(b"caf\xe9 cr\xe8me"), "café crème");
+    }
+
+    #[test]
+    fn test_decode_gbk() {
+        assert_eq!(GBK_TABLE.len(), (0xFE - 0x81 + 1) * GBK_TRAIL_COUNT * 2);
+        assert_eq!(decode_gbk(b"\xd6\xd0\xce\xc4 abc"), ("中文 abc".to_string(), true));
+        // 不完整的双字节字符和未定义的尾字节
+        assert_eq!(decode_gbk(b"\xd6"), ("\u{FFFD}".to_string(), false));
+        assert_eq!(decode_gbk(b"\xd6\x7f"), ("\u{FFFD}\x7f".to_string(), false));
+        assert_eq!(decode_gbk(b"\x80\xd6\xd0"), ("\u{FFFD}中".to_string(), false));
+    }
+}
@@ -1,37 +1,63 @@
+use std::fmt;
 use std::io::{self, Read};
+use std::ops::RangeInclusive;
 
 #[derive(Debug)]
 pub enum Error {
     Eof,
     InvalidVarint,
-    InvalidWireType,
+    /// 标识符中的wire type不是0到5
+    InvalidWireType(u8),
+    InvalidFieldNumber,
+    /// 嵌套消息中的字段超出了所在消息的范围
+    ChildExceedsParent,
+    /// 声明为`strict`的字符串字段中有按其编码无法解码的字节
+    InvalidString,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Eof => write!(f, "unexpected end of input"),
+            Error::InvalidVarint => write!(f, "invalid varint encoding"),
+            Error::InvalidWireType(wire_type) => write!(f, "invalid wire type {}", wire_type),
+            Error::InvalidFieldNumber => write!(f, "invalid field number"),
+            Error::ChildExceedsParent => write!(f, "nested field exceeds the enclosing message"),
+            Error::InvalidString => write!(f, "invalid bytes in strict string"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// protobuf保留给内部实现的field number，不能出现在数据中
+pub const RESERVED_FIELD_NUMBERS: RangeInclusive<u32> = 19000..=19999;
+
+/// field number的最大值 (2^29 - 1)
+pub const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+
+pub fn is_valid_field_number(n: u32) -> bool {
+    n != 0 && n <= MAX_FIELD_NUMBER && !RESERVED_FIELD_NUMBERS.contains(&n)
+}
+
+/// 读取一个varint，过长的非最短编码视为错误
 pub fn read_varint<R: Read>(reader: &mut R) -> Result<Option<u64>, Error> {
-    let mut result = 0u64;
-    let mut pos = 0;
+    let mut buf = Vec::new();
     
     loop {
-        let mut buf = [0u8; 1];
-        match reader.read_exact(&mut buf) {
+        let mut byte = [0u8; 1];
+        match reader.read_exact(&mut byte) {
             Ok(()) => {
-                let b = buf[0];
-                result |= ((b & 0x7F) as u64) << pos;
-                pos += 7;
-                
-                if b & 0x80 == 0 {
-                    if b == 0 && pos != 7 {
-                        return Err(Error::InvalidVarint);
-                    }
-                    return Ok(Some(result));
+                buf.push(byte[0]);
+                if byte[0] & 0x80 == 0 {
+                    return parse_varint_bytes(&buf).map(Some);
                 }
-                
-                if pos >= 64 {
+                if buf.len() >= 10 {
                     return Err(Error::InvalidVarint);
                 }
             }
             Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
-                if pos == 0 {
+                if buf.is_empty() {
                     return Ok(None);
                 }
                 return Err(Error::Eof);
@@ -75,55 +101,80 @@ pub fn read_value<R: Read>(reader: &mut R, wire_type: u8) -> Result<Option<Vec<u
                 }
             }
         }
-        1 => {
-            let mut buf = vec![0u8; 8];
-            match reader.read_exact(&mut buf) {
-                Ok(()) => Ok(Some(buf)),
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
-                Err(_) => Err(Error::Eof),
-            }
-        }
+        1 => read_fixed(reader, 8),
         2 => {
             let length = match read_varint(reader)? {
-                Some(len) => len as usize,
+                Some(len) => len,
                 None => return Ok(None),
             };
             
-            let mut buf = vec![0u8; length];
-            match reader.read_exact(&mut buf) {
-                Ok(()) => Ok(Some(buf)),
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            // 长度来自不可信的输入，按实际读到的数据增长缓冲区，而不是预先分配
+            let mut buf = Vec::new();
+            match reader.take(length).read_to_end(&mut buf) {
+                Ok(n) if n as u64 == length => Ok(Some(buf)),
+                Ok(_) => Ok(None),
                 Err(_) => Err(Error::Eof),
             }
         }
         3 | 4 => {
             Ok(Some(vec![wire_type]))
         }
-        5 => {
-            let mut buf = vec![0u8; 4];
-            match reader.read_exact(&mut buf) {
-                Ok(()) => Ok(Some(buf)),
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
-                Err(_) => Err(Error::Eof),
+        5 => read_fixed(reader, 4),
+        _ => Err(Error::InvalidWireType(wire_type)),
+    }
+}
+
+/// 读取固定长度的值：一个字节都没有时为`None`，只读到一部分说明数据被截断
+fn read_fixed<R: Read>(reader: &mut R, size: u64) -> Result<Option<Vec<u8>>, Error> {
+    let mut buf = Vec::new();
+    match reader.take(size).read_to_end(&mut buf) {
+        Ok(0) => Ok(None),
+        Ok(n) if n as u64 == size => Ok(Some(buf)),
+        _ => Err(Error::Eof),
+    }
+}
+
+/// 在已知边界的数据中读取字段值，chunk的长度在分配内存之前检查
+///
+/// 数据不足时，顶层消息（`depth == 0`）返回`Eof`，嵌套消息返回`ChildExceedsParent`
+pub fn read_bounded_value(cursor: &mut io::Cursor<&[u8]>, wire_type: u8, depth: usize) -> Result<Vec<u8>, Error> {
+    let overrun = || if depth > 0 { Error::ChildExceedsParent } else { Error::Eof };
+    
+    if wire_type == 2 {
+        let mut peek = cursor.clone();
+        if let Some(length) = read_varint(&mut peek)? {
+            let remaining = (peek.get_ref().len() as u64).saturating_sub(peek.position());
+            if length > remaining {
+                return Err(overrun());
             }
         }
-        _ => Err(Error::InvalidWireType),
+    }
+    
+    match read_value(cursor, wire_type) {
+        Ok(Some(value)) => Ok(value),
+        Ok(None) | Err(Error::Eof) => Err(overrun()),
+        Err(e) => Err(e),
     }
 }
 
-pub fn parse_varint_bytes(buf: &[u8]) -> Result<u64, Error> {
+/// 解码varint，同时返回它是否为最短编码；所有varint的编码检查都经过这里
+///
+/// `buf`必须恰好是一个varint，结束字节后面还有数据时视为错误
+pub fn decode_varint_bytes(buf: &[u8]) -> Result<(u64, bool), Error> {
     let mut result = 0u64;
     let mut pos = 0;
     
-    for &b in buf {
+    for (index, &b) in buf.iter().enumerate() {
         result |= ((b & 0x7F) as u64) << pos;
         pos += 7;
         
         if b & 0x80 == 0 {
-            if b == 0 && pos != 7 {
+            if index + 1 != buf.len() {
                 return Err(Error::InvalidVarint);
             }
-            return Ok(result);
+            // 最后一个字节为0说明前面可以更早结束
+            let canonical = b != 0 || pos == 7;
+            return Ok((result, canonical));
         }
         
         if pos >= 64 {
@@ -134,12 +185,121 @@ pub fn parse_varint_bytes(buf: &[u8]) -> Result<u64, Error> {
     Err(Error::InvalidVarint)
 }
 
+/// 解码varint，非最短编码视为错误
+pub fn parse_varint_bytes(buf: &[u8]) -> Result<u64, Error> {
+    match decode_varint_bytes(buf)? {
+        (value, true) => Ok(value),
+        (_, false) => Err(Error::InvalidVarint),
+    }
+}
+
+/// 把整个数据块按连续的varint解析，任何多余或不完整的字节都视为错误
+pub fn read_packed_varints(data: &[u8]) -> Result<Vec<u64>, Error> {
+    let mut cursor = io::Cursor::new(data);
+    let mut values = Vec::new();
+    while let Some(value) = read_varint(&mut cursor)? {
+        values.push(value);
+    }
+    Ok(values)
+}
+
+pub fn encode_varint(mut value: u64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    loop {
+        let b = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(b);
+            return buf;
+        }
+        buf.push(b | 0x80);
+    }
+}
+
+/// 字段的标识符：field number左移3位后与wire type组合，再按varint编码
+pub fn encode_tag(number: u32, wire_type: u8) -> Vec<u8> {
+    encode_varint(((number as u64) << 3) | wire_type as u64)
+}
+
+/// sint32/sint64的编码：0, -1, 1, -2 ...依次对应0, 1, 2, 3 ...
+pub fn zigzag_encode(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
 pub fn zigzag_decode(n: u64) -> i64 {
-    let negative = (n & 1) != 0;
-    let x = (n >> 1) as i64;
-    if negative {
-        -(x + 1)
-    } else {
-        x
+    // 用异或还原符号位，避免u64::MAX时`-(x + 1)`溢出
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_field_number() {
+        assert!(!is_valid_field_number(0));
+        assert!(is_valid_field_number(1));
+        assert!(is_valid_field_number(18999));
+        assert!(!is_valid_field_number(19000));
+        assert!(!is_valid_field_number(19999));
+        assert!(is_valid_field_number(20000));
+        assert!(is_valid_field_number(MAX_FIELD_NUMBER));
+        assert!(!is_valid_field_number(MAX_FIELD_NUMBER + 1));
+    }
+
+    #[test]
+    fn test_read_value_huge_length() {
+        // 声称长度为u64::MAX的chunk，实际只有3字节
+        let data = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01abc";
+        assert_eq!(read_value(&mut io::Cursor::new(&data[..]), 2).unwrap(), None);
+        assert!(matches!(read_bounded_value(&mut io::Cursor::new(&data[..]), 2, 0), Err(Error::Eof)));
+        
+        assert_eq!(read_value(&mut io::Cursor::new(&b"\x03abc"[..]), 2).unwrap(), Some(b"abc".to_vec()));
+    }
+
+    #[test]
+    fn test_read_value_truncated_fixed() {
+        assert_eq!(read_value(&mut io::Cursor::new(&b""[..]), 5).unwrap(), None);
+        assert!(matches!(read_value(&mut io::Cursor::new(&b"\x01\x02"[..]), 5), Err(Error::Eof)));
+        assert_eq!(read_value(&mut io::Cursor::new(&b"\x01\x02\x03\x04\x05"[..]), 5).unwrap(), Some(b"\x01\x02\x03\x04".to_vec()));
+        
+        assert_eq!(read_value(&mut io::Cursor::new(&b""[..]), 1).unwrap(), None);
+        assert!(matches!(read_value(&mut io::Cursor::new(&b"\x01\x02\x03\x04"[..]), 1), Err(Error::Eof)));
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(Error::Eof.to_string(), "unexpected end of input");
+        let error = read_value(&mut io::Cursor::new(&b"\x01"[..]), 7).unwrap_err();
+        assert_eq!(error.to_string(), "invalid wire type 7");
+        
+        let boxed: Box<dyn std::error::Error> = Box::new(Error::InvalidVarint);
+        assert_eq!(boxed.to_string(), "invalid varint encoding");
+    }
+
+    #[test]
+    fn test_encode_tag() {
+        assert_eq!(encode_tag(1, 0), b"\x08");
+        assert_eq!(encode_tag(2, 2), b"\x12");
+        assert_eq!(encode_tag(16, 0), b"\x80\x01");
+        assert_eq!(read_identifier(&mut io::Cursor::new(&encode_tag(MAX_FIELD_NUMBER, 5)[..])).unwrap(), Some((MAX_FIELD_NUMBER, 5)));
+    }
+
+    #[test]
+    fn test_zigzag_decode() {
+        assert_eq!(zigzag_decode(0), 0);
+        assert_eq!(zigzag_decode(1), -1);
+        assert_eq!(zigzag_decode(2), 1);
+        assert_eq!(zigzag_decode(u64::MAX), i64::MIN);
+        assert_eq!(zigzag_decode(u64::MAX - 1), i64::MAX);
+        assert_eq!(zigzag_decode(zigzag_encode(-12345)), -12345);
+    }
+
+    #[test]
+    fn test_varint_trailing_bytes() {
+        assert_eq!(parse_varint_bytes(b"\x96\x01").unwrap(), 150);
+        // 第一个字节已经结束了varint
+        assert!(matches!(parse_varint_bytes(b"\x01\x02"), Err(Error::InvalidVarint)));
+        assert!(matches!(decode_varint_bytes(b"\x80\x00\x01"), Err(Error::InvalidVarint)));
     }
 }
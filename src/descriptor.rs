@@ -0,0 +1,310 @@
+use crate::core::{self, parse_varint_bytes};
+use crate::schema::{EnumDefinition, Schema};
+use crate::tree::{decode_field, Field};
+use crate::well_known::WELL_KNOWN_TYPES;
+use std::collections::{BTreeMap, HashMap};
+
+/// `FieldDescriptorProto.label`中表示repeated的值
+const LABEL_REPEATED: u64 = 3;
+/// `FieldDescriptorProto.type`中需要通过`type_name`查找的类型
+const TYPE_GROUP: u64 = 10;
+const TYPE_MESSAGE: u64 = 11;
+const TYPE_ENUM: u64 = 14;
+
+/// 描述文件中的一个message：注册使用的完整名称、各个`FieldDescriptorProto`和是否为map的entry
+struct MessageDescriptor {
+    name: String,
+    fields: Vec<Vec<Field>>,
+    map_entry: bool,
+}
+
+/// 解析`protoc --descriptor_set_out`生成的`FileDescriptorSet`，返回schema和按名称排序的所有enum。
+/// message和enum都以去掉开头`.`的完整名称（`pkg.Outer.Inner`）注册，字段引用的类型同样使用完整名称，
+/// 不同作用域中的同名类型不会互相覆盖
+///
+/// map字段显示为`map<K, V>`，对应的entry消息不会单独注册；引用了集合之外的类型时按猜测展开
+pub fn parse_descriptor_set(data: &[u8]) -> Result<(Schema, Vec<EnumDefinition>), core::Error> {
+    // 完整名称（`.pkg.Outer.Inner`） -> message
+    let mut messages = HashMap::new();
+    // 完整名称 -> enum，按名称排序使返回的顺序固定
+    let mut enums = BTreeMap::new();
+
+    // FileDescriptorSet: 1 = file
+    for file in read_fields(data)?.iter().filter(|f| f.number == 1) {
+        // FileDescriptorProto: 2 = package, 4 = message_type, 5 = enum_type
+        let file = read_fields(&file.raw)?;
+        let scope = match string_field(&file, 2) {
+            Some(package) => format!(".{}", package),
+            None => String::new(),
+        };
+        for message in file.iter().filter(|f| f.number == 4) {
+            collect_message(&message.raw, &scope, &mut messages, &mut enums)?;
+        }
+        for definition in file.iter().filter(|f| f.number == 5) {
+            let (name, values) = read_enum(&definition.raw)?;
+            let full_name = format!("{}.{}", scope, name);
+            enums.insert(full_name.clone(), (full_name[1..].to_string(), values));
+        }
+    }
+
+    let mut schema = Schema::new();
+    for message in messages.values().filter(|m| !m.map_entry) {
+        let fields = schema.entry(message.name.clone()).or_default();
+        for field in &message.fields {
+            // FieldDescriptorProto: 1 = name, 3 = number, 4 = label
+            let Some(number) = varint_field(field, 3).and_then(|n| u32::try_from(n).ok()) else {
+                return Err(core::Error::InvalidFieldNumber);
+            };
+            let name = string_field(field, 1).unwrap_or_default();
+            let field_type = match field_type(field, &messages, &enums) {
+                FieldType::Map(key, value) => format!("map<{}, {}>", key, value),
+                FieldType::Single(field_type) if varint_field(field, 4) == Some(LABEL_REPEATED) => {
+                    format!("{} repeated", field_type)
+                }
+                FieldType::Single(field_type) => field_type,
+            };
+            fields.insert(number, (field_type.into(), name.into()));
+        }
+    }
+    Ok((schema, enums.into_values().collect()))
+}
+
+/// 依次读取消息中的所有字段，不猜测嵌套消息
+fn read_fields(data: &[u8]) -> Result<Vec<Field>, core::Error> {
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let (field, next) = decode_field(data, offset)?;
+        fields.push(field);
+        offset = next;
+    }
+    Ok(fields)
+}
+
+/// 字段多次出现时以最后一次为准
+fn string_field(fields: &[Field], number: u32) -> Option<String> {
+    let field = fields.iter().rev().find(|f| f.number == number)?;
+    Some(String::from_utf8_lossy(&field.raw).into_owned())
+}
+
+fn varint_field(fields: &[Field], number: u32) -> Option<u64> {
+    let field = fields.iter().rev().find(|f| f.number == number)?;
+    parse_varint_bytes(&field.raw).ok()
+}
+
+/// 递归读取`DescriptorProto`：1 = name, 2 = field, 3 = nested_type, 4 = enum_type, 7 = options
+fn collect_message(
+    data: &[u8],
+    scope: &str,
+    messages: &mut HashMap<String, MessageDescriptor>,
+    enums: &mut BTreeMap<String, EnumDefinition>,
+) -> Result<(), core::Error> {
+    let fields = read_fields(data)?;
+    let full_name = format!("{}.{}", scope, string_field(&fields, 1).unwrap_or_default());
+    let name = full_name[1..].to_string();
+
+    for nested in fields.iter().filter(|f| f.number == 3) {
+        collect_message(&nested.raw, &full_name, messages, enums)?;
+    }
+    for definition in fields.iter().filter(|f| f.number == 4) {
+        let (enum_name, values) = read_enum(&definition.raw)?;
+        let enum_full_name = format!("{}.{}", full_name, enum_name);
+        enums.insert(enum_full_name.clone(), (enum_full_name[1..].to_string(), values));
+    }
+
+    // MessageOptions: 7 = map_entry
+    let map_entry = match fields.iter().rev().find(|f| f.number == 7) {
+        Some(options) => varint_field(&read_fields(&options.raw)?, 7) == Some(1),
+        None => false,
+    };
+    let descriptors = fields.iter()
+        .filter(|f| f.number == 2)
+        .map(|f| read_fields(&f.raw))
+        .collect::<Result<_, _>>()?;
+    messages.insert(full_name, MessageDescriptor { name, fields: descriptors, map_entry });
+    Ok(())
+}
+
+/// `EnumDescriptorProto`：1 = name, 2 = value（`EnumValueDescriptorProto`：1 = name, 2 = number）
+fn read_enum(data: &[u8]) -> Result<EnumDefinition, core::Error> {
+    let fields = read_fields(data)?;
+    let mut values = Vec::new();
+    for value in fields.iter().filter(|f| f.number == 2) {
+        let value = read_fields(&value.raw)?;
+        // 负数按int32的规则编码为10字节的varint
+        let number = varint_field(&value, 2).unwrap_or_default() as i32 as i64;
+        values.push((number, string_field(&value, 1).unwrap_or_default()));
+    }
+    Ok((string_field(&fields, 1).unwrap_or_default(), values))
+}
+
+enum FieldType {
+    Single(String),
+    Map(String, String),
+}
+
+/// `FieldDescriptorProto`：5 = type, 6 = type_name
+fn field_type(
+    field: &[Field],
+    messages: &HashMap<String, MessageDescriptor>,
+    enums: &BTreeMap<String, EnumDefinition>,
+) -> FieldType {
+    let type_name = string_field(field, 6).unwrap_or_default();
+    let single = match varint_field(field, 5) {
+        Some(TYPE_MESSAGE | TYPE_GROUP) if type_name == ".google.protobuf.Any" => "any".to_string(),
+        Some(TYPE_MESSAGE | TYPE_GROUP) if WELL_KNOWN_TYPES.iter().any(|(known, _)| type_name.strip_prefix('.') == Some(known)) => {
+            type_name[1..].to_string()
+        }
+        Some(TYPE_MESSAGE | TYPE_GROUP) => match messages.get(&type_name) {
+            Some(entry) if entry.map_entry => {
+                let item_type = |number| entry.fields.iter()
+                    .find(|f| varint_field(f, 3) == Some(number))
+                    .map(|f| match field_type(f, messages, enums) {
+                        FieldType::Single(item_type) => item_type,
+                        FieldType::Map(..) => "message".to_string(),
+                    })
+                    .unwrap_or_else(|| "message".to_string());
+                return FieldType::Map(item_type(1), item_type(2));
+            }
+            Some(message) => message.name.clone(),
+            None => "message".to_string(),
+        },
+        Some(TYPE_ENUM) => match enums.get(&type_name) {
+            Some((name, _)) => name.clone(),
+            None => "int32".to_string(),
+        },
+        Some(type_number) => scalar_type(type_number).unwrap_or("message").to_string(),
+        // 没有经过protoc链接的描述文件可能只有type_name
+        None if messages.contains_key(&type_name) => messages[&type_name].name.clone(),
+        None => enums.get(&type_name).map_or_else(|| "message".to_string(), |(name, _)| name.clone()),
+    };
+    FieldType::Single(single)
+}
+
+/// `FieldDescriptorProto.Type`中的标量类型
+fn scalar_type(type_number: u64) -> Option<&'static str> {
+    Some(match type_number {
+        1 => "double",
+        2 => "float",
+        3 => "int64",
+        4 => "uint64",
+        5 => "int32",
+        6 => "fixed64",
+        7 => "fixed32",
+        8 => "bool",
+        9 => "string",
+        12 => "bytes",
+        13 => "uint32",
+        15 => "sfixed32",
+        16 => "sfixed64",
+        17 => "sint32",
+        18 => "sint64",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::strip_ansi;
+    use crate::parser::Parser;
+    use crate::testutil::Payload;
+
+    /// `FieldDescriptorProto`，label 1为optional，3为repeated
+    fn field(name: &str, number: u64, label: u64, field_type: u64, type_name: &str) -> Payload {
+        let field = Payload::new()
+            .field(1).string(name)
+            .field(3).varint(number)
+            .field(4).varint(label)
+            .field(5).varint(field_type);
+        match type_name {
+            "" => field,
+            _ => field.field(6).string(type_name),
+        }
+    }
+
+    fn enum_value(name: &str, number: i64) -> Payload {
+        Payload::new().field(1).string(name).field(2).varint(number as u64)
+    }
+
+    #[test]
+    fn test_load_descriptor_set() {
+        // package shop;
+        // message Order {
+        //     message Item { string sku = 1; }
+        //     uint64 id = 1;
+        //     repeated Item items = 2;
+        //     map<string, int32> counts = 3;
+        //     Status status = 4;
+        //     enum Status { NONE = 0; SHIPPED = 2; }
+        //     Order.Status shipping = 5;
+        // }
+        // enum Status { UNKNOWN = 0; PAID = 1; REFUNDED = -1; }
+        let item = Payload::new()
+            .field(1).string("Item")
+            .field(2).message(field("sku", 1, 1, 9, ""));
+        let counts_entry = Payload::new()
+            .field(1).string("CountsEntry")
+            .field(2).message(field("key", 1, 1, 9, "")).message(field("value", 2, 1, 5, ""))
+            .field(7).message(Payload::new().field(7).varint(1));
+        let order = Payload::new()
+            .field(1).string("Order")
+            .field(2)
+            .message(field("id", 1, 1, 4, ""))
+            .message(field("items", 2, 3, 11, ".shop.Order.Item"))
+            .message(field("counts", 3, 3, 11, ".shop.Order.CountsEntry"))
+            .message(field("status", 4, 1, 14, ".shop.Status"))
+            .message(field("shipping", 5, 1, 14, ".shop.Order.Status"))
+            .field(3).message(item).message(counts_entry)
+            .field(4).message(Payload::new()
+                .field(1).string("Status")
+                .field(2).message(enum_value("NONE", 0)).message(enum_value("SHIPPED", 2)));
+        let status = Payload::new()
+            .field(1).string("Status")
+            .field(2).message(enum_value("UNKNOWN", 0)).message(enum_value("PAID", 1)).message(enum_value("REFUNDED", -1));
+        let file = Payload::new()
+            .field(1).string("shop.proto")
+            .field(2).string("shop")
+            .field(4).message(order)
+            .field(5).message(status);
+        let descriptor_set = Payload::new().field(1).message(file).build();
+
+        let (schema, enums) = parse_descriptor_set(&descriptor_set).unwrap();
+        let field = |message: &str, number: u32| {
+            let (field_type, name) = &schema[message][&number];
+            (&**field_type, &**name)
+        };
+        assert_eq!(field("shop.Order", 1), ("uint64", "id"));
+        assert_eq!(field("shop.Order", 2), ("shop.Order.Item repeated", "items"));
+        assert_eq!(field("shop.Order", 3), ("map<string, int32>", "counts"));
+        assert_eq!(field("shop.Order", 4), ("shop.Status", "status"));
+        assert_eq!(field("shop.Order", 5), ("shop.Order.Status", "shipping"));
+        assert_eq!(field("shop.Order.Item", 1), ("string", "sku"));
+        assert!(!schema.contains_key("shop.Order.CountsEntry"));
+        // 同名的enum按完整名称区分，顺序固定
+        let names: Vec<&str> = enums.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["shop.Order.Status", "shop.Status"]);
+        assert_eq!(enums[1].1[2], (-1, "REFUNDED".to_string()));
+
+        let mut parser = Parser::new();
+        parser.load_descriptor_set(&descriptor_set).unwrap();
+        let payload = Payload::new()
+            .field(1).varint(7)
+            .field(2).message(Payload::new().field(1).string("ab"))
+            .field(3).message(Payload::new().field(1).string("x").field(2).varint(3))
+            .field(4).varint(1)
+            .field(5).varint(2)
+            .build();
+        let result = strip_ansi(&parser.parse_message(&payload, "shop.Order").unwrap());
+        assert!(result.contains("sku = \"ab\""), "{}", result);
+        assert!(result.contains("counts = \"x\" => 3"), "{}", result);
+        assert!(result.contains("status = 1 (PAID)"), "{}", result);
+        assert!(result.contains("shipping = 2 (SHIPPED)"), "{}", result);
+        
+        // 没有声明的enum值仍然显示数值
+        let result = strip_ansi(&parser.parse_message(b"\x20\x05", "shop.Order").unwrap());
+        assert!(result.contains("status = 5 (unknown)"), "{}", result);
+
+        assert!(parse_descriptor_set(b"\x0a\x05\x12").is_err());
+    }
+}
@@ -1,17 +1,166 @@
+#[cfg(not(feature = "no-color"))]
+use std::cell::Cell;
+
+/// 输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    /// 构造字段树的Rust代码
+    RustLiteral,
+    /// 带有树形连接线的文本
+    Tree,
+    /// 可以折叠嵌套消息的独立HTML页面
+    Html,
+    /// 每个字段一个JSON对象，嵌套消息为嵌套的数组
+    Json,
+    /// protoscope的文本语法，可以用protoscope重新编码为原始数据
+    Protoscope,
+}
+
+/// 是否在输出中使用颜色，对应命令行的`--color=always|auto|never`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorMode {
+    Always,
+    /// 只有输出到终端并且没有设置`NO_COLOR`时使用颜色
+    #[default]
+    Auto,
+    Never,
+}
+
+impl ColorMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "always" => Some(ColorMode::Always),
+            "auto" => Some(ColorMode::Auto),
+            "never" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+    
+    pub fn should_color(self, is_terminal: bool, no_color_env: bool) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Auto => is_terminal && !no_color_env,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+#[cfg(not(feature = "no-color"))]
+thread_local! {
+    static COLOR_ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// 在运行时开关颜色，只对当前线程生效
+#[cfg(not(feature = "no-color"))]
+pub fn set_color_enabled(enabled: bool) {
+    COLOR_ENABLED.with(|c| c.set(enabled));
+}
+
+/// 启用`no-color` feature时始终没有颜色，运行时的开关不起作用
+#[cfg(feature = "no-color")]
+pub fn set_color_enabled(_enabled: bool) {}
+
+#[cfg(not(feature = "no-color"))]
+fn color_enabled() -> bool {
+    COLOR_ENABLED.with(|c| c.get())
+}
+
+#[cfg(not(feature = "no-color"))]
 pub fn foreground(color: u8, text: &str) -> String {
+    if !color_enabled() {
+        return text.to_string();
+    }
     format!("\x1b[3{}m{}\x1b[m", color, text)
 }
 
+/// 启用`no-color` feature时在编译期去掉所有转义序列
+#[cfg(feature = "no-color")]
+pub fn foreground(_color: u8, text: &str) -> String {
+    text.to_string()
+}
+
+#[cfg(not(feature = "no-color"))]
 pub fn bold(text: &str) -> String {
+    if !color_enabled() {
+        return text.to_string();
+    }
     format!("\x1b[1m{}\x1b[m", text)
 }
 
+#[cfg(feature = "no-color")]
+pub fn bold(text: &str) -> String {
+    text.to_string()
+}
+
+/// 暗色（SGR 2），用于不太重要的附加信息
+#[cfg(not(feature = "no-color"))]
+pub fn dim(text: &str) -> String {
+    if !color_enabled() {
+        return text.to_string();
+    }
+    format!("\x1b[2m{}\x1b[m", text)
+}
 
+#[cfg(feature = "no-color")]
+pub fn dim(text: &str) -> String {
+    text.to_string()
+}
 
 pub fn foreground_bold(color: u8, text: &str) -> String {
     bold(&foreground(color, text))
 }
 
+/// 去掉ANSI颜色转义序列
+pub fn strip_ansi(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// 转义HTML中的特殊字符
+pub fn escape_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// 转义JSON字符串中的特殊字符，返回带引号的字符串
+pub fn escape_json(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
 pub fn indent(text: &str, indent_str: Option<&str>) -> String {
     let indent = indent_str.unwrap_or("    ");
     text.lines()
@@ -62,3 +211,75 @@ pub fn hex_dump(data: &[u8]) -> String {
     
     lines.join("\n")
 }
+
+/// 把unix时间戳（秒）格式化为UTC时间，例如`2023-11-14T22:13:20Z`
+pub fn format_epoch_seconds(secs: i64) -> String {
+    let days = secs.div_euclid(86400);
+    let time = secs.rem_euclid(86400);
+    
+    // 公历日期换算，参考 http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_epoch_seconds() {
+        assert_eq!(format_epoch_seconds(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_epoch_seconds(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_epoch_seconds(-1), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-color"))]
+    fn test_colored_output() {
+        assert_eq!(foreground(2, "abc"), "\x1b[32mabc\x1b[m");
+        assert_eq!(foreground_bold(4, "1"), "\x1b[1m\x1b[34m1\x1b[m\x1b[m");
+        assert_eq!(dim("abc"), "\x1b[2mabc\x1b[m");
+    }
+
+    #[test]
+    #[cfg(feature = "no-color")]
+    fn test_no_color_output() {
+        assert_eq!(foreground(2, "abc"), "abc");
+        assert_eq!(dim("abc"), "abc");
+        assert_eq!(foreground_bold(4, "1"), "1");
+    }
+
+    #[test]
+    fn test_color_mode() {
+        // 输出到终端时never同样不输出颜色
+        assert!(!ColorMode::Never.should_color(true, false));
+        assert!(ColorMode::Always.should_color(false, true));
+        assert!(ColorMode::Auto.should_color(true, false));
+        assert!(!ColorMode::Auto.should_color(true, true));
+        assert!(!ColorMode::Auto.should_color(false, false));
+        assert_eq!(ColorMode::from_name("never"), Some(ColorMode::Never));
+        assert_eq!(ColorMode::from_name("sometimes"), None);
+        
+        // 设置了NO_COLOR时，所有辅助函数都原样返回文本
+        set_color_enabled(ColorMode::Auto.should_color(true, true));
+        for output in [foreground(2, "abc"), bold("abc"), dim("abc"), foreground_bold(4, "abc")] {
+            assert_eq!(output, "abc");
+        }
+        
+        set_color_enabled(ColorMode::Never.should_color(true, false));
+        let output = crate::parser::Parser::new().parse_message(crate::PROTOBUF_EXAMPLE, "root").unwrap();
+        set_color_enabled(true);
+        assert!(!output.contains('\x1b'), "{}", output);
+        assert!(output.contains("1 <varint> = "), "{}", output);
+    }
+}
@@ -0,0 +1,46 @@
+/// `google.rpc.Code`中定义的gRPC状态码
+pub const STATUS_CODES: &[(i64, &str)] = &[
+    (0, "OK"),
+    (1, "CANCELLED"),
+    (2, "UNKNOWN"),
+    (3, "INVALID_ARGUMENT"),
+    (4, "DEADLINE_EXCEEDED"),
+    (5, "NOT_FOUND"),
+    (6, "ALREADY_EXISTS"),
+    (7, "PERMISSION_DENIED"),
+    (8, "RESOURCE_EXHAUSTED"),
+    (9, "FAILED_PRECONDITION"),
+    (10, "ABORTED"),
+    (11, "OUT_OF_RANGE"),
+    (12, "UNIMPLEMENTED"),
+    (13, "INTERNAL"),
+    (14, "UNAVAILABLE"),
+    (15, "DATA_LOSS"),
+    (16, "UNAUTHENTICATED"),
+];
+
+/// (field number, 类型, 字段名)
+pub type FieldDefinition = (u32, &'static str, &'static str);
+
+/// `google.rpc.Status`本身和`google/rpc/error_details.proto`中常见的detail类型：
+/// (消息名, 字段列表)
+///
+/// 嵌套的消息（例如`RetryInfo.retry_delay`）使用`message`类型，按猜测展开
+pub const STATUS_TYPES: &[(&str, &[FieldDefinition])] = &[
+    ("google.rpc.Status", &[(1, "google.rpc.Code", "code"), (2, "string", "message"), (3, "any", "details")]),
+    ("google.rpc.ErrorInfo", &[(1, "string", "reason"), (2, "string", "domain"), (3, "message", "metadata")]),
+    ("google.rpc.RetryInfo", &[(1, "message", "retry_delay")]),
+    ("google.rpc.DebugInfo", &[(1, "string repeated", "stack_entries"), (2, "string", "detail")]),
+    ("google.rpc.QuotaFailure", &[(1, "message", "violations")]),
+    ("google.rpc.PreconditionFailure", &[(1, "message", "violations")]),
+    ("google.rpc.BadRequest", &[(1, "message", "field_violations")]),
+    ("google.rpc.RequestInfo", &[(1, "string", "request_id"), (2, "string", "serving_data")]),
+    ("google.rpc.ResourceInfo", &[
+        (1, "string", "resource_type"),
+        (2, "string", "resource_name"),
+        (3, "string", "owner"),
+        (4, "string", "description"),
+    ]),
+    ("google.rpc.Help", &[(1, "message", "links")]),
+    ("google.rpc.LocalizedMessage", &[(1, "string", "locale"), (2, "string", "message")]),
+];
@@ -1,5 +1,5 @@
 use std::io::Cursor;
-use crate::core::{read_identifier, read_value, parse_varint_bytes};
+use crate::core::{is_valid_field_number, read_identifier, read_varint, parse_varint_bytes};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum GuesserError {
@@ -7,91 +7,172 @@ pub enum GuesserError {
     InvalidData,
 }
 
+impl std::fmt::Display for GuesserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GuesserError::Eof => write!(f, "unexpected end of input"),
+            GuesserError::InvalidData => write!(f, "not a valid protobuf message"),
+        }
+    }
+}
+
+impl std::error::Error for GuesserError {}
+
+/// 较大的数据块只根据前几个字段做判断
+pub const GUESS_FIELD_COUNT: usize = 3;
+
+/// 不超过这个长度的数据块检查所有字段（仍受`MAX_GUESS_ITERATIONS`限制）
+pub const FULL_SCAN_BYTES: usize = 256;
+
+/// 与`GUESS_FIELD_COUNT`无关的硬性上限，即使以后放宽字段数量，
+/// 构造的输入也不能让猜测读取过多的字段；chunk的内容只检查长度，不会被复制
+const MAX_GUESS_ITERATIONS: usize = 64;
+
+/// 逐个字段累积的猜测状态，解析器可以在解析的同时进行判断而不必重复读取数据
+#[derive(Debug, Clone)]
+pub struct MessageGuess {
+    /// 最多观察的字段数，之后的字段不再影响结果
+    field_limit: usize,
+    fields_seen: usize,
+    is_ctrl_char_found: bool,
+    weird_value_count: usize,
+}
+
+impl Default for MessageGuess {
+    fn default() -> Self {
+        Self::new(GUESS_FIELD_COUNT)
+    }
+}
+
+impl MessageGuess {
+    pub fn new(field_limit: usize) -> Self {
+        Self { field_limit, fields_seen: 0, is_ctrl_char_found: false, weird_value_count: 0 }
+    }
+    
+    /// 按数据长度选择观察的字段数：较短的数据检查全部字段，较长的只检查前`GUESS_FIELD_COUNT`个
+    pub fn for_data(data: &[u8]) -> Self {
+        Self::new(if data.len() <= FULL_SCAN_BYTES { MAX_GUESS_ITERATIONS } else { GUESS_FIELD_COUNT })
+    }
+    
+    /// 记录一个字段，`field_data`从字段的tag开始，`value`为read_value返回的数据
+    pub fn observe(&mut self, field_data: &[u8], wire_type: u8, value: &[u8]) {
+        if self.is_complete() {
+            return;
+        }
+        self.fields_seen += 1;
+
+        // 纯文本中几乎不会出现控制字符，而tag和长度经常落在这个区间
+        self.is_ctrl_char_found |= field_data.iter().take(4).any(|&c| c < 32 && c != b'\n');
+
+        match wire_type {
+            // 检查64位数据的最后字节是否为0或255
+            1 if !matches!(value.last(), Some(0 | 255)) => self.weird_value_count += 1,
+            // 放宽chunk长度检查，允许更大的chunk
+            2 if value.len() > 500 || value.is_empty() => self.weird_value_count += 1,
+            _ => {}
+        }
+    }
+
+    /// 已经观察到足够的字段，之后的字段不再影响结果
+    pub fn is_complete(&self) -> bool {
+        self.fields_seen >= self.field_limit
+    }
+
+    /// 不需要继续读取就能确定不是消息：即使观察满`field_limit`个字段，异常值也已经太多
+    pub fn is_rejected(&self) -> bool {
+        self.weird_value_count > allowed_weird_values(self.field_limit)
+            || (self.is_complete() && !self.is_ctrl_char_found)
+    }
+
+    pub fn is_message(&self) -> bool {
+        // 放宽判断条件：如果至少找到一个有效字段且异常值不多，就认为是消息
+        self.is_ctrl_char_found && self.fields_seen > 0
+            && self.weird_value_count <= allowed_weird_values(self.fields_seen)
+    }
+}
+
+/// 允许的异常值数量：不超过3个字段时最多1个，更多字段时最多三分之一
+fn allowed_weird_values(fields: usize) -> usize {
+    (fields / 3).max(1)
+}
+
+/// 用于`--trace`输出的猜测依据
+impl std::fmt::Display for MessageGuess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} fields, {} weird values, control chars {}",
+            self.fields_seen, self.weird_value_count, if self.is_ctrl_char_found { "found" } else { "not found" })
+    }
+}
+
 /// 猜测数据块是否为protobuf消息
 pub fn guess_is_message(data: &[u8]) -> Result<bool, GuesserError> {
+    guess_with_budget(data).map(|(is_message, _)| is_message)
+}
+
+/// 返回猜测结果和读取的字段数，最多读取`MAX_GUESS_ITERATIONS`个字段，
+/// 用完预算时按已经读取的字段给出结论
+fn guess_with_budget(data: &[u8]) -> Result<(bool, usize), GuesserError> {
     let mut cursor = Cursor::new(data);
-    let mut weird_value_count = 0;
-    let mut valid_fields_found = 0;
+    let mut guess = MessageGuess::for_data(data);
+    let mut iterations = 0;
 
-    for _ in 0..3 {
+    while !guess.is_complete() && !guess.is_rejected() && iterations < MAX_GUESS_ITERATIONS {
+        iterations += 1;
+        let start = cursor.position() as usize;
 
         // 读取标识符
         let (field_number, wire_type) = match read_identifier(&mut cursor) {
             Ok(Some((key, wt))) => (key, wt),
             Ok(None) => break,
-            Err(_) => return Err(GuesserError::InvalidData),
+            Err(_) => return Ok((false, iterations)),
         };
 
         // 检查field number范围
-        if field_number == 0 || (19000..=19999).contains(&field_number) {
+        if !is_valid_field_number(field_number) {
             return Err(GuesserError::InvalidData);
         }
 
-        valid_fields_found += 1;
-
-        // 根据wire type处理数据
-        match wire_type {
-            3 | 4 => { // StartGroup/EndGroup
-                // 不增加异常计数
-            }
-            5 => { // 32bit
-                match read_value(&mut cursor, wire_type) {
-                    Ok(Some(_)) => {},
-                    _ => return Err(GuesserError::Eof),
-                }
-            }
-            1 => { // 64bit
-                match read_value(&mut cursor, wire_type) {
-                    Ok(Some(value_data)) => {
-                        // 检查64位数据的最后字节是否为0或255
-                        if !matches!(value_data.last(), Some(0 | 255)) {
-                            weird_value_count += 1;
-                        }
-                    }
-                    _ => return Err(GuesserError::Eof),
-                }
-            }
-            2 => { // Chunk
-                // 读取chunk长度
-                let length = match read_value(&mut cursor, wire_type) {
-                    Ok(Some(value_data)) => {
-                        match parse_varint_bytes(&value_data) {
-                            Ok(len) => len as usize,
-                            Err(_) => return Err(GuesserError::InvalidData),
-                        }
-                    }
-                    _ => return Err(GuesserError::Eof),
-                };
-                
-                // 放宽chunk长度检查，允许更大的chunk
-                if length > 500 || length == 0 {
-                    weird_value_count += 1;
-                }
-
-                // 跳过chunk数据
-                if cursor.position() as usize + length > data.len() {
-                    return Err(GuesserError::Eof);
-                }
-                cursor.set_position(cursor.position() + length as u64);
-            }
-            0 => { // Varint
-                match read_value(&mut cursor, wire_type) {
-                    Ok(Some(value_data)) => {
-                        let _ = parse_varint_bytes(&value_data)?;
-                    }
-                    _ => return Err(GuesserError::Eof),
-                }
-            }
+        // 根据wire type取得数据，chunk的长度与完整的数据比较
+        let value = match wire_type {
+            3 | 4 => &data[..0], // StartGroup/EndGroup
+            0 | 1 | 2 | 5 => value_slice(&mut cursor, wire_type).map_err(|_| GuesserError::Eof)?,
             _ => return Err(GuesserError::InvalidData),
+        };
+        if wire_type == 0 {
+            parse_varint_bytes(value)?;
         }
 
+        guess.observe(&data[start..], wire_type, value);
+
         if cursor.position() as usize >= data.len() {
             break;
         }
     }
 
-    // 放宽判断条件：如果至少找到一个有效字段且异常值不多，就认为是消息
-    Ok(valid_fields_found > 0 && weird_value_count <= 1)
+    Ok((guess.is_message(), iterations))
+}
+
+/// 读取一个字段值并返回它在数据中的切片，chunk的长度超过剩余数据时为`Eof`
+fn value_slice<'a>(cursor: &mut Cursor<&'a [u8]>, wire_type: u8) -> Result<&'a [u8], crate::core::Error> {
+    let data: &'a [u8] = cursor.get_ref();
+    let length = match wire_type {
+        0 => {
+            let start = cursor.position() as usize;
+            read_varint(cursor)?.ok_or(crate::core::Error::Eof)?;
+            return Ok(&data[start..cursor.position() as usize]);
+        }
+        1 => 8,
+        5 => 4,
+        _ => read_varint(cursor)?.ok_or(crate::core::Error::Eof)?,
+    };
+    let start = cursor.position() as usize;
+    let remaining = (data.len() - start) as u64;
+    if length > remaining {
+        return Err(crate::core::Error::Eof);
+    }
+    let end = start + length as usize;
+    cursor.set_position(end as u64);
+    Ok(&data[start..end])
 }
 
 impl From<crate::core::Error> for GuesserError {
@@ -103,11 +184,12 @@ impl From<crate::core::Error> for GuesserError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil::Payload;
 
     #[test]
     fn test_guess_is_message() {
         // 有效的protobuf消息
-        assert_eq!(guess_is_message(b"\x0a\x08POKECOIN"), Ok(true));
+        assert_eq!(guess_is_message(&Payload::new().field(1).string("POKECOIN").build()), Ok(true));
         
         // 纯字符串
         assert_eq!(guess_is_message(b"POKECOIN"), Ok(false));
@@ -118,4 +200,61 @@ mod tests {
         // 无效的varint
         assert_eq!(guess_is_message(b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff"), Ok(false));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_guess_later_fields() {
+        // 前三个字段中有两个最后一字节不是0或255的64位值，后三个字段都是正常的varint
+        let weird = 0x0807060504030201;
+        let weird_prefix = &Payload::new()
+            .field(1).fixed64(weird).field(2).fixed64(weird)
+            .field(3).varint(1).field(4).varint(2).field(5).varint(3).field(6).varint(4)
+            .build();
+        assert_eq!(guess_is_message(weird_prefix), Ok(true));
+        
+        // 反过来，前三个字段正常，后三个字段都是异常的64位值
+        let weird_suffix = &Payload::new()
+            .field(1).varint(1).field(2).varint(2).field(3).varint(3)
+            .field(4).fixed64(weird).field(5).fixed64(weird).field(6).fixed64(weird)
+            .build();
+        assert_eq!(guess_is_message(weird_suffix), Ok(false));
+        
+        // 只看前三个字段时结论相反
+        let prefix_only = |data: &[u8]| {
+            let mut guess = MessageGuess::new(GUESS_FIELD_COUNT);
+            let mut offset = 0;
+            while !guess.is_complete() {
+                let (field, next) = crate::tree::decode_field(data, offset).unwrap();
+                guess.observe(&data[offset..], field.wire_type as u8, &field.raw);
+                offset = next;
+            }
+            guess.is_message()
+        };
+        assert!(!prefix_only(weird_prefix));
+        assert!(prefix_only(weird_suffix));
+    }
+
+    #[test]
+    fn test_guess_budget() {
+        // 大量很小的字段，以及长度远超实际数据的chunk
+        let small_fields = b"\x08\x01".repeat(1 << 20);
+        let (is_message, iterations) = guess_with_budget(&small_fields).unwrap();
+        assert!(is_message);
+        assert!(iterations <= MAX_GUESS_ITERATIONS);
+
+        // 声明的长度远超实际数据的chunk
+        let mut huge_chunk = b"\x0a\xff\xff\xff\xff\xff\xff\xff\xff\x7f".to_vec();
+        huge_chunk.extend(vec![b'a'; 1 << 17]);
+        assert_eq!(guess_is_message(&huge_chunk), Err(GuesserError::Eof));
+
+        // 很长的chunk只检查长度，结论与较短的chunk相同
+        for length in [100, 70000] {
+            let mut data = b"\x0a".to_vec();
+            data.extend(crate::core::encode_varint(length as u64));
+            data.extend(vec![b'a'; length]);
+            data.extend(b"\x10\x01");
+            assert_eq!(guess_is_message(&data), Ok(true), "{}", length);
+        }
+
+        assert_eq!(guess_is_message(b"\x0a\xff\xff\xff\xff\x0f"), Err(GuesserError::Eof));
+    }
+}
@@ -0,0 +1,367 @@
+use std::fmt;
+
+/// 输入数据预处理时的错误，偏移量均以字节为单位
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputError {
+    InvalidJson(usize),
+    PathNotFound(String),
+    NotAString(String),
+    InvalidBase64(usize),
+    InvalidHex(usize),
+    /// 十六进制数字的个数为奇数
+    OddHexLength(usize),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::InvalidJson(offset) => write!(f, "invalid JSON at byte {}", offset),
+            InputError::PathNotFound(path) => write!(f, "JSON path {:?} not found", path),
+            InputError::NotAString(path) => write!(f, "value at JSON path {:?} is not a string", path),
+            InputError::InvalidBase64(offset) => write!(f, "invalid base64 at byte {}", offset),
+            InputError::InvalidHex(offset) => write!(f, "invalid hex digit at byte {}", offset),
+            InputError::OddHexLength(digits) => write!(f, "odd number of hex digits ({})", digits),
+        }
+    }
+}
+
+/// 解码base64，同时接受标准和URL安全的字母表，末尾的`=`可以省略
+///
+/// 字母表由第一个出现的`+/`或`-_`决定，两种字母表混用时视为错误。
+/// 忽略空白字符，因此可以直接使用`base64`命令每76个字符换行的输出
+pub fn decode_base64(text: &str) -> Result<Vec<u8>, InputError> {
+    let text = text.trim_end_matches(|c: char| c == '=' || c.is_ascii_whitespace());
+    let mut result = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut digits = 0;
+    let mut last_offset = 0;
+    let url_safe = text.bytes().find(|c| b"+/-_".contains(c)).map(|c| c == b'-' || c == b'_');
+
+    for (offset, c) in text.bytes().enumerate() {
+        if c.is_ascii_whitespace() {
+            continue;
+        }
+        let value = match (c, url_safe) {
+            (b'A'..=b'Z', _) => c - b'A',
+            (b'a'..=b'z', _) => c - b'a' + 26,
+            (b'0'..=b'9', _) => c - b'0' + 52,
+            (b'+', Some(false)) | (b'-', Some(true)) => 62,
+            (b'/', Some(false)) | (b'_', Some(true)) => 63,
+            _ => return Err(InputError::InvalidBase64(offset)),
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        digits += 1;
+        last_offset = offset;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
+    }
+
+    // 剩下一个字符时无法组成完整的字节
+    if digits % 4 == 1 {
+        return Err(InputError::InvalidBase64(last_offset));
+    }
+    Ok(result)
+}
+
+/// 解码十六进制文本，例如从Wireshark复制的`0a 08 50 4f`，忽略空白字符，
+/// 每组数字前可以带有`0x`
+pub fn decode_hex(text: &str) -> Result<Vec<u8>, InputError> {
+    let mut digits = Vec::with_capacity(text.len());
+    let bytes = text.as_bytes();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let c = bytes[offset];
+        let at_group_start = offset == 0 || bytes[offset - 1].is_ascii_whitespace();
+        if c.is_ascii_whitespace() {
+            offset += 1;
+        } else if at_group_start && c == b'0' && matches!(bytes.get(offset + 1), Some(b'x' | b'X')) {
+            offset += 2;
+        } else {
+            let value = (c as char).to_digit(16).ok_or(InputError::InvalidHex(offset))?;
+            digits.push(value as u8);
+            offset += 1;
+        }
+    }
+    
+    if digits.len() % 2 == 1 {
+        return Err(InputError::OddHexLength(digits.len()));
+    }
+    Ok(digits.chunks(2).map(|pair| (pair[0] << 4) | pair[1]).collect())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// 读取JSON文档中以`.`分隔的路径上的字符串，数组使用下标，例如`data.items.0.payload`
+pub fn extract_json_string(json: &str, path: &str) -> Result<String, InputError> {
+    let mut reader = JsonReader { data: json.as_bytes(), pos: 0 };
+    let root = reader.read_document()?;
+
+    let mut value = &root;
+    for key in path.split('.').filter(|key| !key.is_empty()) {
+        let next = match value {
+            JsonValue::Object(entries) => entries.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            JsonValue::Array(items) => key.parse::<usize>().ok()
+                .and_then(|index| items.get(index)),
+            _ => None,
+        };
+        value = next.ok_or_else(|| InputError::PathNotFound(path.to_string()))?;
+    }
+
+    match value {
+        JsonValue::String(s) => Ok(s.clone()),
+        _ => Err(InputError::NotAString(path.to_string())),
+    }
+}
+
+struct JsonReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl JsonReader<'_> {
+    fn read_document(&mut self) -> Result<JsonValue, InputError> {
+        let value = self.read_value()?;
+        self.skip_whitespace();
+        if self.pos != self.data.len() {
+            return Err(self.error());
+        }
+        Ok(value)
+    }
+
+    fn error(&self) -> InputError {
+        InputError::InvalidJson(self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.data.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), InputError> {
+        self.skip_whitespace();
+        if self.data.get(self.pos) != Some(&c) {
+            return Err(self.error());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn read_keyword(&mut self, keyword: &str, value: JsonValue) -> Result<JsonValue, InputError> {
+        if !self.data[self.pos..].starts_with(keyword.as_bytes()) {
+            return Err(self.error());
+        }
+        self.pos += keyword.len();
+        Ok(value)
+    }
+
+    fn read_value(&mut self) -> Result<JsonValue, InputError> {
+        self.skip_whitespace();
+        match self.data.get(self.pos) {
+            Some(b'{') => self.read_object(),
+            Some(b'[') => self.read_array(),
+            Some(b'"') => Ok(JsonValue::String(self.read_string()?)),
+            Some(b't') => self.read_keyword("true", JsonValue::Bool(true)),
+            Some(b'f') => self.read_keyword("false", JsonValue::Bool(false)),
+            Some(b'n') => self.read_keyword("null", JsonValue::Null),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while matches!(self.data.get(self.pos), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+                    self.pos += 1;
+                }
+                let number = std::str::from_utf8(&self.data[start..self.pos]).map_err(|_| self.error())?;
+                Ok(JsonValue::Number(number.to_string()))
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn read_object(&mut self) -> Result<JsonValue, InputError> {
+        self.expect(b'{')?;
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.data.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(entries));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.read_string()?;
+            self.expect(b':')?;
+            entries.push((key, self.read_value()?));
+            self.skip_whitespace();
+            match self.data.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(entries));
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    fn read_array(&mut self) -> Result<JsonValue, InputError> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.data.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(items));
+        }
+
+        loop {
+            items.push(self.read_value()?);
+            self.skip_whitespace();
+            match self.data.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, InputError> {
+        if self.data.get(self.pos) != Some(&b'"') {
+            return Err(self.error());
+        }
+        self.pos += 1;
+
+        let mut result = String::new();
+        loop {
+            let start = self.pos;
+            while !matches!(self.data.get(self.pos), Some(b'"' | b'\\') | None) {
+                self.pos += 1;
+            }
+            result.push_str(std::str::from_utf8(&self.data[start..self.pos]).map_err(|_| self.error())?);
+
+            match self.data.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.data.get(self.pos) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.read_unicode_escape()?,
+                        _ => return Err(self.error()),
+                    };
+                    self.pos += 1;
+                    result.push(c);
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    /// 读取`\uXXXX`，处理UTF-16代理对；结束时pos指向最后一个十六进制字符
+    fn read_unicode_escape(&mut self) -> Result<char, InputError> {
+        let high = self.read_hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error());
+        }
+        if !self.data[self.pos + 1..].starts_with(b"\\u") {
+            return Err(self.error());
+        }
+        self.pos += 2;
+        let low = self.read_hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error());
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).ok_or_else(|| self.error())
+    }
+
+    fn read_hex4(&mut self) -> Result<u32, InputError> {
+        let digits = self.data.get(self.pos + 1..self.pos + 5).ok_or_else(|| self.error())?;
+        let digits = std::str::from_utf8(digits).map_err(|_| self.error())?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.error())?;
+        self.pos += 4;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("CJYB").unwrap(), b"\x08\x96\x01");
+        assert_eq!(decode_base64("CgNhYmM=").unwrap(), b"\x0a\x03abc");
+        assert_eq!(decode_base64("CgNhYmM").unwrap(), b"\x0a\x03abc");
+        assert_eq!(decode_base64("-_8").unwrap(), b"\xfb\xff");
+        assert_eq!(decode_base64("CJ*B"), Err(InputError::InvalidBase64(2)));
+        
+        // `base64`命令的输出每76个字符换行，末尾也有换行
+        let data: Vec<u8> = (0..120).collect();
+        let encoded = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4\n\
+            OTo7PD0+P0BBQkNERUZHSElKS0xNTk9QUVJTVFVWV1hZWltcXV5fYGFiY2RlZmdoaWprbG1ub3Bx\r\n\
+            cnN0dXZ3\n";
+        assert_eq!(decode_base64(encoded), Ok(data));
+        assert_eq!(decode_base64("CgNh YmM=\n"), Ok(b"\x0a\x03abc".to_vec()));
+        assert_eq!(decode_base64("CgNhY\n"), Err(InputError::InvalidBase64(4)));
+    }
+
+    #[test]
+    fn test_base64_alphabet() {
+        assert_eq!(decode_base64("+/8="), Ok(vec![0xfb, 0xff]));
+        assert_eq!(decode_base64("-_8"), Ok(vec![0xfb, 0xff]));
+        // 标准字母表中出现了URL安全的字符
+        assert_eq!(decode_base64("+/_A"), Err(InputError::InvalidBase64(2)));
+        assert_eq!(decode_base64("AB=C"), Err(InputError::InvalidBase64(2)));
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("0a 08 50 4f\n4B"), Ok(b"\x0a\x08POK".to_vec()));
+        assert_eq!(decode_hex("0x0a08504F"), Ok(b"\x0a\x08PO".to_vec()));
+        assert_eq!(decode_hex("0x0a 0x08"), Ok(b"\x0a\x08".to_vec()));
+        assert_eq!(decode_hex(""), Ok(Vec::new()));
+        assert_eq!(decode_hex("0a 0g"), Err(InputError::InvalidHex(4)));
+        assert_eq!(decode_hex("0a0"), Err(InputError::OddHexLength(3)));
+        // 0x只能出现在一组数字的开头
+        assert_eq!(decode_hex("0a0x08"), Err(InputError::InvalidHex(3)));
+    }
+
+    #[test]
+    fn test_json_payload() {
+        let json = r#"{"level": "info", "ts": 1.5e3, "data": {"items": [null, {"payload": "CJYBEgNhYmM="}]}}"#;
+        let payload = extract_json_string(json, "data.items.1.payload").unwrap();
+        let data = decode_base64(&payload).unwrap();
+        let result = Parser::new().parse_message(&data, "root").unwrap();
+        assert!(result.contains("150"), "{}", result);
+        assert!(result.contains("\"abc\""), "{}", result);
+
+        assert_eq!(extract_json_string(json, "data.missing"), Err(InputError::PathNotFound("data.missing".to_string())));
+        assert_eq!(extract_json_string(json, "ts"), Err(InputError::NotAString("ts".to_string())));
+        assert_eq!(extract_json_string(r#"{"a": "é\u00e9\ud83d\ude00"}"#, "a").unwrap(), "éé😀");
+        assert_eq!(extract_json_string(r#"{"a": }"#, "a"), Err(InputError::InvalidJson(6)));
+    }
+}
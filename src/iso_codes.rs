@@ -0,0 +1,73 @@
+/// ISO 3166-1国家代码：(数字代码, 两位字母代码, 三位字母代码, 名称)，只收录常见的国家和地区
+pub const COUNTRIES: &[(u16, &str, &str, &str)] = &[
+    (36, "AU", "AUS", "Australia"),
+    (76, "BR", "BRA", "Brazil"),
+    (124, "CA", "CAN", "Canada"),
+    (156, "CN", "CHN", "China"),
+    (250, "FR", "FRA", "France"),
+    (276, "DE", "DEU", "Germany"),
+    (344, "HK", "HKG", "Hong Kong"),
+    (356, "IN", "IND", "India"),
+    (360, "ID", "IDN", "Indonesia"),
+    (372, "IE", "IRL", "Ireland"),
+    (380, "IT", "ITA", "Italy"),
+    (392, "JP", "JPN", "Japan"),
+    (410, "KR", "KOR", "South Korea"),
+    (446, "MO", "MAC", "Macao"),
+    (458, "MY", "MYS", "Malaysia"),
+    (484, "MX", "MEX", "Mexico"),
+    (528, "NL", "NLD", "Netherlands"),
+    (554, "NZ", "NZL", "New Zealand"),
+    (578, "NO", "NOR", "Norway"),
+    (608, "PH", "PHL", "Philippines"),
+    (643, "RU", "RUS", "Russia"),
+    (682, "SA", "SAU", "Saudi Arabia"),
+    (702, "SG", "SGP", "Singapore"),
+    (710, "ZA", "ZAF", "South Africa"),
+    (724, "ES", "ESP", "Spain"),
+    (752, "SE", "SWE", "Sweden"),
+    (756, "CH", "CHE", "Switzerland"),
+    (158, "TW", "TWN", "Taiwan"),
+    (764, "TH", "THA", "Thailand"),
+    (792, "TR", "TUR", "Türkiye"),
+    (784, "AE", "ARE", "United Arab Emirates"),
+    (826, "GB", "GBR", "United Kingdom"),
+    (840, "US", "USA", "United States"),
+    (704, "VN", "VNM", "Viet Nam"),
+];
+
+/// ISO 4217货币代码：(数字代码, 字母代码, 名称)
+pub const CURRENCIES: &[(u16, &str, &str)] = &[
+    (36, "AUD", "Australian Dollar"),
+    (124, "CAD", "Canadian Dollar"),
+    (156, "CNY", "Yuan Renminbi"),
+    (344, "HKD", "Hong Kong Dollar"),
+    (356, "INR", "Indian Rupee"),
+    (392, "JPY", "Yen"),
+    (410, "KRW", "Won"),
+    (643, "RUB", "Russian Ruble"),
+    (702, "SGD", "Singapore Dollar"),
+    (756, "CHF", "Swiss Franc"),
+    (826, "GBP", "Pound Sterling"),
+    (840, "USD", "US Dollar"),
+    (901, "TWD", "New Taiwan Dollar"),
+    (978, "EUR", "Euro"),
+    (986, "BRL", "Brazilian Real"),
+];
+
+pub fn country_by_number(number: u64) -> Option<&'static str> {
+    COUNTRIES.iter().find(|c| c.0 as u64 == number).map(|c| c.3)
+}
+
+/// 同时接受两位和三位的字母代码
+pub fn country_by_alpha(code: &str) -> Option<&'static str> {
+    COUNTRIES.iter().find(|c| c.1 == code || c.2 == code).map(|c| c.3)
+}
+
+pub fn currency_by_number(number: u64) -> Option<&'static str> {
+    CURRENCIES.iter().find(|c| c.0 as u64 == number).map(|c| c.2)
+}
+
+pub fn currency_by_alpha(code: &str) -> Option<&'static str> {
+    CURRENCIES.iter().find(|c| c.1 == code).map(|c| c.2)
+}
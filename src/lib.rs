@@ -0,0 +1,19 @@
+pub mod charset;
+pub mod core;
+pub mod descriptor;
+pub mod formatter;
+pub mod grpc;
+pub mod guesser;
+pub mod input;
+pub mod iso_codes;
+pub mod parser;
+pub mod schema;
+pub mod stream;
+#[cfg(test)]
+pub(crate) mod testutil;
+pub mod tree;
+pub mod types;
+pub mod well_known;
+
+/// 内置的示例数据，用于自检和测试
+pub const PROTOBUF_EXAMPLE: &[u8] = b"\x08\x8f\x81\xeb\xcf\xe0*\x12\x08kotlin46:\x05\x00\x01\x03\x04\x07B\x00H\xfa\x01U\x00\x00HCr\n\n\x08POKECOINr\x0c\n\x08STARDUST\x10d";
@@ -1,29 +1,685 @@
-mod core;
-mod formatter;
-mod guesser;
-mod parser;
-mod types;
+use protobuf_inspector_rs::core;
+use protobuf_inspector_rs::formatter::{set_color_enabled, ColorMode, OutputFormat};
+use protobuf_inspector_rs::input::{decode_base64, decode_hex, extract_json_string};
+use protobuf_inspector_rs::parser::{parse_expand_path, Parser};
+use protobuf_inspector_rs::schema::default_schema_path;
+use protobuf_inspector_rs::types::{ChunkKind, FixedInterpretation, NonCanonicalVarints, WireTypeStats};
+use protobuf_inspector_rs::PROTOBUF_EXAMPLE;
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
-use parser::Parser;
-use std::io::Read;
+const USAGE: &str = "Usage: protobuf-inspector-rs [--no-nested] [--strict] [--lenient] [--allow-field-zero] [--zero-padding] [--fixed=hex,unsigned,signed,float] [--chunk-order=message,string,bytes] [--max-digits=N] [--max-depth=N] [--display-depth=N] [--expand=PATH] [--varints=error|warn|accept] [--format=text|rust|tree|html|json|protoscope] [--hex|--base64] [--ascii] [--wire-details] [--offsets] [--zigzag-raw] [--check-nul] [--float-arrays] [--trace] [--max-nesting-report] [--summary|--no-summary] [--delimited|--grpc] [--stream[=hex]] [--color=always|auto|never] [--selftest] [--watch PATH] [--json-path PATH] [--enum-proto PATH] [--proto PATH] [--descriptors PATH] [--message-type|--root-type NAME] [--type NAME]... [FILE...]";
 
-fn parse_main(data: &[u8]) -> Result<String, core::Error> {
-    let mut parser = Parser::new();
-    parser.parse_message(data, "root")
+/// 输入中消息的分隔方式，除了单条消息以外只支持文本格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Framing {
+    /// 整个输入是一条消息
+    Single,
+    /// `--delimited`：多条以varint长度为前缀的消息
+    Delimited,
+    /// `--grpc`：gRPC的消息帧，5字节的帧头之后是一条消息
+    Grpc,
 }
 
-fn main() {
-    let mut buffer = Vec::new();
-    std::io::stdin().read_to_end(&mut buffer)
-        .expect("Failed to read from stdin");
+/// `type_names`是`--delimited`和`--grpc`中每段消息的类型，单条消息时只使用第一个
+fn parse_main(parser: &mut Parser, data: &[u8], type_names: &[&str], format: OutputFormat, framing: Framing) -> Result<String, core::Error> {
+    let type_name = type_names[0];
+    if data.is_empty() {
+        // JSON和protoscope的输出需要保持可以被解析
+        return Ok(match format {
+            OutputFormat::Json => "[]",
+            OutputFormat::Protoscope => "",
+            _ => "(empty input)",
+        }.to_string());
+    }
+    
+    match format {
+        OutputFormat::Text if framing == Framing::Delimited => parser.parse_delimited_messages(data, type_names),
+        OutputFormat::Text if framing == Framing::Grpc => parser.parse_grpc_frames(data, type_names),
+        OutputFormat::Text => parser.parse_message(data, type_name),
+        // 与文本输出一样使用schema、解析选项和max_depth
+        _ => {
+            let message = parser.parse_to_tree(data, type_name)?;
+            parser.render_fields(&message.fields, type_name, format)
+        }
+    }
+}
+
+/// 每段消息的类型：没有用`--type`指定时都是顶层消息的类型
+fn type_names<'a>(segment_types: &'a [String], root_type: &'a str) -> Vec<&'a str> {
+    if segment_types.is_empty() {
+        return vec![root_type];
+    }
+    segment_types.iter().map(String::as_str).collect()
+}
+
+/// `--max-nesting-report`的输出，需要先用`parse_message`解析
+fn nesting_report(stats: &WireTypeStats) -> String {
+    let path: Vec<String> = stats.max_depth_path.iter().map(|n| n.to_string()).collect();
+    format!("max nesting depth: {} (path {})", stats.max_depth, path.join("."))
+}
+
+/// `--summary`在输出开头加上的一行概要，同样需要先用`parse_message`解析
+fn summary(type_name: &str, stats: &WireTypeStats) -> String {
+    format!("{}: {} bytes, {} fields, max depth {}", type_name, stats.total_bytes, stats.field_count(), stats.max_depth)
+}
+
+/// `--message-type`指定的消息，可以带有`.proto`中的package前缀；
+/// 没有package前缀时也可以匹配唯一一个以`.NAME`结尾的完整名称（例如描述文件中注册的类型）
+fn resolve_message_type(parser: &Parser, name: &str) -> Option<String> {
+    let name = name.trim_start_matches('.');
+    let mut candidate = name;
+    while !parser.types.contains_key(candidate) {
+        let Some((_, rest)) = candidate.split_once('.') else {
+            let suffix = format!(".{}", name);
+            let mut matches = parser.types.keys().filter(|key| key.ends_with(&suffix));
+            return matches.next().filter(|_| matches.next().is_none()).cloned();
+        };
+        candidate = rest;
+    }
+    Some(candidate.to_string())
+}
+
+/// 解析内置示例，检查输出中是否包含预期的内容
+fn self_test() -> Result<(), String> {
+    let output = parse_main(&mut Parser::new(), PROTOBUF_EXAMPLE, &["root"], OutputFormat::Text, Framing::Single)
+        .map_err(|e| format!("failed to parse example: {}", e))?;
+    
+    for token in ["kotlin46", "POKECOIN"] {
+        if !output.contains(token) {
+            return Err(format!("missing {:?} in output:\n{}", token, output));
+        }
+    }
+    Ok(())
+}
+
+/// 轮询文件的修改时间和大小，文件发生变化时返回新的内容
+struct FileWatcher {
+    path: PathBuf,
+    last_state: Option<(SystemTime, u64)>,
+    error_reported: bool,
+}
+
+impl FileWatcher {
+    fn new(path: PathBuf) -> Self {
+        FileWatcher { path, last_state: None, error_reported: false }
+    }
     
-    match parse_main(&buffer) {
-        Ok(result) => {
-            println!("{}", result);
+    fn poll(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let result = std::fs::metadata(&self.path)
+            .and_then(|metadata| Ok((metadata.modified()?, metadata.len())));
+        let state = match result {
+            Ok(state) => state,
+            // 文件可能正在被重新生成，同一个错误只报告一次
+            Err(e) if !self.error_reported => {
+                self.error_reported = true;
+                self.last_state = None;
+                return Err(e);
+            }
+            Err(_) => return Ok(None),
+        };
+        self.error_reported = false;
+        
+        if self.last_state == Some(state) {
+            return Ok(None);
         }
-        Err(e) => {
-            eprintln!("Error: {:?}", e);
+        self.last_state = Some(state);
+        std::fs::read(&self.path).map(Some)
+    }
+}
+
+fn watch(parser: &mut Parser, path: PathBuf, type_names: &[&str], format: OutputFormat, framing: Framing) -> ! {
+    let mut watcher = FileWatcher::new(path);
+    loop {
+        match watcher.poll() {
+            Ok(Some(data)) => {
+                // 清屏后重新输出
+                print!("\x1b[2J\x1b[H");
+                match parse_main(parser, &data, type_names, format, framing) {
+                    Ok(result) => println!("{}", result),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Error: {}", e),
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+}
+
+fn main() {
+    let mut parser = Parser::new();
+    if let Some(path) = default_schema_path() {
+        let result = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parser.load_schema(&text).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("Error: failed to load schema {}: {}", path.display(), e);
             std::process::exit(1);
         }
     }
+    
+    let mut format = OutputFormat::Text;
+    let mut color_mode = ColorMode::Auto;
+    let mut watch_path = None;
+    let mut message_type = None;
+    let mut segment_types = Vec::new();
+    let mut json_path = None;
+    let mut expand_path = None;
+    let mut nesting_report_enabled = false;
+    let mut summary_enabled = false;
+    let mut framing = Framing::Single;
+    let mut stream: Option<bool> = None;
+    let mut files = Vec::new();
+    let mut hex_input = false;
+    let mut base64_input = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            arg if arg.starts_with("--fixed=") => {
+                let names = arg.trim_start_matches("--fixed=").split(',');
+                match names.map(FixedInterpretation::from_name).collect() {
+                    Some(interpretations) => parser.options.fixed_interpretations = interpretations,
+                    None => {
+                        eprintln!("Invalid interpretation list: {}\n{}", arg, USAGE);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            arg if arg.starts_with("--chunk-order=") => {
+                let names = arg.trim_start_matches("--chunk-order=").split(',');
+                match names.map(ChunkKind::from_name).collect() {
+                    Some(order) => parser.options.chunk_interpretation_order = order,
+                    None => {
+                        eprintln!("Invalid interpretation list: {}\n{}", arg, USAGE);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            arg if arg.starts_with("--max-digits=") => {
+                match arg.trim_start_matches("--max-digits=").parse() {
+                    Ok(limit) => parser.options.max_integer_digits = Some(limit),
+                    Err(_) => {
+                        eprintln!("Invalid digit limit: {}\n{}", arg, USAGE);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            "--varints=error" => parser.options.non_canonical_varints = NonCanonicalVarints::Error,
+            "--varints=warn" => parser.options.non_canonical_varints = NonCanonicalVarints::Warn,
+            "--varints=accept" => parser.options.non_canonical_varints = NonCanonicalVarints::Accept,
+            arg if arg.starts_with("--color=") => {
+                match ColorMode::from_name(arg.trim_start_matches("--color=")) {
+                    Some(mode) => color_mode = mode,
+                    None => {
+                        eprintln!("Invalid color mode: {}\n{}", arg, USAGE);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            arg if arg.starts_with("--max-depth=") => {
+                match arg.trim_start_matches("--max-depth=").parse() {
+                    Ok(depth) => parser.max_depth = depth,
+                    Err(_) => {
+                        eprintln!("Invalid depth limit: {}\n{}", arg, USAGE);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            arg if arg.starts_with("--display-depth=") => {
+                match arg.trim_start_matches("--display-depth=").parse() {
+                    Ok(depth) => parser.options.max_display_depth = Some(depth),
+                    Err(_) => {
+                        eprintln!("Invalid display depth: {}\n{}", arg, USAGE);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            arg if arg.starts_with("--expand=") => {
+                match parse_expand_path(arg.trim_start_matches("--expand=")) {
+                    Some(path) => expand_path = Some(path),
+                    None => {
+                        eprintln!("Invalid path: {}\n{}", arg, USAGE);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            "--format=text" => format = OutputFormat::Text,
+            "--format=rust" => format = OutputFormat::RustLiteral,
+            "--format=tree" => format = OutputFormat::Tree,
+            "--format=html" => format = OutputFormat::Html,
+            "--format=json" => format = OutputFormat::Json,
+            "--format=protoscope" => format = OutputFormat::Protoscope,
+            "--hex" => hex_input = true,
+            "--base64" => base64_input = true,
+            "--ascii" => parser.options.ascii_only = true,
+            "--wire-details" => parser.options.show_wire_details = true,
+            "--offsets" => parser.options.show_offsets = true,
+            "--zigzag-raw" => parser.options.show_zigzag_raw = true,
+            "--check-nul" => parser.options.flag_nul_in_strings = true,
+            "--float-arrays" => parser.options.show_float_arrays = true,
+            "--trace" => parser.options.trace = true,
+            "--max-nesting-report" => nesting_report_enabled = true,
+            "--summary" => summary_enabled = true,
+            "--no-summary" => summary_enabled = false,
+            "--delimited" => framing = Framing::Delimited,
+            "--grpc" => framing = Framing::Grpc,
+            "--stream" => stream = Some(false),
+            "--stream=hex" => stream = Some(true),
+            "--no-nested" => parser.options.no_nested = true,
+            "--strict" => parser.options.strict = true,
+            "--lenient" => parser.options.lenient = true,
+            "--allow-field-zero" => parser.options.allow_field_zero = true,
+            "--zero-padding" => parser.options.allow_zero_padding = true,
+            "--watch" => match args.next() {
+                Some(path) => watch_path = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("--watch requires a path\n{}", USAGE);
+                    std::process::exit(2);
+                }
+            },
+            "--json-path" => match args.next() {
+                Some(path) => json_path = Some(path),
+                None => {
+                    eprintln!("--json-path requires a path\n{}", USAGE);
+                    std::process::exit(2);
+                }
+            },
+            "--enum-proto" => {
+                let Some(path) = args.next() else {
+                    eprintln!("--enum-proto requires a path\n{}", USAGE);
+                    std::process::exit(2);
+                };
+                let result = std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|text| parser.load_enum_proto(&text).map_err(|e| e.to_string()));
+                if let Err(e) = result {
+                    eprintln!("Error: failed to load {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            "--proto" => {
+                let Some(path) = args.next() else {
+                    eprintln!("--proto requires a path\n{}", USAGE);
+                    std::process::exit(2);
+                };
+                let result = std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|text| parser.load_proto(&text).map_err(|e| e.to_string()));
+                if let Err(e) = result {
+                    eprintln!("Error: failed to load {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            "--descriptors" => {
+                let Some(path) = args.next() else {
+                    eprintln!("--descriptors requires a path\n{}", USAGE);
+                    std::process::exit(2);
+                };
+                let result = std::fs::read(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| parser.load_descriptor_set(&data).map_err(|e| e.to_string()));
+                if let Err(e) = result {
+                    eprintln!("Error: failed to load {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            "--message-type" | "--root-type" => match args.next() {
+                Some(name) => message_type = Some(name),
+                None => {
+                    eprintln!("{} requires a name\n{}", arg, USAGE);
+                    std::process::exit(2);
+                }
+            },
+            "--type" => match args.next() {
+                Some(name) => segment_types.push(name),
+                None => {
+                    eprintln!("--type requires a name\n{}", USAGE);
+                    std::process::exit(2);
+                }
+            },
+            "--selftest" => match self_test() {
+                Ok(()) => {
+                    println!("selftest passed");
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("selftest failed: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            _ if !arg.starts_with('-') => files.push(arg),
+            _ => {
+                eprintln!("Unknown option: {}\n{}", arg, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+    
+    // 只有文本格式会逐个字段解析并统计
+    if nesting_report_enabled && format != OutputFormat::Text {
+        eprintln!("--max-nesting-report requires --format=text\n{}", USAGE);
+        std::process::exit(2);
+    }
+    if framing != Framing::Single && (format != OutputFormat::Text || stream.is_some() || expand_path.is_some()) {
+        eprintln!("--delimited and --grpc require --format=text and cannot be combined with --stream or --expand\n{}", USAGE);
+        std::process::exit(2);
+    }
+    if !segment_types.is_empty() && framing == Framing::Single {
+        eprintln!("--type requires --delimited or --grpc\n{}", USAGE);
+        std::process::exit(2);
+    }
+    if summary_enabled && (format != OutputFormat::Text || stream.is_some()) {
+        eprintln!("--summary requires --format=text and cannot be combined with --stream\n{}", USAGE);
+        std::process::exit(2);
+    }
+    
+    // `--proto`可能在`--message-type`之后，所有参数读完后才能查找
+    let root_type = match message_type {
+        Some(name) => match resolve_message_type(&parser, &name) {
+            Some(root_type) => root_type,
+            None => {
+                eprintln!("Unknown message type: {}\n{}", name, USAGE);
+                std::process::exit(2);
+            }
+        },
+        None => "root".to_string(),
+    };
+    let segment_types: Vec<String> = segment_types.iter()
+        .map(|name| match resolve_message_type(&parser, name) {
+            Some(segment_type) => segment_type,
+            None => {
+                eprintln!("Unknown message type: {}\n{}", name, USAGE);
+                std::process::exit(2);
+            }
+        })
+        .collect();
+    
+    if hex_input && base64_input {
+        eprintln!("--hex and --base64 cannot be used together\n{}", USAGE);
+        std::process::exit(2);
+    }
+    
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    set_color_enabled(color_mode.should_color(std::io::stdout().is_terminal(), no_color_env));
+    
+    if let Some(path) = watch_path {
+        watch(&mut parser, path, &type_names(&segment_types, &root_type), format, framing);
+    }
+    
+    // 逐个字段输出，不把输入读入内存
+    if let Some(show_hex) = stream {
+        if format != OutputFormat::Text || json_path.is_some() || expand_path.is_some() || hex_input || base64_input {
+            eprintln!("--stream only works with the text format and cannot be combined with --json-path, --expand, --hex or --base64\n{}", USAGE);
+            std::process::exit(2);
+        }
+        let mut failed = false;
+        for (index, file) in input_names(&files).enumerate() {
+            print_header(&files, index);
+            let result = match file {
+                Some(path) => std::fs::File::open(path)
+                    .map_err(|e| format!("failed to open {}: {}", path, e))
+                    .and_then(|mut file| {
+                        let mut reader = std::io::BufReader::new(&mut file);
+                        parser.write_message(&mut reader, &root_type, &mut std::io::stdout().lock(), show_hex)
+                            .map_err(|e| e.to_string())
+                    }),
+                None => parser.write_message(&mut std::io::stdin().lock(), &root_type, &mut std::io::stdout().lock(), show_hex)
+                    .map_err(|e| e.to_string()),
+            };
+            print_trace(&parser);
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                failed = true;
+                continue;
+            }
+            if nesting_report_enabled {
+                println!("{}", nesting_report(&parser.stats));
+            }
+            for warning in &parser.warnings {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+        std::process::exit(if failed { 1 } else { 0 });
+    }
+    
+    let settings = InputSettings { format, hex: hex_input, base64: base64_input, json_path, expand_path, nesting_report: nesting_report_enabled, summary: summary_enabled, framing, root_type, segment_types };
+    let mut failed = false;
+    for (index, file) in input_names(&files).enumerate() {
+        print_header(&files, index);
+        let data = match file {
+            Some(path) => std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path, e)),
+            None => {
+                let mut buffer = Vec::new();
+                std::io::stdin().read_to_end(&mut buffer)
+                    .map(|_| buffer)
+                    .map_err(|e| format!("failed to read from stdin: {}", e))
+            }
+        };
+        
+        let result = data.and_then(|data| inspect(&mut parser, data, &settings));
+        print_trace(&parser);
+        match result {
+            Ok(result) => {
+                println!("{}", result);
+                for warning in &parser.warnings {
+                    eprintln!("Warning: {}", warning);
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// `--trace`的输出写到stderr，不影响stdout中的解析结果
+fn print_trace(parser: &Parser) {
+    for line in &parser.trace {
+        eprintln!("trace: {}", line);
+    }
+}
+
+/// 每份输入共用的命令行设置
+struct InputSettings {
+    format: OutputFormat,
+    /// 输入是十六进制文本
+    hex: bool,
+    /// 输入是base64文本，标准和URL安全的字母表都可以
+    base64: bool,
+    json_path: Option<String>,
+    /// `--expand`的路径：(field number, 第几次出现)
+    expand_path: Option<Vec<(u32, usize)>>,
+    nesting_report: bool,
+    summary: bool,
+    framing: Framing,
+    /// 顶层消息的类型，默认为`root`
+    root_type: String,
+    /// `--type`按顺序指定的每段消息的类型，类型不够时沿用最后一个
+    segment_types: Vec<String>,
+}
+
+/// 依次返回每个输入文件，没有指定文件时只有一个`None`，表示读取stdin
+fn input_names(files: &[String]) -> impl Iterator<Item = Option<&str>> {
+    let stdin = files.is_empty().then_some(None);
+    files.iter().map(|f| Some(f.as_str())).chain(stdin)
+}
+
+/// 指定了多个文件时，在每个文件的输出前加上文件名
+fn print_header(files: &[String], index: usize) {
+    if files.len() > 1 {
+        if index > 0 {
+            println!();
+        }
+        println!("==> {} <==", files[index]);
+    }
+}
+
+/// 处理一份输入，返回需要输出的文本
+fn inspect(parser: &mut Parser, mut data: Vec<u8>, settings: &InputSettings) -> Result<String, String> {
+    if settings.hex {
+        data = decode_hex(&String::from_utf8_lossy(&data)).map_err(|e| e.to_string())?;
+    }
+    if settings.base64 {
+        // 忽略末尾的换行，报告的位置以去掉开头空白后的文本为准
+        data = decode_base64(String::from_utf8_lossy(&data).trim()).map_err(|e| e.to_string())?;
+    }
+    
+    // 从JSON日志中取出base64编码的protobuf
+    if let Some(path) = &settings.json_path {
+        let json = String::from_utf8_lossy(&data);
+        data = extract_json_string(&json, path)
+            .and_then(|payload| decode_base64(&payload))
+            .map_err(|e| e.to_string())?;
+    }
+    
+    if let Some(path) = &settings.expand_path {
+        return match parser.expand_path(&data, path) {
+            Ok(Some(result)) => Ok(result),
+            Ok(None) => Err("path not found".to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+    }
+    
+    let type_names = type_names(&settings.segment_types, &settings.root_type);
+    let mut result = parse_main(parser, &data, &type_names, settings.format, settings.framing).map_err(|e| e.to_string())?;
+    if settings.nesting_report && !data.is_empty() {
+        result = format!("{}\n{}", result, nesting_report(&parser.stats));
+    }
+    if settings.summary && !data.is_empty() {
+        result = format!("{}\n{}", summary(&settings.root_type, &parser.stats), result);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(parse_main(&mut Parser::new(), b"", &["root"], OutputFormat::Text, Framing::Single).unwrap(), "(empty input)");
+        assert_eq!(parse_main(&mut Parser::new(), b"", &["root"], OutputFormat::Json, Framing::Single).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_structured_formats_use_options() {
+        // {1: {1: 1}}，没有schema时会被猜测为嵌套消息
+        let data = b"\x0a\x02\x08\x01";
+        let output = parse_main(&mut Parser::new(), data, &["root"], OutputFormat::Json, Framing::Single).unwrap();
+        assert!(output.contains("\"type\": \"message\""), "{}", output);
+        
+        let mut parser = Parser::new().with_strict(true);
+        let output = parse_main(&mut parser, data, &["root"], OutputFormat::Json, Framing::Single).unwrap();
+        assert_eq!(output, "[{\"field\": 1, \"wire_type\": 2, \"type\": \"bytes\", \"value\": \"0801\"}]");
+        
+        parser.options.strict = false;
+        parser.options.no_nested = true;
+        let output = parse_main(&mut parser, data, &["root"], OutputFormat::Tree, Framing::Single).unwrap();
+        assert!(!output.contains("message"), "{}", output);
+    }
+
+    #[test]
+    fn test_resolve_message_type() {
+        let mut parser = Parser::new();
+        parser.load_schema("[shop.Order]\n[shop.Order.Item]\n[Item]\n").unwrap();
+        assert_eq!(resolve_message_type(&parser, ".shop.Order").as_deref(), Some("shop.Order"));
+        assert_eq!(resolve_message_type(&parser, "other.Item").as_deref(), Some("Item"));
+        // 没有package前缀时匹配唯一的完整名称
+        assert_eq!(resolve_message_type(&parser, "Order").as_deref(), Some("shop.Order"));
+        assert_eq!(resolve_message_type(&parser, "Missing"), None);
+    }
+
+    #[test]
+    fn test_self_test() {
+        assert_eq!(self_test(), Ok(()));
+    }
+
+    #[test]
+    fn test_file_watcher() {
+        let path = std::env::temp_dir().join(format!("protobuf-inspector-watch-{}.bin", std::process::id()));
+        std::fs::write(&path, b"\x08\x01").unwrap();
+        
+        let mut watcher = FileWatcher::new(path.clone());
+        assert_eq!(watcher.poll().unwrap(), Some(b"\x08\x01".to_vec()));
+        assert_eq!(watcher.poll().unwrap(), None);
+        
+        // 模拟文件被重新生成
+        std::fs::write(&path, b"\x08\x96\x01").unwrap();
+        let data = watcher.poll().unwrap().unwrap();
+        let output = parse_main(&mut Parser::new(), &data, &["root"], OutputFormat::Text, Framing::Single).unwrap();
+        assert!(output.contains("150"), "{}", output);
+        assert_eq!(watcher.poll().unwrap(), None);
+        
+        // 文件消失时只报告一次错误
+        std::fs::remove_file(&path).unwrap();
+        assert!(watcher.poll().is_err());
+        assert_eq!(watcher.poll().unwrap(), None);
+    }
+    
+    #[test]
+    fn test_delimited_input() {
+        let data = b"\x02\x08\x01\x03\x08\x96\x01\x05\x0a";
+        let output = parse_main(&mut Parser::new(), data, &["root"], OutputFormat::Text, Framing::Delimited).unwrap();
+        let output = protobuf_inspector_rs::formatter::strip_ansi(&output);
+        assert!(output.starts_with("[0] root:"), "{}", output);
+        assert!(output.contains("[1] root:\n    1 <varint> = 150"), "{}", output);
+        assert!(output.ends_with("[2] (truncated message: expected 5 bytes, 1 available)"), "{}", output);
+    }
+    
+    #[test]
+    fn test_nesting_report() {
+        // 1 { 2 { 3: 150 } }
+        let mut parser = Parser::new();
+        parse_main(&mut parser, b"\x0a\x05\x12\x03\x18\x96\x01\x20\x01", &["root"], OutputFormat::Text, Framing::Single).unwrap();
+        assert_eq!(nesting_report(&parser.stats), "max nesting depth: 2 (path 1.2.3)");
+        
+        parse_main(&mut parser, b"\x08\x01", &["root"], OutputFormat::Text, Framing::Single).unwrap();
+        assert_eq!(nesting_report(&parser.stats), "max nesting depth: 0 (path 1)");
+    }
+    
+    #[test]
+    fn test_summary() {
+        let mut parser = Parser::new();
+        parse_main(&mut parser, PROTOBUF_EXAMPLE, &["root"], OutputFormat::Text, Framing::Single).unwrap();
+        assert_eq!(summary("root", &parser.stats), "root: 60 bytes, 11 fields, max depth 1");
+        
+        let settings = InputSettings { format: OutputFormat::Text, hex: false, base64: false, json_path: None, expand_path: None, nesting_report: false, summary: true, framing: Framing::Single, root_type: "root".to_string(), segment_types: Vec::new() };
+        let output = inspect(&mut Parser::new(), b"\x0a\x05\x12\x03\x18\x96\x01\x20\x01".to_vec(), &settings).unwrap();
+        assert!(output.starts_with("root: 9 bytes, 4 fields, max depth 2\nroot:"), "{}", output);
+        assert_eq!(inspect(&mut Parser::new(), Vec::new(), &settings).unwrap(), "(empty input)");
+    }
+    
+    #[test]
+    fn test_inspect_files() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("protobuf-inspector-input-{}.bin", std::process::id()));
+        std::fs::write(&path, b"\x08\x96\x01").unwrap();
+        let files = vec![path.display().to_string(), "missing.bin".to_string()];
+        assert_eq!(input_names(&files).collect::<Vec<_>>(), [Some(files[0].as_str()), Some("missing.bin")]);
+        assert_eq!(input_names(&[]).collect::<Vec<_>>(), [None]);
+        
+        let settings = InputSettings { format: OutputFormat::Text, hex: false, base64: false, json_path: None, expand_path: None, nesting_report: false, summary: false, framing: Framing::Single, root_type: "root".to_string(), segment_types: Vec::new() };
+        let data = std::fs::read(&path).unwrap();
+        let output = inspect(&mut Parser::new(), data, &settings).unwrap();
+        assert!(output.contains("150"), "{}", output);
+        std::fs::remove_file(&path).unwrap();
+        
+        let hex_settings = InputSettings { hex: true, ..settings };
+        let output = inspect(&mut Parser::new(), b"08 96 01\n".to_vec(), &hex_settings).unwrap();
+        assert!(output.contains("150"), "{}", output);
+        assert_eq!(inspect(&mut Parser::new(), b"08 9z".to_vec(), &hex_settings), Err("invalid hex digit at byte 4".to_string()));
+        
+        let base64_settings = InputSettings { hex: false, base64: true, ..hex_settings };
+        let output = inspect(&mut Parser::new(), b"CJYB\n".to_vec(), &base64_settings).unwrap();
+        assert!(output.contains("150"), "{}", output);
+        
+        let settings = InputSettings { base64: false, expand_path: Some(vec![(5, 0)]), ..base64_settings };
+        assert_eq!(inspect(&mut Parser::new(), b"\x08\x01".to_vec(), &settings), Err("path not found".to_string()));
+    }
 }
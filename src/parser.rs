@@ -1,13 +1,89 @@
-use crate::core::{self, read_identifier, read_value};
-use crate::formatter::{foreground_bold, indent};
+use crate::core::{self, decode_varint_bytes, encode_varint, is_valid_field_number, parse_varint_bytes, read_bounded_value, read_identifier, read_value, read_varint, zigzag_decode};
+use crate::descriptor::parse_descriptor_set;
+use crate::formatter::{dim, escape_html, escape_json, foreground, foreground_bold, hex_dump, indent, strip_ansi, OutputFormat};
+use crate::grpc;
+use crate::guesser::{guess_is_message, MessageGuess};
+use crate::input::decode_base64;
+use crate::iso_codes;
+use crate::schema::{parse_enum_proto, parse_message_proto, parse_schema, Schema, SchemaError};
+use crate::tree::{decode_message_with_max_depth, Field, Message};
 use crate::types::*;
-use std::collections::HashMap;
-use std::io::Cursor;
+use crate::well_known;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Cursor, Read, Write};
+use std::sync::Arc;
+
+/// `Parser::set_field_postprocessor`设置的回调
+pub type FieldPostprocessor = Box<dyn Fn(&mut Field)>;
+
+/// `Parser::extract_chunks`的结果：(字段路径, 原始内容)
+pub type ExtractedChunk = (Vec<u32>, Vec<u8>);
+
+/// `Parser::resolve_field_type`得到的类型或字段名
+///
+/// 线类型名称和schema中的字符串直接共享，只有改写过的（例如去掉`deprecated`）才单独分配
+enum SchemaStr {
+    Static(&'static str),
+    Shared(Arc<str>),
+    Owned(String),
+}
+
+impl std::ops::Deref for SchemaStr {
+    type Target = str;
+    
+    fn deref(&self) -> &str {
+        match self {
+            SchemaStr::Static(s) => s,
+            SchemaStr::Shared(s) => s,
+            SchemaStr::Owned(s) => s,
+        }
+    }
+}
+
+/// 消息中一个字段的输出，`format_message`把连续出现的同一字段合并到一个标题下
+struct FieldLine {
+    /// 不属于某个字段的行（例如末尾的补0）为`None`，不会被合并
+    key: Option<u32>,
+    wire_type: u8,
+    /// `options.show_offsets`的位置前缀，没有开启时为空
+    offsets: String,
+    /// 以field number开头的完整输出
+    text: String,
+}
+
+/// `Parser::max_depth`的默认值
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
+/// `Parser::nested_parse_limit`的默认值
+pub const DEFAULT_NESTED_PARSE_LIMIT: usize = 4096;
 
 pub struct Parser {
-    pub types: HashMap<String, HashMap<u32, (String, String)>>,
+    pub types: Schema,
     pub native_types: HashMap<String, Box<dyn TypeHandler>>,
-    pub wire_types_not_matching: bool,
+    /// 最近一次解析中线类型与schema中的类型或者同一消息中前一次出现不一致的字段：(消息类型, field number)
+    pub wire_type_mismatches: BTreeSet<(String, u32)>,
+    /// 尝试识别`[varint长度][message]`形式的二次封装chunk
+    pub detect_framed_messages: bool,
+    pub options: ParseOptions,
+    /// 嵌套消息的最大层数，超过时显示`recursion depth exceeded`而不再展开
+    pub max_depth: usize,
+    /// 只对短于这个长度的chunk尝试解析嵌套消息
+    ///
+    /// 每个chunk都会先按消息试着解析一遍，失败后才显示为字符串或bytes，
+    /// 上限越大，较长的bytes字段和其中的各层chunk被重复尝试的开销越大
+    pub nested_parse_limit: usize,
+    /// 最近一次`parse_message`/`parse_delimited_messages`的统计，不包含猜测失败的嵌套消息
+    pub stats: WireTypeStats,
+    /// 最近一次解析中收集到的警告，例如出现了schema中标记为`deprecated`的字段
+    pub warnings: Vec<String>,
+    /// 开启`options.trace`时，最近一次解析中每个字段的判断过程
+    pub trace: Vec<String>,
+    /// 正在解析的字段路径，用于折叠摘要中提示展开路径：(field number, 在所在消息中是第几个chunk)
+    current_path: Vec<(u32, usize)>,
+    /// 正在解析的数据在最外层输入中的位置，用于`options.show_offsets`
+    base_offset: usize,
+    field_postprocessor: Option<FieldPostprocessor>,
 }
 
 impl Parser {
@@ -15,7 +91,17 @@ impl Parser {
         let mut parser = Parser {
             types: HashMap::new(),
             native_types: HashMap::new(),
-            wire_types_not_matching: false,
+            wire_type_mismatches: BTreeSet::new(),
+            detect_framed_messages: false,
+            options: ParseOptions::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            nested_parse_limit: DEFAULT_NESTED_PARSE_LIMIT,
+            stats: WireTypeStats::default(),
+            warnings: Vec::new(),
+            trace: Vec::new(),
+            current_path: Vec::new(),
+            base_offset: 0,
+            field_postprocessor: None,
         };
         
         parser.types.insert("message".to_string(), HashMap::new());
@@ -29,28 +115,123 @@ impl Parser {
         parser.register_native_type("sint32", Box::new(SInt32Handler));
         parser.register_native_type("sint64", Box::new(SInt64Handler));
         parser.register_native_type("bool", Box::new(BoolHandler));
-        parser.register_native_type("enum", Box::new(VarintHandler));
+        parser.register_native_type("enum", Box::new(EnumHandler { values: Vec::new(), bitmask: false }));
         parser.register_native_type("32bit", Box::new(Bit32Handler));
         parser.register_native_type("64bit", Box::new(Bit64Handler));
         parser.register_native_type("chunk", Box::new(ChunkHandler));
         parser.register_native_type("bytes", Box::new(BytesHandler));
         parser.register_native_type("string", Box::new(StringHandler));
+        parser.register_native_type("string:latin1", Box::new(StringHandler));
+        parser.register_native_type("string:gbk", Box::new(StringHandler));
         parser.register_native_type("message", Box::new(ChunkHandler));
-        parser.register_native_type("packed", Box::new(ChunkHandler));
+        parser.register_native_type("any", Box::new(ChunkHandler));
         parser.register_native_type("float", Box::new(FloatHandler));
         parser.register_native_type("double", Box::new(DoubleHandler));
+        parser.register_native_type("float16", Box::new(Float16Handler));
+        parser.register_native_type("bfloat16", Box::new(BFloat16Handler));
+        parser.register_native_type("timestamp_delta", Box::new(TimestampDeltaHandler));
+        parser.register_native_type("bitfield", Box::new(BitfieldHandler));
+        parser.register_native_type("iso_country", Box::new(IsoCodeHandler {
+            by_number: iso_codes::country_by_number,
+            by_alpha: iso_codes::country_by_alpha,
+        }));
+        parser.register_native_type("iso_currency", Box::new(IsoCodeHandler {
+            by_number: iso_codes::currency_by_number,
+            by_alpha: iso_codes::currency_by_alpha,
+        }));
         parser.register_native_type("fixed32", Box::new(Fixed32Handler));
         parser.register_native_type("sfixed32", Box::new(SFixed32Handler));
         parser.register_native_type("fixed64", Box::new(Fixed64Handler));
         parser.register_native_type("sfixed64", Box::new(SFixed64Handler));
         
+        parser.register_enum("google.rpc.Code", grpc::STATUS_CODES, false);
+        for (message, fields) in grpc::STATUS_TYPES.iter().chain(well_known::WELL_KNOWN_TYPES) {
+            let fields = fields.iter()
+                .map(|(number, field_type, name)| (*number, ((*field_type).into(), (*name).into())))
+                .collect();
+            parser.types.insert(message.to_string(), fields);
+        }
+        
         parser
     }
     
+    /// 设置嵌套消息的最大层数，默认为`DEFAULT_MAX_DEPTH`
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+    
+    /// 开启严格模式，见`ParseOptions::strict`
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+    
+    /// 设置尝试解析嵌套消息的chunk长度上限，默认为`DEFAULT_NESTED_PARSE_LIMIT`，见`nested_parse_limit`
+    pub fn with_nested_parse_limit(mut self, limit: usize) -> Self {
+        self.nested_parse_limit = limit;
+        self
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
     fn register_native_type(&mut self, name: &str, handler: Box<dyn TypeHandler>) {
         self.native_types.insert(name.to_string(), handler);
     }
     
+    /// 注册一个enum类型，之后可以在`types`中以`name`作为字段类型
+    pub fn register_enum(&mut self, name: &str, values: &[(i64, &str)], bitmask: bool) {
+        let values = values.iter()
+            .map(|(v, n)| (*v, n.to_string()))
+            .collect();
+        self.register_native_type(name, Box::new(EnumHandler { values, bitmask }));
+    }
+    
+    /// 从`.proto`文本中注册所有enum，只解析enum定义，不需要完整的消息schema
+    pub fn load_enum_proto(&mut self, text: &str) -> Result<(), SchemaError> {
+        for (name, values) in parse_enum_proto(text)? {
+            self.register_native_type(&name, Box::new(EnumHandler { values, bitmask: false }));
+        }
+        Ok(())
+    }
+    
+    /// 从`.proto`文本中注册所有enum和message，嵌套的message以`Outer.Inner`命名
+    pub fn load_proto(&mut self, text: &str) -> Result<(), SchemaError> {
+        let schema = parse_message_proto(text)?;
+        self.load_enum_proto(text)?;
+        for (message, fields) in schema {
+            self.types.entry(message).or_default().extend(fields);
+        }
+        Ok(())
+    }
+    
+    /// 从`protoc --descriptor_set_out`生成的`FileDescriptorSet`中注册所有enum和message，
+    /// 都以完整名称`pkg.Outer.Inner`命名
+    pub fn load_descriptor_set(&mut self, data: &[u8]) -> Result<(), core::Error> {
+        let (schema, enums) = parse_descriptor_set(data)?;
+        for (name, values) in enums {
+            self.register_native_type(&name, Box::new(EnumHandler { values, bitmask: false }));
+        }
+        for (message, fields) in schema {
+            self.types.entry(message).or_default().extend(fields);
+        }
+        Ok(())
+    }
+    
+    /// 加载TOML格式的schema（格式见`schema::parse_schema`），同名消息中的字段会被覆盖
+    pub fn load_schema(&mut self, text: &str) -> Result<(), SchemaError> {
+        for (message, fields) in parse_schema(text)? {
+            self.types.entry(message).or_default().extend(fields);
+        }
+        Ok(())
+    }
+    
     pub fn match_native_type(&self, type_name: &str) -> &dyn TypeHandler {
         let type_primary = type_name.split_whitespace().next().unwrap_or(type_name);
         if let Some(handler) = self.native_types.get(type_primary) {
@@ -61,64 +242,976 @@ impl Parser {
     }
     
     pub fn parse_message(&mut self, data: &[u8], type_name: &str) -> Result<String, core::Error> {
+        // 设置了postprocessor时先在字段树上调用它，再按修改后的字段重新编码解析
+        if let Some(processor) = self.field_postprocessor.take() {
+            let result = self.parse_to_tree(data, type_name).and_then(|mut message| {
+                message.visit_fields_mut(&mut |field| processor(field));
+                self.parse_message(&message.encode(), type_name)
+            });
+            self.field_postprocessor = Some(processor);
+            return result;
+        }
+
+        self.stats = WireTypeStats { total_bytes: data.len(), ..WireTypeStats::default() };
+        self.warnings.clear();
+        self.wire_type_mismatches.clear();
+        self.trace.clear();
+        self.current_path.clear();
+        self.base_offset = 0;
         self.parse_message_with_depth(data, type_name, 0)
     }
     
+    /// 数据后面还有其他内容时，只解析开头能够完整读取的字段，同时返回这些字段占用的字节数。
+    /// 遇到无法读取的tag、超出数据末尾的值、没有对应EndGroup的group或者多余的EndGroup时认为消息在此结束
+    pub fn parse_message_with_len(&mut self, data: &[u8], type_name: &str) -> Result<(String, usize), core::Error> {
+        let consumed = self.complete_prefix_len(data);
+        let output = self.parse_message(&data[..consumed], type_name)?;
+        Ok((output, consumed))
+    }
+    
+    /// 开头能够完整读取的字段占用的字节数
+    fn complete_prefix_len(&self, data: &[u8]) -> usize {
+        let mut cursor = Cursor::new(data);
+        let mut consumed = 0;
+        while let Ok(Some((key, wire_type))) = self.read_next_identifier(&mut cursor) {
+            let complete = match wire_type {
+                // group需要读到对应的EndGroup才算完整
+                3 => {
+                    let start = cursor.position();
+                    skip_group(&mut cursor, key);
+                    cursor.position() != start
+                }
+                4 => false,
+                _ => read_bounded_value(&mut cursor, wire_type, 0).is_ok(),
+            };
+            if !complete {
+                break;
+            }
+            consumed = cursor.position() as usize;
+        }
+        consumed
+    }
+    
+    /// 按路径展开被折叠的嵌套消息，路径的每一步是(field number, 第几次出现)，
+    /// 重复字段的出现次数只计算chunk，从0开始；路径不存在或者不是chunk时返回`None`
+    ///
+    /// 只解析路径上的各层，展开后的消息同样受`max_display_depth`限制
+    pub fn expand_path(&mut self, data: &[u8], path: &[(u32, usize)]) -> Result<Option<String>, core::Error> {
+        let mut current = data.to_vec();
+        // `current`在最外层输入中的位置，`show_offsets`输出的仍然是绝对位置
+        let mut offset = 0;
+        for (depth, &(number, index)) in path.iter().enumerate() {
+            let mut cursor = Cursor::new(current.as_slice());
+            let mut found = None;
+            let mut seen = 0;
+            while let Some((key, wire_type)) = read_identifier(&mut cursor)? {
+                let value = read_bounded_value(&mut cursor, wire_type, depth)?;
+                if key == number && wire_type == 2 {
+                    if seen == index {
+                        found = Some((offset + cursor.position() as usize - value.len(), value));
+                        break;
+                    }
+                    seen += 1;
+                }
+            }
+            match found {
+                Some((value_offset, value)) => (offset, current) = (value_offset, value),
+                None => return Ok(None),
+            }
+        }
+        
+        self.stats = WireTypeStats { total_bytes: current.len(), ..WireTypeStats::default() };
+        self.warnings.clear();
+        self.wire_type_mismatches.clear();
+        self.trace.clear();
+        self.current_path = path.to_vec();
+        self.base_offset = offset;
+        self.parse_message_with_depth(&current, "message", 0).map(Some)
+    }
+    
+    /// 按schema解析为字段树，不生成带颜色的文本，适合嵌入到其他程序中使用
+    ///
+    /// 每个字段的`type_name`是schema中的类型（没有schema时为线类型的名称），
+    /// schema中声明为消息的字段按声明的类型展开，其他被识别为嵌套消息的chunk为`message`。
+    /// 嵌套消息的识别与`tree::decode_message`相同，同样受`no_nested`和`strict`控制
+    pub fn parse_to_tree(&mut self, data: &[u8], root_type: &str) -> Result<Message, core::Error> {
+        self.warnings.clear();
+        self.wire_type_mismatches.clear();
+        self.trace.clear();
+        self.parse_tree_with_depth(data, root_type, 0, 0)
+    }
+    
+    /// `base`为`data`在最外层输入中的位置，用于计算各个字段的`range`
+    fn parse_tree_with_depth(&mut self, data: &[u8], type_name: &str, depth: usize, base: usize) -> Result<Message, core::Error> {
+        let mut cursor = Cursor::new(data);
+        let mut fields = Vec::new();
+        
+        loop {
+            let start = base + cursor.position() as usize;
+            let Some((number, wire_type)) = self.read_next_identifier(&mut cursor)? else {
+                break;
+            };
+            let wire_type = WireType::from_u8(wire_type).ok_or(core::Error::InvalidWireType(wire_type))?;
+            if matches!(wire_type, WireType::StartGroup | WireType::EndGroup) {
+                let range = start..base + cursor.position() as usize;
+                fields.push(Field { number, wire_type, type_name: wire_type.name().to_string(), raw: Vec::new(), range, message: None });
+                continue;
+            }
+            
+            let raw = read_bounded_value(&mut cursor, wire_type as u8, depth)?;
+            let range = start..base + cursor.position() as usize;
+            let (actual_type, _) = self.resolve_field_type(type_name, number, wire_type as u8);
+            let declared = self.declared_message_type(&actual_type);
+            let message = if wire_type == WireType::Chunk && depth < self.max_depth && let Some(nested_type) = declared {
+                self.parse_tree_with_depth(&raw, nested_type, depth + 1, range.end - raw.len()).ok()
+            } else if &*actual_type == "chunk" && depth < self.max_depth && !self.options.no_nested && !self.options.strict
+                && guess_is_message(&raw) == Ok(true) {
+                self.parse_tree_with_depth(&raw, "message", depth + 1, range.end - raw.len()).ok()
+            } else {
+                None
+            };
+            let type_name = if message.is_some() && declared.is_none() { "message".to_string() } else { actual_type.to_string() };
+            fields.push(Field { number, wire_type, type_name, raw, range, message });
+        }
+        
+        Ok(Message { fields })
+    }
+    
+    /// 解析gRPC错误详情中的`google.rpc.Status`，例如trailer中的`grpc-status-details-bin`。
+    /// 输入可以是base64编码的文本，也可以是原始字节；`details`中已知的detail类型会按名称展开
+    pub fn parse_grpc_status(&mut self, data: &[u8]) -> Result<String, core::Error> {
+        // Status的第一个字节是tag（0x08或0x12），不可能是合法的base64字符
+        let decoded = std::str::from_utf8(data).ok()
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+            .and_then(|text| decode_base64(text).ok());
+        self.parse_message(decoded.as_deref().unwrap_or(data), "google.rpc.Status")
+    }
+    
+    /// 解析以varint长度前缀分隔的多条消息（`writeDelimitedTo`的格式），第i条消息按`type_names[i]`解析，
+    /// 类型不够时沿用最后一个类型
+    ///
+    /// 最后一条消息被截断时，输出已经解析的消息和期望与实际剩余的字节数，并记录一条警告
+    pub fn parse_delimited_messages(&mut self, data: &[u8], type_names: &[&str]) -> Result<String, core::Error> {
+        let mut cursor = Cursor::new(data);
+        let mut outputs = Vec::new();
+        self.stats = WireTypeStats { total_bytes: data.len(), ..WireTypeStats::default() };
+        self.warnings.clear();
+        self.wire_type_mismatches.clear();
+        self.trace.clear();
+        self.base_offset = 0;
+        
+        while let Some(length) = read_varint(&mut cursor)? {
+            let start = cursor.position() as usize;
+            let index = outputs.len();
+            let available = data.len() - start;
+            if length > available as u64 {
+                let problem = format!("expected {} bytes, {} available", length, available);
+                outputs.push(format!("[{}] {}", index, foreground(1, &format!("(truncated message: {})", problem))));
+                self.warnings.push(format!("truncated message {}: {}", index, problem));
+                break;
+            }
+            let end = start + length as usize;
+            
+            let type_name = type_names.get(index)
+                .or(type_names.last())
+                .copied()
+                .unwrap_or("root");
+            let message = self.with_base_offset(start, |parser| parser.parse_message_with_depth(&data[start..end], type_name, 0))?;
+            outputs.push(format!("[{}] {}", index, message));
+            cursor.set_position(end as u64);
+        }
+        
+        Ok(outputs.join("\n"))
+    }
+    
+    /// 解析gRPC的消息帧：每帧以1字节的压缩标记和4字节大端序的长度开头，第i帧按`type_names[i]`解析，
+    /// 类型不够时沿用最后一个类型
+    ///
+    /// 不知道压缩算法，压缩的帧不会被解析，只记录一条警告；最后一帧被截断时与`parse_delimited_messages`相同
+    pub fn parse_grpc_frames(&mut self, data: &[u8], type_names: &[&str]) -> Result<String, core::Error> {
+        let mut outputs = Vec::new();
+        let mut offset = 0;
+        self.stats = WireTypeStats { total_bytes: data.len(), ..WireTypeStats::default() };
+        self.warnings.clear();
+        self.wire_type_mismatches.clear();
+        self.trace.clear();
+        self.base_offset = 0;
+        
+        while offset < data.len() {
+            let index = outputs.len();
+            let Some(header) = data.get(offset..offset + 5) else {
+                let problem = format!("expected 5 header bytes, {} available", data.len() - offset);
+                outputs.push(format!("[{}] {}", index, foreground(1, &format!("(truncated frame: {})", problem))));
+                self.warnings.push(format!("truncated frame {}: {}", index, problem));
+                break;
+            };
+            // 标志只能是0或1，其他值说明帧头没有对齐或者数据不是gRPC帧，后面的长度也不可信
+            if header[0] > 1 {
+                let problem = format!("unknown flag 0x{:02X}", header[0]);
+                outputs.push(format!("[{}] {}", index, foreground(1, &format!("(invalid frame: {})", problem))));
+                self.warnings.push(format!("invalid frame {}: {}", index, problem));
+                break;
+            }
+            let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+            let start = offset + 5;
+            let available = data.len() - start;
+            if length > available {
+                let problem = format!("expected {} bytes, {} available", length, available);
+                outputs.push(format!("[{}] {}", index, foreground(1, &format!("(truncated frame: {})", problem))));
+                self.warnings.push(format!("truncated frame {}: {}", index, problem));
+                break;
+            }
+            offset = start + length;
+            
+            let label = format!("[{}] ({} bytes)", index, length);
+            if header[0] == 1 {
+                outputs.push(format!("{} {}", label, foreground(1, "(compressed)")));
+                self.warnings.push(format!("frame {} is compressed and cannot be decoded", index));
+                continue;
+            }
+            let type_name = type_names.get(index)
+                .or(type_names.last())
+                .copied()
+                .unwrap_or("root");
+            let message = self.with_base_offset(start, |parser| parser.parse_message_with_depth(&data[start..offset], type_name, 0))?;
+            outputs.push(format!("{} {}", label, message));
+        }
+        
+        Ok(outputs.join("\n"))
+    }
+    
+    /// 把文件映射到内存中，按`parse_delimited_messages`解析其中的多条消息，
+    /// 适合很大的抓包文件，不需要先把整个文件读入`Vec`
+    ///
+    /// 最后一条消息不完整时与`parse_delimited_messages`相同，输出期望和实际剩余的字节数并记录警告。
+    /// 解析期间文件不能被其他进程修改
+    #[cfg(feature = "mmap")]
+    pub fn parse_file_mmap<P: AsRef<std::path::Path>>(&mut self, path: P, type_name: &str) -> io::Result<String> {
+        let file = std::fs::File::open(path)?;
+        // 空文件无法映射
+        if file.metadata()?.len() == 0 {
+            return Ok(String::new());
+        }
+        // SAFETY: 映射是只读的，调用者需要保证解析期间文件不被截断或修改
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        self.parse_delimited_messages(&map, &[type_name])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+    
+    /// 边读边输出顶层字段，输入和输出都不需要完整地放在内存中，适合很大的输入。
+    /// 不开启`show_hex`时输出与`parse_message`相同（末尾多一个换行）
+    ///
+    /// `show_hex`会在每个字段之前输出它的原始字节（tag、长度前缀和值）。
+    /// 顶层字段无法引用同一消息中的其他字段，`timestamp_delta`只显示差值，
+    /// 重复出现的标量字段也不会标注`(overridden)`，连续出现的同一字段不会合并为`N name (M items) = [...]`
+    pub fn write_message<R: Read, W: Write>(&mut self, reader: &mut R, type_name: &str, out: &mut W, show_hex: bool) -> io::Result<()> {
+        let to_io_error = |e: core::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut reader = RecordingReader { inner: reader, recorded: Vec::new() };
+        let mut keys_types = HashMap::new();
+        let mut chunk_counts = HashMap::new();
+        let mut empty = true;
+        self.stats = WireTypeStats::default();
+        self.warnings.clear();
+        self.wire_type_mismatches.clear();
+        self.trace.clear();
+        self.current_path.clear();
+        self.base_offset = 0;
+        
+        writeln!(out, "{}:", type_name)?;
+        loop {
+            reader.recorded.clear();
+            let Some((key, wire_type)) = self.read_next_identifier(&mut reader).map_err(to_io_error)? else {
+                break;
+            };
+            if let Some(wire_type) = WireType::from_u8(wire_type) {
+                if self.stats.max_depth_path.is_empty() {
+                    self.stats.max_depth_path = vec![key];
+                }
+                self.stats.record(wire_type, 0);
+            }
+            
+            let line = if wire_type == 3 || wire_type == 4 {
+                self.handle_group_type(key, wire_type).map_err(to_io_error)?.unwrap_or_default()
+            } else {
+                let value = read_value(&mut reader, wire_type)
+                    .and_then(|value| value.ok_or(core::Error::Eof))
+                    .map_err(to_io_error)?;
+                self.check_wire_type_consistency(type_name, key, wire_type, &mut keys_types);
+                self.current_path.push((key, next_chunk_index(&mut chunk_counts, key, wire_type)));
+                let value_start = self.stats.total_bytes + reader.recorded.len() - value.len();
+                let line = self.with_base_offset(value_start, |parser| parser.parse_field_value(key, wire_type, type_name, &value, &[], 0));
+                self.current_path.pop();
+                line.map_err(to_io_error)?
+            };
+            let field_start = self.stats.total_bytes;
+            self.stats.total_bytes += reader.recorded.len();
+            let line = format!("{}{}", self.offsets(field_start, self.stats.total_bytes), line);
+            
+            if show_hex {
+                writeln!(out, "{}", indent(&hex_dump(&reader.recorded), Some("    # ")))?;
+            }
+            writeln!(out, "{}", indent(&line, None))?;
+            empty = false;
+        }
+        
+        if empty {
+            writeln!(out, "{}", indent("empty", None))?;
+        }
+        out.flush()
+    }
+    
     fn parse_message_with_depth(&mut self, data: &[u8], type_name: &str, depth: usize) -> Result<String, core::Error> {
-        if depth > 10 {
+        if depth > self.max_depth {
             return Ok("recursion depth exceeded".to_string());
         }
         
+        let lines = self.parse_fields(data, type_name, depth, None)?;
+        Ok(self.format_message(type_name, lines))
+    }
+    
+    /// 在`data[offset..]`中解析嵌套的内容，期间`base_offset`指向它的起始位置
+    fn with_base_offset<T>(&mut self, offset: usize, parse: impl FnOnce(&mut Self) -> T) -> T {
+        self.base_offset += offset;
+        let result = parse(self);
+        self.base_offset -= offset;
+        result
+    }
+    
+    /// 开启`options.show_offsets`时加在行首的字段在最外层输入中的位置`@start-end `（不含end）
+    fn offsets(&self, start: usize, end: usize) -> String {
+        if !self.options.show_offsets {
+            return String::new();
+        }
+        let range = format!("@{:#x}-{:#x}", self.base_offset + start, self.base_offset + end);
+        format!("{} ", dim(&range))
+    }
+    
+    /// 逐个解析字段；传入`guess`时同时进行消息猜测，一旦确定不是消息就提前返回错误
+    fn parse_fields(
+        &mut self,
+        data: &[u8],
+        type_name: &str,
+        depth: usize,
+        mut guess: Option<&mut MessageGuess>,
+    ) -> Result<Vec<FieldLine>, core::Error> {
         let mut cursor = Cursor::new(data);
         let mut lines = Vec::new();
         let mut keys_types = HashMap::new();
+        let mut chunk_counts = HashMap::new();
+        let occurrences = self.scalar_field_occurrences(data, type_name);
+        let merged = self.merge_repeated_fields(data, type_name);
+        let map_fields = self.guess_map_fields(data, type_name);
         
-        while let Some((key, wire_type)) = self.read_next_identifier(&mut cursor)? {
-            let line = self.process_field(&mut cursor, key, wire_type, type_name, depth, &mut keys_types)?;
-            if let Some(line) = line {
-                lines.push(line);
+        loop {
+            let field_start = cursor.position() as usize;
+            
+            // 部分传输层会在数据末尾补0，只在顶层消息中识别
+            if self.options.allow_zero_padding && depth == 0 && field_start < data.len()
+                && data[field_start..].iter().all(|&b| b == 0) {
+                let line = format!("({} bytes of zero padding)", data.len() - field_start);
+                lines.push(FieldLine { key: None, wire_type: 0, offsets: self.offsets(field_start, data.len()), text: line });
+                break;
+            }
+            
+            let Some((key, wire_type)) = self.read_next_identifier(&mut cursor)? else {
+                break;
+            };
+            if let Some(wire_type) = WireType::from_u8(wire_type) {
+                if depth > self.stats.max_depth || self.stats.max_depth_path.is_empty() {
+                    self.stats.max_depth_path = self.current_path.iter().map(|&(number, _)| number).chain([key]).collect();
+                }
+                self.stats.record(wire_type, depth);
+            }
+            
+            // MessageSet中的每一项都是field 1的group，里面是type_id和消息本身
+            if key == 1 && wire_type == 3 && !self.options.strict
+                && let Some(line) = self.try_parse_message_set_item(&mut cursor, depth) {
+                if let Some(guess) = guess.as_deref_mut() {
+                    guess.observe(&data[field_start..], wire_type, &[]);
+                }
+                let offsets = self.offsets(field_start, cursor.position() as usize);
+                lines.push(FieldLine { key: Some(key), wire_type, offsets, text: line });
+                continue;
+            }
+            
+            // group中的字段按嵌套消息输出
+            let value_start = cursor.position() as usize;
+            let value_data = if wire_type == 3 || wire_type == 4 {
+                self.current_path.push((key, 0));
+                let line = self.parse_group(&mut cursor, key, wire_type, type_name, depth);
+                self.current_path.pop();
+                let offsets = self.offsets(field_start, cursor.position() as usize);
+                lines.push(FieldLine { key: Some(key), wire_type, offsets, text: line? });
+                Vec::new()
+            } else {
+                self.read_field_value(&mut cursor, wire_type, depth)?
+            };
+            let prefix_len = cursor.position() as usize - value_start - value_data.len();
+            
+            if let Some(guess) = guess.as_deref_mut() {
+                guess.observe(&data[field_start..], wire_type, &value_data);
+                if guess.is_rejected() {
+                    return Err(core::Error::InvalidVarint);
+                }
+            }
+            
+            if wire_type == 3 || wire_type == 4 {
+                continue;
+            }
+            
+            // 合并后的repeated字段在第一次出现的位置输出全部的值，之后的出现不再输出
+            if let Some((first_start, values)) = merged.get(&key) {
+                if field_start == *first_start {
+                    let handler_wire_type = self.types[type_name][&key].0.split_whitespace().next()
+                        .map_or(wire_type, |primary| self.match_native_type(primary).wire_type() as u8);
+                    let (actual_type, field_name) = self.resolve_field_type(type_name, key, handler_wire_type);
+                    let line = self.format_field_line(key, &actual_type, &field_name, format!("[{}]", values.join(", ")));
+                    let offsets = self.offsets(field_start, cursor.position() as usize);
+                    lines.push(FieldLine { key: Some(key), wire_type, offsets, text: line });
+                }
+                continue;
+            }
+            
+            // 检查线类型一致性
+            self.check_wire_type_consistency(type_name, key, wire_type, &mut keys_types);
+            
+            // 解析字段
+            self.current_path.push((key, next_chunk_index(&mut chunk_counts, key, wire_type)));
+            let line = self.with_base_offset(value_start + prefix_len, |parser| {
+                // 没有schema时，看起来像map的repeated字段按`key => value`显示
+                let entry = map_fields.get(&key).and_then(|map_type| parser.try_parse_declared_type(map_type, &value_data, depth));
+                match entry {
+                    Some(entry) => Ok(parser.format_field_line(key, "map", "", entry)),
+                    None => parser.parse_field_value(key, wire_type, type_name, &value_data, data, depth),
+                }
+            });
+            self.current_path.pop();
+            let mut line = line?;
+            
+            if wire_type == 2 && self.options.show_wire_details {
+                let details = format!("[len={} ({}-byte prefix)]", value_data.len(), prefix_len);
+                line = line.replacen(" = ", &format!(" {} = ", foreground(6, &details)), 1);
+            }
+            
+            // 非repeated的标量字段出现多次时，只有最后一次生效
+            if let Some(&(count, last_start)) = occurrences.get(&key) && count > 1 {
+                if field_start == last_start {
+                    line = format!("{} {}", line, foreground(2, "(effective)"));
+                } else {
+                    line = format!("{} {}", line, foreground(1, "(overridden)"));
+                }
             }
+            let offsets = self.offsets(field_start, cursor.position() as usize);
+            lines.push(FieldLine { key: Some(key), wire_type, offsets, text: line });
         }
         
-        if lines.is_empty() {
-            lines.push("empty".to_string());
+        Ok(lines)
+    }
+    
+    /// schema中声明为非repeated标量的字段：field number -> (出现次数, 最后一次出现的位置)
+    ///
+    /// 按照protobuf的规则，这些字段重复出现时后面的值覆盖前面的值。
+    /// 类型带有`repeated`修饰词、消息类型以及没有schema的字段不计入
+    fn scalar_field_occurrences(&self, data: &[u8], type_name: &str) -> HashMap<u32, (usize, usize)> {
+        let mut occurrences = HashMap::new();
+        let Some(type_map) = self.types.get(type_name).filter(|type_map| !type_map.is_empty()) else {
+            return occurrences;
+        };
+        let is_scalar = |number: u32| type_map.get(&number).is_some_and(|(field_type, _)| {
+            let primary = field_type.split_whitespace().next().unwrap_or_default();
+            !field_type.split_whitespace().skip(1).any(|word| word == "repeated")
+                && self.native_types.contains_key(primary)
+                && !matches!(primary, "message" | "chunk" | "any")
+        });
+        
+        let mut cursor = Cursor::new(data);
+        loop {
+            let start = cursor.position() as usize;
+            let Ok(Some((key, wire_type))) = read_identifier(&mut cursor) else {
+                break;
+            };
+            if wire_type == 3 {
+                skip_group(&mut cursor, key);
+            } else if wire_type != 4 && read_bounded_value(&mut cursor, wire_type, 0).is_err() {
+                break;
+            }
+            if is_scalar(key) {
+                let entry = occurrences.entry(key).or_insert((0, start));
+                *entry = (entry.0 + 1, start);
+            }
+        }
+        occurrences
+    }
+    
+    /// 没有schema的chunk字段多次出现，并且每一项都恰好是`{1: key, 2: value}`时猜测为map：
+    /// field number -> 按线类型推断的`map<K, V>`类型
+    ///
+    /// 各项的key和value的线类型必须一致，chunk的key必须是UTF-8，key不能重复
+    fn guess_map_fields(&self, data: &[u8], type_name: &str) -> HashMap<u32, String> {
+        let mut entries: HashMap<u32, Vec<Vec<u8>>> = HashMap::new();
+        let mut rejected = Vec::new();
+        if self.options.no_nested || self.options.strict {
+            return HashMap::new();
+        }
+        
+        let mut cursor = Cursor::new(data);
+        while let Ok(Some((key, wire_type))) = read_identifier(&mut cursor) {
+            if matches!(wire_type, 3 | 4) {
+                continue;
+            }
+            let Ok(value) = read_bounded_value(&mut cursor, wire_type, 0) else {
+                break;
+            };
+            if wire_type != 2 || self.get_field_type_info(type_name, key).0 != "message" {
+                rejected.push(key);
+            } else {
+                entries.entry(key).or_default().push(value);
+            }
         }
         
-        Ok(format!("{}:\n{}", type_name, indent(&lines.join("\n"), None)))
+        let item_type = |wire_type| match wire_type {
+            0 => Some("varint"),
+            1 => Some("64bit"),
+            5 => Some("32bit"),
+            _ => None,
+        };
+        entries.into_iter()
+            .filter(|(key, values)| values.len() > 1 && !rejected.contains(key))
+            .filter_map(|(key, values)| {
+                let pairs = values.iter().map(|value| map_entry_fields(value)).collect::<Option<Vec<_>>>()?;
+                let (key_wire_type, value_wire_type) = (pairs[0].0.0, pairs[0].1.0);
+                if pairs.iter().any(|((k, _), (v, _))| *k != key_wire_type || *v != value_wire_type) {
+                    return None;
+                }
+                if key_wire_type == 2 && pairs.iter().any(|((_, k), _)| std::str::from_utf8(k).is_err()) {
+                    return None;
+                }
+                let mut keys: Vec<&[u8]> = pairs.iter().map(|((_, k), _)| k.as_slice()).collect();
+                keys.sort();
+                keys.dedup();
+                if keys.len() != pairs.len() {
+                    return None;
+                }
+                let key_type = if key_wire_type == 2 { "string" } else { item_type(key_wire_type)? };
+                let value_type = if value_wire_type == 2 { "chunk" } else { item_type(value_wire_type)? };
+                Some((key, format!("map<{}, {}>", key_type, value_type)))
+            })
+            .collect()
     }
     
-    fn read_next_identifier(&self, cursor: &mut Cursor<&[u8]>) -> Result<Option<(u32, u8)>, core::Error> {
-        match read_identifier(cursor) {
-            Ok(Some((key, wire_type))) => Ok(Some((key, wire_type))),
-            Ok(None) => Ok(None),
-            Err(e) => Err(e),
+    /// schema中声明为`repeated`的数值字段可以同时以packed和非packed的形式出现，
+    /// 至少有一次packed时按出现顺序合并为一个列表：field number -> (第一次出现的位置, 各个值)
+    ///
+    /// 任何一次出现无法按类型解码时不合并该字段，仍然逐个显示
+    fn merge_repeated_fields(&self, data: &[u8], type_name: &str) -> HashMap<u32, (usize, Vec<String>)> {
+        let mut merged: HashMap<u32, (usize, Vec<String>, bool)> = HashMap::new();
+        let mut failed = Vec::new();
+        let Some(type_map) = self.types.get(type_name).filter(|type_map| !type_map.is_empty()) else {
+            return HashMap::new();
+        };
+        
+        let mut cursor = Cursor::new(data);
+        loop {
+            let start = cursor.position() as usize;
+            let Ok(Some((key, wire_type))) = read_identifier(&mut cursor) else {
+                break;
+            };
+            if wire_type == 3 {
+                skip_group(&mut cursor, key);
+            }
+            if matches!(wire_type, 3 | 4) {
+                continue;
+            }
+            let Ok(value) = read_bounded_value(&mut cursor, wire_type, 0) else {
+                break;
+            };
+            
+            let Some((field_type, _)) = type_map.get(&key)
+                .filter(|(field_type, _)| has_modifier(field_type, "repeated")) else {
+                continue;
+            };
+            let handler = self.match_native_type(field_type);
+            // 同时接受chunk的类型（例如ISO字母代码）不会是packed
+            if !matches!(handler.wire_type(), WireType::Varint | WireType::Bit32 | WireType::Bit64)
+                || handler.accepts_wire_type(WireType::Chunk) {
+                continue;
+            }
+            
+            // 把packed的chunk拆成单个元素的原始字节
+            let elements = if wire_type == handler.wire_type() as u8 {
+                Some(vec![value])
+            } else if wire_type == 2 {
+                split_packed(&value, handler.wire_type())
+            } else {
+                None
+            };
+            let values: Option<Vec<String>> = elements.and_then(|elements| elements.iter()
+                .map(|element| self.parse_value_with_type(field_type, handler.wire_type() as u8, element).ok())
+                .collect());
+            
+            let entry = merged.entry(key).or_insert((start, Vec::new(), false));
+            entry.2 |= wire_type == 2;
+            match values {
+                Some(values) => entry.1.extend(values),
+                None => failed.push(key),
+            }
+        }
+        
+        merged.into_iter()
+            .filter(|(key, (_, _, has_packed))| *has_packed && !failed.contains(key))
+            .map(|(key, (start, values, _))| (key, (start, values)))
+            .collect()
+    }
+    
+    /// 取出所有chunk字段的原始内容及其路径（从外到内的field number），
+    /// 用于保存嵌入的图片等二进制数据
+    ///
+    /// 被识别为嵌套消息的chunk会继续展开，只有开启`extract_nested_messages`时才会返回它本身
+    pub fn extract_chunks(&self, data: &[u8], type_name: &str) -> Result<Vec<ExtractedChunk>, core::Error> {
+        let message = decode_message_with_max_depth(data, self.max_depth)?;
+        let mut chunks = Vec::new();
+        self.collect_chunks(&message, type_name, &mut Vec::new(), &mut chunks);
+        Ok(chunks)
+    }
+    
+    fn collect_chunks(&self, message: &Message, type_name: &str, path: &mut Vec<u32>, chunks: &mut Vec<ExtractedChunk>) {
+        for field in message.fields.iter().filter(|f| f.wire_type == WireType::Chunk) {
+            path.push(field.number);
+            
+            // schema中声明为其他类型的字段不展开，关闭猜测时同样不展开；严格模式下只展开声明为消息的字段
+            let (field_type, _, _) = self.get_field_type_info(type_name, field.number);
+            let declared = self.types.get(type_name).is_some_and(|fields| fields.contains_key(&field.number));
+            let nested = field.message.as_ref().filter(|_| {
+                !self.options.no_nested && (!self.options.strict || declared)
+                    && (field_type == "message" || self.types.contains_key(&*field_type))
+            });
+            match nested {
+                Some(nested) => {
+                    if self.options.extract_nested_messages {
+                        chunks.push((path.clone(), field.raw.clone()));
+                    }
+                    self.collect_chunks(nested, &field_type, path, chunks);
+                }
+                None => chunks.push((path.clone(), field.raw.clone())),
+            }
+            
+            path.pop();
+        }
+    }
+    
+    /// 设置`parse_message`和`render_fields`输出前对每个字段调用的回调，可以用来改写、脱敏或标注字段
+    ///
+    /// 回调按后序遍历调用：嵌套消息中的字段先于包含它的chunk字段。
+    /// `parse_message`把修改后的字段树重新编码后再解析，输出中的偏移量对应重新编码的数据
+    pub fn set_field_postprocessor(&mut self, processor: FieldPostprocessor) {
+        self.field_postprocessor = Some(processor);
+    }
+    
+    /// 渲染已经解析好的字段树，不需要重新读取原始数据
+    pub fn render_fields(&mut self, fields: &[Field], type_name: &str, format: OutputFormat) -> Result<String, core::Error> {
+        let mut message = Message { fields: fields.to_vec() };
+        self.warnings.clear();
+        self.wire_type_mismatches.clear();
+        self.trace.clear();
+        if let Some(processor) = &self.field_postprocessor {
+            message.visit_fields_mut(&mut |field| processor(field));
+        }
+        
+        match format {
+            OutputFormat::Text => self.render_fields_with_depth(&message.fields, type_name, 0),
+            OutputFormat::RustLiteral => Ok(message.to_rust_literal()),
+            OutputFormat::Tree => {
+                let mut lines = vec![format!("{}:", type_name)];
+                self.render_tree(&message.fields, type_name, "", 0, &mut lines)?;
+                Ok(lines.join("\n"))
+            }
+            OutputFormat::Html => {
+                let mut body = Vec::new();
+                self.render_html(&message.fields, type_name, 0, &mut body)?;
+                Ok(format!("{}\n{}\n{}", HTML_HEADER.replace("{title}", &escape_html(type_name)), body.join("\n"), HTML_FOOTER))
+            }
+            OutputFormat::Json => Ok(self.render_json(&message.fields, type_name, 0)),
+            OutputFormat::Protoscope => {
+                let mut lines = Vec::new();
+                self.render_protoscope(&message.fields, type_name, 0, &mut lines);
+                Ok(lines.join("\n"))
+            }
+        }
+    }
+    
+    fn render_fields_with_depth(&mut self, fields: &[Field], type_name: &str, depth: usize) -> Result<String, core::Error> {
+        if depth > self.max_depth {
+            return Ok("recursion depth exceeded".to_string());
+        }
+        
+        let mut lines = Vec::new();
+        for field in fields {
+            let wire_type = field.wire_type as u8;
+            if matches!(field.wire_type, WireType::StartGroup | WireType::EndGroup) {
+                let line = self.handle_group_type(field.number, wire_type)?;
+                lines.extend(line.map(|text| FieldLine { key: None, wire_type, offsets: String::new(), text }));
+                continue;
+            }
+            
+            let (actual_type, field_name) = self.resolve_field_type(type_name, field.number, wire_type);
+            let value = match &field.message {
+                Some(message) => {
+                    let nested_type = self.declared_message_type(&actual_type).unwrap_or("message");
+                    self.render_fields_with_depth(&message.fields, nested_type, depth + 1)?
+                }
+                None => self.parse_value_in_message(&actual_type, wire_type, &field.raw, &|n| varint_in_fields(fields, n))?,
+            };
+            let text = self.format_field_line(field.number, &actual_type, &field_name, value);
+            lines.push(FieldLine { key: Some(field.number), wire_type, offsets: String::new(), text });
         }
+        
+        Ok(self.format_message(type_name, lines))
     }
     
-    fn process_field(
+    /// 用连接线代替缩进输出字段树，`prefix`是上层已经画好的竖线
+    fn render_tree(
         &mut self,
-        cursor: &mut Cursor<&[u8]>,
-        key: u32,
-        wire_type: u8,
+        fields: &[Field],
         type_name: &str,
+        prefix: &str,
         depth: usize,
-        keys_types: &mut HashMap<u32, u8>,
-    ) -> Result<Option<String>, core::Error> {
-        // 处理group类型
-        if wire_type == 3 || wire_type == 4 {
-            return self.handle_group_type(key, wire_type);
+        lines: &mut Vec<String>,
+    ) -> Result<(), core::Error> {
+        let (branch, last_branch, pipe) = if self.options.ascii_only || cfg!(feature = "no-color") {
+            ("+-- ", "\\-- ", "|   ")
+        } else {
+            ("├── ", "└── ", "│   ")
+        };
+        
+        if depth > self.max_depth {
+            lines.push(format!("{}{}recursion depth exceeded", prefix, last_branch));
+            return Ok(());
+        }
+        if fields.is_empty() {
+            lines.push(format!("{}{}empty", prefix, last_branch));
+            return Ok(());
+        }
+        
+        for (index, field) in fields.iter().enumerate() {
+            let is_last = index + 1 == fields.len();
+            let head = format!("{}{}", prefix, if is_last { last_branch } else { branch });
+            let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { pipe });
+            
+            let wire_type = field.wire_type as u8;
+            let (line, nested) = if matches!(field.wire_type, WireType::StartGroup | WireType::EndGroup) {
+                (self.handle_group_type(field.number, wire_type)?.unwrap_or_default(), None)
+            } else {
+                let (actual_type, field_name) = self.resolve_field_type(type_name, field.number, wire_type);
+                let value = match &field.message {
+                    Some(_) => "message".to_string(),
+                    None => self.parse_value_in_message(&actual_type, wire_type, &field.raw, &|n| varint_in_fields(fields, n))?,
+                };
+                let nested_type = self.declared_message_type(&actual_type).unwrap_or("message").to_string();
+                (self.format_field_line(field.number, &actual_type, &field_name, value), field.message.as_ref().map(|m| (m, nested_type)))
+            };
+            
+            // 多行的值（例如hex dump）后续行接在子节点的前缀后面
+            let mut value_lines = line.lines();
+            lines.push(format!("{}{}", head, value_lines.next().unwrap_or_default()));
+            lines.extend(value_lines.map(|l| format!("{}{}", child_prefix, l)));
+            
+            if let Some((message, nested_type)) = nested {
+                self.render_tree(&message.fields, &nested_type, &child_prefix, depth + 1, lines)?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// 输出HTML，嵌套消息放在`<details>`中，所有文本都经过转义
+    fn render_html(&mut self, fields: &[Field], type_name: &str, depth: usize, out: &mut Vec<String>) -> Result<(), core::Error> {
+        if depth > self.max_depth {
+            out.push("<div class=\"message\">recursion depth exceeded</div>".to_string());
+            return Ok(());
+        }
+        
+        out.push("<div class=\"message\">".to_string());
+        if fields.is_empty() {
+            out.push("<div class=\"field\">empty</div>".to_string());
         }
         
-        // 读取值数据
-        let value_data = self.read_field_value(cursor, wire_type)?;
+        for field in fields {
+            let wire_type = field.wire_type as u8;
+            if matches!(field.wire_type, WireType::StartGroup | WireType::EndGroup) {
+                let line = self.handle_group_type(field.number, wire_type)?.unwrap_or_default();
+                out.push(format!("<div class=\"field group\">{}</div>", escape_html(&strip_ansi(&line))));
+                continue;
+            }
+            
+            let (actual_type, field_name) = self.resolve_field_type(type_name, field.number, wire_type);
+            let label = format!("<span class=\"number\">{}</span> <span class=\"name\">{}</span> = ",
+                field.number,
+                escape_html(&if field_name.is_empty() { format!("<{}>", &*actual_type) } else { field_name.to_string() }));
+            
+            match &field.message {
+                Some(message) => {
+                    out.push(format!("<details open><summary>{}<span class=\"value\">message</span></summary>", label));
+                    let nested_type = self.declared_message_type(&actual_type).unwrap_or("message");
+                    self.render_html(&message.fields, nested_type, depth + 1, out)?;
+                    out.push("</details>".to_string());
+                }
+                None => {
+                    let value = self.parse_value_in_message(&actual_type, wire_type, &field.raw, &|n| varint_in_fields(fields, n))?;
+                    out.push(format!("<div class=\"field\">{}<span class=\"value wt-{}\">{}</span></div>",
+                        label, self.get_wire_type_name(wire_type), escape_html(&strip_ansi(&value))));
+                }
+            }
+        }
         
-        // 检查线类型一致性
-        self.check_wire_type_consistency(key, wire_type, keys_types);
+        out.push("</div>".to_string());
+        Ok(())
+    }
+    
+    /// 输出JSON数组，每个字段为`{"field", "wire_type", "type", "value"}`，schema中有字段名时加上`name`。
+    /// 嵌套消息的`value`是其中字段组成的数组，超过`max_depth`的嵌套消息按bytes输出
+    fn render_json(&self, fields: &[Field], type_name: &str, depth: usize) -> String {
+        let objects: Vec<String> = fields.iter().map(|field| {
+            let (field_type, field_name, _) = self.get_field_type_info(type_name, field.number);
+            let full_type = (field_type != "message").then_some(&*field_type);
+            let declared = full_type.map(|t| t.split_whitespace().next().unwrap_or_default());
+            let (json_type, value) = match &field.message {
+                Some(message) if depth < self.max_depth => {
+                    ("message", self.render_json(&message.fields, declared.unwrap_or("message"), depth + 1))
+                }
+                _ => json_value(field, full_type),
+            };
+            
+            let name = if field_name.is_empty() { String::new() } else { format!(", \"name\": {}", escape_json(field_name)) };
+            format!("{{\"field\": {}{}, \"wire_type\": {}, \"type\": {}, \"value\": {}}}",
+                field.number, name, field.wire_type as u8, escape_json(declared.unwrap_or(json_type)), value)
+        }).collect();
+        format!("[{}]", objects.join(", "))
+    }
+    
+    /// 输出protoscope语法：嵌套消息和chunk写在`{}`中，group写为`N: !{ ... }`，
+    /// 有符号的类型使用`z`后缀、固定长度的类型使用`i32`/`i64`后缀，字段名写在`#`注释中
+    /// 嵌套消息的行由调用者统一缩进，`depth`只用于限制层数
+    fn render_protoscope(&self, fields: &[Field], type_name: &str, depth: usize, lines: &mut Vec<String>) {
+        let matched = matched_groups(fields);
+        let mut open_groups = 0;
+        for (index, field) in fields.iter().enumerate() {
+            let (field_type, field_name, _) = self.get_field_type_info(type_name, field.number);
+            let declared = (field_type != "message").then_some(&*field_type);
+            let comment = if field_name.is_empty() { String::new() } else { format!("  # {}", field_name) };
+            let pad = "  ".repeat(open_groups);
+            
+            match field.wire_type {
+                WireType::StartGroup if matched[index] => {
+                    lines.push(format!("{}{}: !{{{}", pad, field.number, comment));
+                    open_groups += 1;
+                }
+                WireType::EndGroup if matched[index] => {
+                    open_groups -= 1;
+                    lines.push(format!("{}}}", &pad[2..]));
+                }
+                WireType::StartGroup => lines.push(format!("{}{}:SGROUP{}", pad, field.number, comment)),
+                WireType::EndGroup => lines.push(format!("{}{}:EGROUP{}", pad, field.number, comment)),
+                WireType::Chunk if field.message.is_some() && depth < self.max_depth => {
+                    let message = field.message.as_ref().unwrap();
+                    let nested_type = declared.and_then(|t| t.split_whitespace().next()).unwrap_or("message");
+                    lines.push(format!("{}{}: {{{}", pad, field.number, comment));
+                    let mut nested = Vec::new();
+                    self.render_protoscope(&message.fields, nested_type, depth + 1, &mut nested);
+                    let inner_pad = format!("{}  ", pad);
+                    lines.extend(nested.iter().map(|line| format!("{}{}", inner_pad, line)));
+                    lines.push(format!("{}}}", pad));
+                }
+                _ => lines.push(format!("{}{}: {}{}", pad, field.number, protoscope_value(field, declared), comment)),
+            }
+        }
+    }
+    
+    /// 字段的显示类型和名称，同时检查schema中的类型是否与线类型匹配；
+    /// 废弃的字段会在名称后标注`(deprecated)`并记录一条警告
+    ///
+    /// 类型和名称直接共享schema中的字符串，只有废弃的字段需要改写而分配
+    fn resolve_field_type(&mut self, type_name: &str, number: u32, wire_type: u8) -> (SchemaStr, SchemaStr) {
+        let Some((field_type, field_name)) = self.types.get(type_name).and_then(|type_map| type_map.get(&number)) else {
+            return (SchemaStr::Static(self.get_wire_type_name(wire_type)), SchemaStr::Static(""));
+        };
+        if &**field_type == "message" {
+            return (SchemaStr::Static(self.get_wire_type_name(wire_type)), SchemaStr::Shared(field_name.clone()));
+        }
+        let (field_type, field_name) = (field_type.clone(), field_name.clone());
+        self.check_handler_wire_type_match(type_name, number, &field_type, wire_type);
         
-        // 解析字段
-        let parsed_line = self.parse_field_value(key, wire_type, type_name, &value_data, depth)?;
+        let Some(actual_type) = strip_deprecated(&field_type) else {
+            return (SchemaStr::Shared(field_type), SchemaStr::Shared(field_name));
+        };
+        let display_name = if field_name.is_empty() { format!("<{}>", actual_type) } else { field_name.to_string() };
+        // 重复字段只警告一次
+        let warning = format!("deprecated field {} ({}) found in {}", display_name, number, type_name);
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+        let field_name = format!("{} {}", display_name, foreground(3, "(deprecated)"));
+        (SchemaStr::Owned(actual_type), SchemaStr::Owned(field_name))
+    }
+    
+    /// schema中的类型是已知的消息类型时返回消息名，去掉`repeated`等修饰词
+    fn declared_message_type<'a>(&self, field_type: &'a str) -> Option<&'a str> {
+        field_type.split_whitespace().next()
+            .filter(|t| self.types.contains_key(*t) && !self.native_types.contains_key(*t))
+    }
+    
+    /// 同一字段连续出现多次时合并为`N name (M items) = [...]`，字段名取第一项的，
+    /// 每一项只保留值；字段名与第一项不同（例如线类型不一致）的项保留`name = `，
+    /// 线类型与第一项不同的项标注`(wire type mismatch)`
+    fn format_message(&self, type_name: &str, lines: Vec<FieldLine>) -> String {
+        let mut output = Vec::new();
+        let mut lines = lines.into_iter().peekable();
+        while let Some(line) = lines.next() {
+            let mut run = vec![line];
+            while let Some(next) = lines.next_if(|next| next.key.is_some() && next.key == run[0].key) {
+                run.push(next);
+            }
+            let (Some(key), true) = (run[0].key, run.len() > 1) else {
+                output.extend(run.into_iter().map(|line| format!("{}{}", line.offsets, line.text)));
+                continue;
+            };
+            
+            // 每一行为`{field number} {name} = {value}`
+            let label = foreground_bold(4, &key.to_string());
+            let split = |line: &FieldLine| -> (String, String) {
+                let text = line.text.strip_prefix(&format!("{} ", label)).unwrap_or(&line.text);
+                match text.split_once(" = ") {
+                    Some((name, value)) => (name.to_string(), value.to_string()),
+                    None => (String::new(), text.to_string()),
+                }
+            };
+            let (name, _) = split(&run[0]);
+            let items: Vec<String> = run.iter().map(|line| {
+                let (item_name, value) = split(line);
+                let value = if item_name == name { value } else { format!("{} = {}", item_name, value) };
+                let mismatch = if line.wire_type != run[0].wire_type {
+                    format!(" {}", foreground(1, "(wire type mismatch)"))
+                } else {
+                    String::new()
+                };
+                format!("{}{}{}", line.offsets, value, mismatch)
+            }).collect();
+            output.push(format!("{} {} ({} items) = [\n{}\n]", label, name, run.len(), indent(&items.join("\n"), None)));
+        }
+        if output.is_empty() {
+            output.push("empty".to_string());
+        }
         
-        Ok(Some(parsed_line))
+        format!("{}:\n{}", type_name, indent(&output.join("\n"), None))
+    }
+    
+    fn read_next_identifier<R: Read>(&self, reader: &mut R) -> Result<Option<(u32, u8)>, core::Error> {
+        match read_identifier(reader) {
+            Ok(Some((0, wire_type))) if self.options.allow_field_zero => Ok(Some((0, wire_type))),
+            Ok(Some((key, _))) if !is_valid_field_number(key) => Err(core::Error::InvalidFieldNumber),
+            Ok(Some((key, wire_type))) => Ok(Some((key, wire_type))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
     
     fn handle_group_type(&self, key: u32, wire_type: u8) -> Result<Option<String>, core::Error> {
@@ -131,119 +1224,1859 @@ impl Parser {
         Ok(Some(line))
     }
     
-    fn read_field_value(&self, cursor: &mut Cursor<&[u8]>, wire_type: u8) -> Result<Vec<u8>, core::Error> {
-        match read_value(cursor, wire_type) {
-            Ok(Some(data)) => Ok(data),
-            Ok(None) => Err(core::Error::Eof),
-            Err(e) => Err(e),
+    /// 解析从StartGroup到同一field number的EndGroup之间的字段，schema中声明了消息类型时按该类型解析
+    ///
+    /// 没有对应的EndGroup，或者EndGroup没有对应的StartGroup时，只输出这个标记并给出警告，
+    /// 之后的字段仍然在同一层解析
+    fn parse_group(&mut self, cursor: &mut Cursor<&[u8]>, key: u32, wire_type: u8, type_name: &str, depth: usize) -> Result<String, core::Error> {
+        let data = *cursor.get_ref();
+        let start = cursor.position() as usize;
+        let Some((body_len, group_len)) = (wire_type == 3).then(|| group_len(&data[start..], key)).flatten() else {
+            let (marker, problem) = if wire_type == 3 {
+                ("startgroup", "unterminated group")
+            } else {
+                ("endgroup", "end of group without start")
+            };
+            self.warnings.push(format!("{} {} in {}", problem, key, type_name));
+            return Ok(format!("{} <{}> = {}", foreground_bold(4, &key.to_string()), marker, foreground(1, &format!("({})", problem))));
+        };
+        cursor.set_position((start + group_len) as u64);
+        
+        let (field_type, field_name, _) = self.get_field_type_info(type_name, key);
+        let declared = self.declared_message_type(&field_type).map(str::to_string);
+        let field_name = field_name.to_string();
+        let value = if depth + 1 > self.max_depth {
+            "recursion depth exceeded".to_string()
+        } else {
+            let nested_type = declared.as_deref().unwrap_or("message");
+            let lines = self.with_base_offset(start, |parser| {
+                parser.parse_fields(&data[start..start + body_len], nested_type, depth + 1, None)
+            })?;
+            self.format_message(declared.as_deref().unwrap_or("group"), lines)
+        };
+        Ok(self.format_field_line(key, "group", &field_name, value))
+    }
+    
+    /// 识别`1 <startgroup> {2: type_id, 3: message} 1 <endgroup>`，失败时不移动cursor
+    fn try_parse_message_set_item(&mut self, cursor: &mut Cursor<&[u8]>, depth: usize) -> Option<String> {
+        let start = cursor.position();
+        let saved_stats = self.stats.clone();
+        let warning_count = self.warnings.len();
+        let saved_mismatches = self.wire_type_mismatches.clone();
+        let item = (|| {
+            if read_identifier(cursor).ok()?? != (2, 0) {
+                return None;
+            }
+            let type_id = read_varint(cursor).ok()??;
+            if read_identifier(cursor).ok()?? != (3, 2) {
+                return None;
+            }
+            let message = read_value(cursor, 2).ok()??;
+            let message_start = cursor.position() as usize - message.len();
+            if read_identifier(cursor).ok()?? != (1, 4) {
+                return None;
+            }
+            Some((type_id, message, message_start))
+        })();
+        
+        let line = item.and_then(|(type_id, message, message_start)| {
+            if depth + 1 > self.max_depth {
+                return None;
+            }
+            let lines = self.with_base_offset(message_start, |parser| parser.parse_fields(&message, "message", depth + 1, None)).ok()?;
+            let value = self.format_message(&format!("MessageSet[{}]", type_id), lines);
+            Some(format!("{} <group> = {}", foreground_bold(4, "1"), value))
+        });
+        if line.is_none() {
+            cursor.set_position(start);
+            self.stats = saved_stats;
+            self.warnings.truncate(warning_count);
+            self.wire_type_mismatches = saved_mismatches;
         }
+        line
+    }
+    
+    fn read_field_value(&self, cursor: &mut Cursor<&[u8]>, wire_type: u8, depth: usize) -> Result<Vec<u8>, core::Error> {
+        read_bounded_value(cursor, wire_type, depth)
     }
     
-    fn check_wire_type_consistency(&mut self, key: u32, wire_type: u8, keys_types: &mut HashMap<u32, u8>) {
+    fn check_wire_type_consistency(&mut self, type_name: &str, key: u32, wire_type: u8, keys_types: &mut HashMap<u32, u8>) {
         if let Some(&existing_type) = keys_types.get(&key)
             && existing_type != wire_type {
-                self.wire_types_not_matching = true;
+                self.record_wire_type_mismatch(type_name, key);
             }
         keys_types.insert(key, wire_type);
     }
     
+    /// 记录线类型不一致的字段，同一消息类型中的同一字段只警告一次
+    fn record_wire_type_mismatch(&mut self, type_name: &str, key: u32) {
+        if self.wire_type_mismatches.insert((type_name.to_string(), key)) {
+            self.warnings.push(format!("wire type mismatch for field {} in {}", key, type_name));
+        }
+    }
+    
     fn parse_field_value(
         &mut self,
         key: u32,
         wire_type: u8,
         type_name: &str,
         value_data: &[u8],
+        message_data: &[u8],
         depth: usize,
     ) -> Result<String, core::Error> {
-        let (field_type, field_name) = self.get_field_type_info(type_name, key);
-        let actual_type = if field_type == "message" {
-            self.get_wire_type_name(wire_type)
-        } else {
-            &field_type
-        };
-        
-        // 检查类型处理器的线类型匹配
-        self.check_handler_wire_type_match(actual_type, wire_type, &field_type);
+        let (actual_type, field_name) = self.resolve_field_type(type_name, key, wire_type);
+        let actual_type = &*actual_type;
+        let wire_type_name = self.get_wire_type_name(wire_type);
+        self.trace_decision(|| format!("<{}> ({} bytes), type {}", wire_type_name, value_data.len(), actual_type));
         
         // 解析值
-        let mut parsed_value = self.parse_value_with_type(actual_type, value_data)?;
+        let mut parsed_value = self.parse_value_in_message(actual_type, wire_type, value_data, &|n| varint_in_message(message_data, n))?;
+        
+        // google.protobuf.Any，repeated字段的每个元素分别解开
+        if actual_type == "any"
+            && let Some(any_msg) = self.try_parse_any(value_data, depth) {
+            parsed_value = any_msg;
+        }
+        
+        // schema中声明的消息类型和map
+        if wire_type == 2
+            && let Some(declared) = self.try_parse_declared_type(actual_type, value_data, depth) {
+            parsed_value = declared;
+        }
         
-        // 尝试解析嵌套消息
-        if actual_type == "chunk" && self.should_try_nested_parse(value_data)
-            && let Ok(nested_msg) = self.try_parse_nested_message(value_data, depth) {
+        // 尝试解析嵌套消息，超过显示深度时只做猜测，不解析内容
+        if actual_type == "chunk" && !self.should_try_nested_parse(value_data) {
+            self.trace_decision(|| "nested parse skipped".to_string());
+        } else if actual_type == "chunk" && !self.options.allows_nested_message(value_data) {
+            self.trace_decision(|| "nested parse skipped by interpretation order".to_string());
+        } else if actual_type == "chunk" {
+            if self.options.max_display_depth.is_some_and(|max| depth + 1 > max) {
+                let is_message = guess_is_message(value_data) == Ok(true);
+                self.trace_decision(|| format!("beyond display depth, guessed message: {}", is_message));
+                if is_message {
+                    parsed_value = format!("message ({} bytes, collapsed: expand path {})", value_data.len(), format_expand_path(&self.current_path));
+                }
+            } else if let Ok(nested_msg) = self.try_parse_nested_message(value_data, depth) {
                 parsed_value = nested_msg;
+            } else if self.detect_framed_messages
+                && let Ok(framed_msg) = self.try_parse_framed_message(value_data, depth) {
+                self.trace_decision(|| "framed message accepted".to_string());
+                parsed_value = framed_msg;
+            } else if self.options.lenient
+                && let Some(truncated_msg) = self.try_parse_truncated_message(value_data, depth) {
+                self.trace_decision(|| "truncated nested message accepted".to_string());
+                parsed_value = truncated_msg;
             }
+        }
         
-        let display_name = if field_name.is_empty() {
-            format!("<{}>", actual_type)
-        } else {
-            field_name
-        };
-        
-        Ok(format!("{} {} = {}", foreground_bold(4, &key.to_string()), display_name, parsed_value))
+        Ok(self.format_field_line(key, actual_type, &field_name, parsed_value))
     }
     
-    fn check_handler_wire_type_match(&mut self, actual_type: &str, wire_type: u8, field_type: &str) {
+    fn format_field_line(&self, key: u32, actual_type: &str, field_name: &str, value: String) -> String {
+        if field_name.is_empty() {
+            format!("{} <{}> = {}", foreground_bold(4, &key.to_string()), actual_type, value)
+        } else {
+            format!("{} {} = {}", foreground_bold(4, &key.to_string()), field_name, value)
+        }
+    }
+    
+    /// schema中的类型与数据中的线类型不一致，通常说明schema猜错了
+    fn check_handler_wire_type_match(&mut self, type_name: &str, key: u32, actual_type: &str, wire_type: u8) {
         let wire_type_enum = match WireType::from_u8(wire_type) {
             Some(wt) => wt,
             None => return,
         };
         
-        let handler_wire_type = self.match_native_type(actual_type).wire_type();
-        
-        if handler_wire_type != wire_type_enum && field_type != "message" {
-            self.wire_types_not_matching = true;
+        if !self.match_native_type(actual_type).accepts_wire_type(wire_type_enum) {
+            self.record_wire_type_mismatch(type_name, key);
+        }
+    }
+    
+    /// `timestamp_delta`需要同一消息中基准字段的值，其他类型直接交给类型处理器
+    fn parse_value_in_message(
+        &self,
+        actual_type: &str,
+        wire_type: u8,
+        value_data: &[u8],
+        base_lookup: &dyn Fn(u32) -> Option<i64>,
+    ) -> Result<String, core::Error> {
+        let is_delta = actual_type.split_whitespace().next() == Some("timestamp_delta");
+        match TimestampDeltaHandler::base_field(actual_type) {
+            Some(base_field) if is_delta && wire_type == 0 => TimestampDeltaHandler
+                .format(value_data, base_lookup(base_field))
+                .map_err(|_| core::Error::InvalidVarint),
+            _ => self.parse_value_with_type(actual_type, wire_type, value_data),
         }
     }
     
-    fn parse_value_with_type(&self, actual_type: &str, value_data: &[u8]) -> Result<String, core::Error> {
-        self.match_native_type(actual_type)
-            .parse(value_data, actual_type)
+    fn parse_value_with_type(&self, actual_type: &str, wire_type: u8, value_data: &[u8]) -> Result<String, core::Error> {
+        // 放宽非最短编码时，重新编码后再交给类型处理器
+        if wire_type == 0 && self.options.non_canonical_varints != NonCanonicalVarints::Error
+            && let Ok((value, false)) = decode_varint_bytes(value_data) {
+            let parsed = self.parse_value_with_type(actual_type, wire_type, &encode_varint(value))?;
+            return Ok(match self.options.non_canonical_varints {
+                NonCanonicalVarints::Warn => format!("{} {}", parsed, foreground(1, "(non-canonical encoding)")),
+                _ => parsed,
+            });
+        }
+        
+        // repeated数值字段packed时的chunk
+        let handler = match self.match_native_type(actual_type) {
+            handler if wire_type == 2 && !handler.accepts_wire_type(WireType::Chunk) && has_modifier(actual_type, "repeated") => &PackedHandler,
+            handler => handler,
+        };
+        handler.parse_with_options(value_data, actual_type, &self.options)
             .map_err(|e| format!("ERROR: {:?}", e))
             .map_err(|_| core::Error::InvalidVarint)
     }
     
-    fn should_try_nested_parse(&self, value_data: &[u8]) -> bool {
-        value_data.len() > 2 && value_data.len() < 100
+    /// 解开`{1: type_url, 2: value}`，按type_url中的类型名查找schema，
+    /// 先用完整名称，再用最后一段；找不到时按无schema的消息解析
+    fn try_parse_any(&mut self, value_data: &[u8], depth: usize) -> Option<String> {
+        if depth + 1 > self.max_depth {
+            return None;
+        }
+        
+        let mut cursor = Cursor::new(value_data);
+        let mut type_url = None;
+        let mut value = Vec::new();
+        let mut value_start = 0;
+        while let Some((key, wire_type)) = read_identifier(&mut cursor).ok()? {
+            let data = read_value(&mut cursor, wire_type).ok()??;
+            match (key, wire_type) {
+                (1, 2) => type_url = Some(String::from_utf8(data).ok()?),
+                (2, 2) => {
+                    value_start = cursor.position() as usize - data.len();
+                    value = data;
+                }
+                _ => return None,
+            }
+        }
+        let type_url = type_url?;
+        
+        let full_name = type_url.rsplit('/').next().unwrap_or_default();
+        let short_name = full_name.rsplit('.').next().unwrap_or_default();
+        let known_type = [full_name, short_name].into_iter()
+            .find(|name| self.types.contains_key(*name))
+            .map(str::to_string);
+        let (label, type_name) = match known_type {
+            Some(type_name) => (format!("Any<{}>", type_url), type_name),
+            None => (format!("Any<{}> (unknown type)", type_url), "message".to_string()),
+        };
+        
+        let lines = self.with_base_offset(value_start, |parser| parser.parse_fields(&value, &type_name, depth + 1, None)).ok()?;
+        Some(self.format_message(&label, lines))
     }
     
-    fn try_parse_nested_message(&mut self, value_data: &[u8], depth: usize) -> Result<String, core::Error> {
-        // 使用增强的猜测逻辑来决定是否尝试解析为嵌套消息
-        match crate::guesser::guess_is_message(value_data) {
-            Ok(true) => {
-                // 猜测为消息，尝试解析
-                let msg = self.parse_message_with_depth(value_data, "message", depth + 1)?;
-                // 只有当解析结果看起来像有效的protobuf消息时才使用
-                if !msg.contains("ERROR") && !msg.contains("empty") && 
-                   msg.lines().count() <= 5 && msg.contains(":") {
-                    return Ok(msg);
+    /// 字段类型是schema中的消息类型时按该类型解析；`map<K, V>`的每一项显示为`key => value`，
+    /// 值同样可以是消息类型。不是这两种类型或者解析失败时返回`None`
+    fn try_parse_declared_type(&mut self, field_type: &str, value_data: &[u8], depth: usize) -> Option<String> {
+        if let Some((key_type, value_type)) = map_entry_types(field_type) {
+            let mut cursor = Cursor::new(value_data);
+            let (mut key, mut value) = (None, None);
+            while let Some((number, wire_type)) = read_identifier(&mut cursor).ok()? {
+                let data = read_bounded_value(&mut cursor, wire_type, depth + 1).ok()?;
+                let item_start = cursor.position() as usize - data.len();
+                let item_type = match number {
+                    1 => key_type,
+                    2 => value_type,
+                    _ => return None,
+                };
+                let item = self.with_base_offset(item_start, |parser| parser.parse_map_item(item_type, wire_type, &data, depth))?;
+                if number == 1 {
+                    key = Some(item);
+                } else {
+                    value = Some(item);
                 }
-                Err(core::Error::InvalidVarint)
             }
-            Ok(false) | Err(_) => {
-                // 猜测不是消息或猜测失败，不尝试嵌套解析
-                Err(core::Error::InvalidVarint)
+            // 缺少的key或value是对应类型的默认值
+            let missing = || "(default)".to_string();
+            return Some(format!("{} => {}", key.unwrap_or_else(missing), value.unwrap_or_else(missing)));
+        }
+        
+        // 类型后面可能带有`repeated`等修饰
+        let type_name = field_type.split_whitespace().next()?;
+        if !self.types.contains_key(type_name) || self.native_types.contains_key(type_name) {
+            return None;
+        }
+        let value = self.parse_message_with_depth(value_data, type_name, depth + 1).ok()?;
+        // Duration等类型在原始字段之前显示换算后的值
+        Some(match well_known::summarize(type_name, value_data) {
+            Some(summary) => format!("{} {}", summary, value),
+            None => value,
+        })
+    }
+    
+    fn parse_map_item(&mut self, item_type: &str, wire_type: u8, data: &[u8], depth: usize) -> Option<String> {
+        if wire_type == 2 {
+            if let Some(declared) = self.try_parse_declared_type(item_type, data, depth + 1) {
+                return Some(declared);
+            }
+            // 没有具体类型的消息按无schema的消息解析，猜测得到的chunk同样需要先通过猜测
+            let nested = match item_type {
+                "message" => self.parse_message_with_depth(data, "message", depth + 2).ok(),
+                "chunk" if self.should_try_nested_parse(data) => self.try_parse_nested_message(data, depth + 1).ok(),
+                _ => None,
+            };
+            if nested.is_some() {
+                return nested;
             }
         }
+        self.parse_value_with_type(item_type, wire_type, data).ok()
+    }
+    
+    /// 开启`options.trace`时记录一条判断，前面加上当前字段的路径
+    fn trace_decision(&mut self, message: impl FnOnce() -> String) {
+        if self.options.trace {
+            let path: Vec<String> = self.current_path.iter().map(|(number, _)| number.to_string()).collect();
+            self.trace.push(format!("{}: {}", path.join("."), message()));
+        }
     }
     
-    fn get_field_type_info(&self, type_name: &str, key: u32) -> (String, String) {
-        if let Some(type_map) = self.types.get(type_name)
-            && let Some((type_str, field_str)) = type_map.get(&key) {
-                return (type_str.clone(), field_str.clone());
+    fn should_try_nested_parse(&self, value_data: &[u8]) -> bool {
+        !self.options.no_nested && !self.options.strict && value_data.len() > 2 && value_data.len() < self.nested_parse_limit
+    }
+    
+    fn try_parse_nested_message(&mut self, value_data: &[u8], depth: usize) -> Result<String, core::Error> {
+        // 猜测失败时撤销这次尝试计入的统计和警告
+        let saved_stats = self.stats.clone();
+        let warning_count = self.warnings.len();
+        let saved_mismatches = self.wire_type_mismatches.clone();
+        let result = self.parse_nested_candidate(value_data, depth);
+        if result.is_err() {
+            self.stats = saved_stats;
+            self.warnings.truncate(warning_count);
+            self.wire_type_mismatches = saved_mismatches;
+        }
+        result
+    }
+    
+    fn parse_nested_candidate(&mut self, value_data: &[u8], depth: usize) -> Result<String, core::Error> {
+        if depth + 1 > self.max_depth {
+            return Err(core::Error::InvalidVarint);
+        }
+        
+        // 猜测和解析在同一次遍历中完成，两者的结论不会出现分歧
+        let mut guess = MessageGuess::for_data(value_data);
+        let lines = match self.parse_fields(value_data, "message", depth + 1, Some(&mut guess)) {
+            Ok(lines) => lines,
+            Err(e) => {
+                self.trace_decision(|| format!("nested message rejected: {:?} ({})", e, guess));
+                return Err(e);
             }
-        ("message".to_string(), String::new())
+        };
+        if !guess.is_message() {
+            self.trace_decision(|| format!("nested message rejected by guesser ({})", guess));
+            return Err(core::Error::InvalidVarint);
+        }
+        
+        // 至少要有一个字段；字段数量不设上限，由猜测决定
+        if lines.is_empty() {
+            self.trace_decision(|| format!("nested message rejected: no fields ({})", guess));
+            return Err(core::Error::InvalidVarint);
+        }
+        self.trace_decision(|| format!("nested message accepted ({})", guess));
+        Ok(self.format_message("message", lines))
+    }
+    
+    /// 宽松模式下，最后一个字段不完整的嵌套消息仍然显示能够解析的部分，剩余的字节标注为`<truncated>`
+    fn try_parse_truncated_message(&mut self, value_data: &[u8], depth: usize) -> Option<String> {
+        let consumed = self.complete_prefix_len(value_data);
+        if consumed == 0 || consumed == value_data.len() {
+            return None;
+        }
+        let nested_msg = self.try_parse_nested_message(&value_data[..consumed], depth).ok()?;
+        let remainder = format!("<truncated> ({} bytes)", value_data.len() - consumed);
+        Some(format!("{}\n{}", nested_msg, indent(&foreground(1, &remainder), None)))
+    }
+    
+    fn try_parse_framed_message(&mut self, value_data: &[u8], depth: usize) -> Result<String, core::Error> {
+        // 部分RPC框架会在bytes字段里再套一层长度前缀：[varint长度][message]
+        let mut cursor = Cursor::new(value_data);
+        let length = read_varint(&mut cursor)?.ok_or(core::Error::Eof)?;
+        let inner = &value_data[cursor.position() as usize..];
+        
+        // 长度前缀必须恰好覆盖剩余的全部数据，避免误判
+        if inner.is_empty() || length != inner.len() as u64 {
+            return Err(core::Error::InvalidVarint);
+        }
+        
+        let inner_start = cursor.position() as usize;
+        let msg = self.with_base_offset(inner_start, |parser| parser.try_parse_nested_message(inner, depth))?;
+        Ok(format!("framed {}", msg))
+    }
+    
+    /// schema中的类型、字段名以及是否废弃，只在类型带有`deprecated`修饰词时才需要复制类型
+    fn get_field_type_info(&self, type_name: &str, key: u32) -> (Cow<'_, str>, &str, bool) {
+        let Some((type_str, field_str)) = self.types.get(type_name).and_then(|type_map| type_map.get(&key)) else {
+            return (Cow::Borrowed("message"), "", false);
+        };
+        match strip_deprecated(type_str) {
+            Some(type_str) => (Cow::Owned(type_str), field_str, true),
+            None => (Cow::Borrowed(type_str), field_str, false),
+        }
     }
     
     fn get_wire_type_name(&self, wire_type: u8) -> &'static str {
+        WireType::from_u8(wire_type).map_or("message", WireType::name)
+    }
+}
+
+/// 类型带有`deprecated`修饰词时返回去掉它之后的类型，例如`["int32 deprecated", "old_id"]`
+fn strip_deprecated(type_str: &str) -> Option<String> {
+    if !type_str.split_whitespace().skip(1).any(|word| word == "deprecated") {
+        return None;
+    }
+    Some(type_str.split_whitespace().filter(|&word| word != "deprecated").collect::<Vec<_>>().join(" "))
+}
+
+/// 记录读取过的字节，`write_message`用它取得每个字段的原始数据
+struct RecordingReader<'a, R> {
+    inner: &'a mut R,
+    recorded: Vec<u8>,
+}
+
+impl<R: Read> Read for RecordingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.recorded.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+const HTML_HEADER: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body { font-family: monospace; }
+.message { margin-left: 1.5em; }
+summary { cursor: pointer; }
+.number { color: #1f5fbf; font-weight: bold; }
+.name { color: #555; }
+.value { white-space: pre; }
+.wt-varint, .wt-64bit, .wt-32bit { color: #a07000; }
+.wt-chunk { color: #2e7d32; }
+.group { color: #7b1fa2; }
+</style>
+</head>
+<body>"#;
+
+const HTML_FOOTER: &str = "</body>\n</html>";
+
+/// 恰好由field 1和field 2组成的消息，返回两者的线类型和原始值
+type MapEntryItem = (u8, Vec<u8>);
+
+fn map_entry_fields(data: &[u8]) -> Option<(MapEntryItem, MapEntryItem)> {
+    let mut cursor = Cursor::new(data);
+    let mut items = Vec::new();
+    while let Some((number, wire_type)) = read_identifier(&mut cursor).ok()? {
+        if matches!(wire_type, 3 | 4) || number as usize != items.len() + 1 {
+            return None;
+        }
+        items.push((wire_type, read_bounded_value(&mut cursor, wire_type, 0).ok()?));
+    }
+    let value = items.pop()?;
+    let key = items.pop()?;
+    items.is_empty().then_some((key, value))
+}
+
+/// `map<K, V>`中的key和value类型
+fn map_entry_types(field_type: &str) -> Option<(&str, &str)> {
+    let inner = field_type.strip_prefix("map<")?.strip_suffix('>')?;
+    let (key_type, value_type) = inner.split_once(',')?;
+    Some((key_type.trim(), value_type.trim()))
+}
+
+/// 在消息的原始数据中查找第一个field number为`number`的varint字段
+fn varint_in_message(data: &[u8], number: u32) -> Option<i64> {
+    let mut cursor = Cursor::new(data);
+    while let Ok(Some((key, wire_type))) = read_identifier(&mut cursor) {
+        let value = read_value(&mut cursor, wire_type).ok()??;
+        if key == number && wire_type == 0 {
+            return parse_varint_bytes(&value).ok().map(|v| v as i64);
+        }
+    }
+    None
+}
+
+/// 在字段树中查找第一个field number为`number`的varint字段
+fn varint_in_fields(fields: &[Field], number: u32) -> Option<i64> {
+    fields.iter()
+        .find(|f| f.number == number && f.wire_type == WireType::Varint)
+        .and_then(|f| parse_varint_bytes(&f.raw).ok())
+        .map(|v| v as i64)
+}
+
+/// JSON中的整数，超出double精确范围（2^53）的值输出为字符串，避免丢失精度
+fn json_integer(value: i128) -> String {
+    if value.unsigned_abs() <= 1 << 53 {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value)
+    }
+}
+
+/// JSON不能表示NaN和无穷大，这些值输出为字符串
+fn json_float(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value)
+    }
+}
+
+/// 没有展开为嵌套消息的字段的(类型, JSON值)，`declared`为schema中的完整类型，包括`be`等修饰词
+///
+/// bytes和无法按类型解码的值输出为十六进制字符串
+fn json_value(field: &Field, declared: Option<&str>) -> (&'static str, String) {
+    let big_endian = declared.is_some_and(|t| has_modifier(t, "be"));
+    let declared = declared.map(|t| t.split_whitespace().next().unwrap_or_default());
+    let raw = &field.raw;
+    let hex = || format!("\"{}\"", raw.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    match field.wire_type {
+        WireType::Varint => {
+            let Ok((value, _)) = decode_varint_bytes(raw) else {
+                return ("bytes", hex());
+            };
+            let json = match declared.unwrap_or("varint") {
+                "varint" | "uint32" | "uint64" | "bitfield" | "iso_country" | "iso_currency" => json_integer(value as i128),
+                "bool" => (value != 0).to_string(),
+                "sint32" | "sint64" => json_integer(zigzag_decode(value) as i128),
+                // int32和enum只取低32位，int64等其他类型按64位有符号数解释
+                "int32" | "enum" => json_integer(value as i32 as i128),
+                _ => json_integer(value as i64 as i128),
+            };
+            ("varint", json)
+        }
+        WireType::Bit64 => {
+            let bytes: [u8; 8] = raw.as_slice().try_into().unwrap_or_default();
+            let json = match declared {
+                Some("double") => json_float(f64::from_le_bytes(bytes)),
+                Some("sfixed64") => json_integer(i64::from_le_bytes(bytes) as i128),
+                Some("fixed64") if big_endian => json_integer(u64::from_be_bytes(bytes) as i128),
+                _ => json_integer(u64::from_le_bytes(bytes) as i128),
+            };
+            ("64bit", json)
+        }
+        WireType::Bit32 => {
+            let bytes: [u8; 4] = raw.as_slice().try_into().unwrap_or_default();
+            let json = match declared {
+                Some("float") => json_float(f32::from_le_bytes(bytes) as f64),
+                Some("sfixed32") => json_integer(i32::from_le_bytes(bytes) as i128),
+                Some("fixed32") if big_endian => json_integer(u32::from_be_bytes(bytes) as i128),
+                _ => json_integer(u32::from_le_bytes(bytes) as i128),
+            };
+            ("32bit", json)
+        }
+        WireType::Chunk => {
+            let text = match declared {
+                Some("string:latin1") => Some(crate::charset::decode_latin1(raw)),
+                Some("string:gbk") => Some(crate::charset::decode_gbk(raw).0),
+                Some("string") => Some(String::from_utf8_lossy(raw).into_owned()),
+                Some(_) => None,
+                None => std::str::from_utf8(raw).ok().filter(|s| is_likely_text(s)).map(str::to_string),
+            };
+            match text {
+                Some(text) => ("string", escape_json(&text)),
+                None => ("bytes", hex()),
+            }
+        }
+        WireType::StartGroup => ("startgroup", "null".to_string()),
+        WireType::EndGroup => ("endgroup", "null".to_string()),
+    }
+}
+
+/// 跳过StartGroup之后直到对应EndGroup的内容，group中的字段不属于外层消息。
+/// 没有对应的EndGroup时不移动，与`parse_group`一样把之后的字段当作同一层
+fn skip_group(cursor: &mut Cursor<&[u8]>, key: u32) {
+    let start = cursor.position() as usize;
+    if let Some((_, len)) = group_len(&cursor.get_ref()[start..], key) {
+        cursor.set_position((start + len) as u64);
+    }
+}
+
+/// 从StartGroup之后开始查找同一field number的EndGroup，返回group内容的长度和包括EndGroup在内的长度。
+/// 嵌套的group需要各自配对，遇到不匹配的EndGroup时返回`None`
+fn group_len(data: &[u8], key: u32) -> Option<(usize, usize)> {
+    let mut cursor = Cursor::new(data);
+    let mut open = vec![key];
+    loop {
+        let field_start = cursor.position() as usize;
+        let (number, wire_type) = read_identifier(&mut cursor).ok()??;
         match wire_type {
-            0 => "varint",
-            1 => "64bit",
-            2 => "chunk",
-            3 => "startgroup",
-            4 => "endgroup",
-            5 => "32bit",
-            _ => "message",
+            3 => open.push(number),
+            4 if open.last() == Some(&number) => {
+                open.pop();
+                if open.is_empty() {
+                    return Some((field_start, cursor.position() as usize));
+                }
+            }
+            4 => return None,
+            _ => {
+                read_bounded_value(&mut cursor, wire_type, 0).ok()?;
+            }
+        }
+    }
+}
+
+/// 字段在所在消息中是第几个相同field number的chunk，其他线类型的字段记为0
+fn next_chunk_index(chunk_counts: &mut HashMap<u32, usize>, key: u32, wire_type: u8) -> usize {
+    if wire_type != 2 {
+        return 0;
+    }
+    let count = chunk_counts.entry(key).or_insert(0);
+    *count += 1;
+    *count - 1
+}
+
+/// `expand_path`的路径写为`1.2[3]`，第一次出现的字段省略`[0]`
+pub fn format_expand_path(path: &[(u32, usize)]) -> String {
+    let steps: Vec<String> = path.iter()
+        .map(|&(number, index)| if index == 0 { number.to_string() } else { format!("{}[{}]", number, index) })
+        .collect();
+    steps.join(".")
+}
+
+/// 解析`format_expand_path`的格式，例如`1.2[3]`
+pub fn parse_expand_path(text: &str) -> Option<Vec<(u32, usize)>> {
+    text.split('.')
+        .map(|step| match step.strip_suffix(']').and_then(|step| step.split_once('[')) {
+            Some((number, index)) => Some((number.parse().ok()?, index.parse().ok()?)),
+            None => Some((step.parse().ok()?, 0)),
+        })
+        .collect()
+}
+
+/// 每个group的开始和结束标记是否能配对，不能配对的标记需要写成`N:SGROUP`/`N:EGROUP`
+fn matched_groups(fields: &[Field]) -> Vec<bool> {
+    let mut matched = vec![false; fields.len()];
+    let mut starts: Vec<usize> = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        match field.wire_type {
+            WireType::StartGroup => starts.push(index),
+            WireType::EndGroup => {
+                if let Some(&start) = starts.last() && fields[start].number == field.number {
+                    starts.pop();
+                    matched[start] = true;
+                    matched[index] = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    matched
+}
+
+/// protoscope的字符串字面量，不可打印的字节写为`\xNN`
+fn protoscope_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            c if c.is_control() => {
+                let mut buf = [0; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    result.push_str(&format!("\\x{:02x}", b));
+                }
+            }
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// 不是嵌套消息的字段在protoscope中的写法，`declared`为schema中的完整类型
+fn protoscope_value(field: &Field, declared: Option<&str>) -> String {
+    let raw = &field.raw;
+    let primary = declared.and_then(|t| t.split_whitespace().next());
+    let hex = |data: &[u8]| format!("`{}`", data.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    match field.wire_type {
+        WireType::Varint => match decode_varint_bytes(raw) {
+            Ok((value, canonical)) => {
+                // 非最短编码用long-form保留多出来的字节数
+                let long_form = if canonical { String::new() } else {
+                    format!("long-form:{} ", raw.len() - encode_varint(value).len())
+                };
+                format!("{}{}", long_form, protoscope_varint(value, primary))
+            }
+            Err(_) => hex(raw),
+        },
+        WireType::Bit32 => {
+            let bytes: [u8; 4] = raw.as_slice().try_into().unwrap_or_default();
+            match primary {
+                Some("float") if f32::from_le_bytes(bytes).is_finite() => format!("{:?}i32", f32::from_le_bytes(bytes)),
+                Some("sfixed32") => format!("{}i32", i32::from_le_bytes(bytes)),
+                _ => format!("{}i32", u32::from_le_bytes(bytes)),
+            }
+        }
+        WireType::Bit64 => {
+            let bytes: [u8; 8] = raw.as_slice().try_into().unwrap_or_default();
+            match primary {
+                Some("double") if f64::from_le_bytes(bytes).is_finite() => format!("{:?}i64", f64::from_le_bytes(bytes)),
+                Some("sfixed64") => format!("{}i64", i64::from_le_bytes(bytes)),
+                _ => format!("{}i64", u64::from_le_bytes(bytes)),
+            }
         }
+        WireType::Chunk => {
+            // schema中的repeated数值字段packed时写为`{1 2 3}`
+            if let Some(field_type) = declared.filter(|t| has_modifier(t, "repeated"))
+                && let Some(elements) = protoscope_packed(raw, field_type) {
+                return format!("{{{}}}", elements.join(" "));
+            }
+            let text = match primary {
+                Some("bytes") => None,
+                Some(t) if t.starts_with("string") || t == "any" => std::str::from_utf8(raw).ok(),
+                _ => std::str::from_utf8(raw).ok().filter(|s| is_likely_text(s)),
+            };
+            match text {
+                Some(text) => format!("{{{}}}", protoscope_string(text)),
+                None if raw.is_empty() => "{}".to_string(),
+                None => format!("{{{}}}", hex(raw)),
+            }
+        }
+        WireType::StartGroup | WireType::EndGroup => unreachable!("groups are handled by render_protoscope"),
+    }
+}
+
+fn protoscope_varint(value: u64, primary: Option<&str>) -> String {
+    match primary {
+        Some("sint32" | "sint64") => format!("{}z", zigzag_decode(value)),
+        Some("int32") => (value as i32).to_string(),
+        Some("int64") => (value as i64).to_string(),
+        Some("bool") if value <= 1 => (value == 1).to_string(),
+        _ => value.to_string(),
+    }
+}
+
+fn protoscope_packed(raw: &[u8], field_type: &str) -> Option<Vec<String>> {
+    let (element_type, handler) = PackedHandler::element(field_type)?;
+    let wire_type = handler.wire_type();
+    Some(split_packed(raw, wire_type)?.into_iter().map(|raw| {
+        let element = Field { number: 0, wire_type, type_name: String::new(), range: 0..raw.len(), raw, message: None };
+        protoscope_value(&element, Some(element_type))
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::Payload;
+    use crate::tree::decode_message;
+
+    // field 1 = [varint 5][message {1: 150, 2: 2}]
+    const FRAMED_PAYLOAD: &[u8] = b"\x0a\x06\x05\x08\x96\x01\x10\x02";
+
+    #[test]
+    fn test_framed_message() {
+        let mut parser = Parser::new();
+        parser.detect_framed_messages = true;
+        let result = parser.parse_message(FRAMED_PAYLOAD, "root").unwrap();
+        assert!(result.contains("framed message:"), "{}", result);
+        assert!(result.contains("150"), "{}", result);
+
+        // 默认不开启
+        let mut parser = Parser::new();
+        let result = parser.parse_message(FRAMED_PAYLOAD, "root").unwrap();
+        assert!(!result.contains("framed message"), "{}", result);
+
+        // 长度前缀与剩余长度不一致时不应识别
+        let mut parser = Parser::new();
+        parser.detect_framed_messages = true;
+        let result = parser.parse_message(b"\x0a\x06\x04\x08\x96\x01\x10\x02", "root").unwrap();
+        assert!(!result.contains("framed message"), "{}", result);
+    }
+
+    #[test]
+    fn test_bitmask_enum() {
+        let mut parser = Parser::new();
+        parser.register_enum("Permission", &[(1, "READ"), (2, "WRITE"), (4, "EXEC")], true);
+        parser.types.get_mut("root").unwrap()
+            .insert(1, ("Permission".into(), "perm".into()));
+        
+        let result = parser.parse_message(b"\x08\x05", "root").unwrap();
+        assert!(result.contains("perm = "), "{}", result);
+        assert!(result.contains("(READ | EXEC)"), "{}", result);
+    }
+
+    #[test]
+    fn test_show_packed_varints() {
+        let payload = b"\x0a\x05\x01\x02\x96\x01\x03";
+        
+        let mut parser = Parser::new();
+        parser.options.show_packed_varints = true;
+        let result = parser.parse_message(payload, "root").unwrap();
+        assert!(result.contains("(as packed varints: [1, 2, 150, 3])"), "{}", result);
+        
+        // 被解析或猜测为消息的chunk不再额外标注
+        let result = parser.parse_message(b"\x0a\x02\x08\x01", "root").unwrap();
+        assert!(!result.contains("as packed varints"), "{}", result);
+        let result = ChunkHandler.parse_with_options(b"\x08\x01", "chunk", &parser.options).unwrap();
+        assert_eq!(result, "message (2 bytes)");
+        
+        let mut parser = Parser::new();
+        let result = parser.parse_message(payload, "root").unwrap();
+        assert!(!result.contains("as packed varints"), "{}", result);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_parse_file_mmap() {
+        let path = std::env::temp_dir().join(format!("protobuf-inspector-mmap-{}.bin", std::process::id()));
+        std::fs::write(&path, b"\x02\x08\x01\x03\x08\x96\x01\x02\x10\x05").unwrap();
+        let result = strip_ansi(&Parser::new().parse_file_mmap(&path, "root").unwrap());
+        assert!(result.contains("[0] root:\n    1 <varint> = 1"), "{}", result);
+        assert!(result.contains("[1] root:\n    1 <varint> = 150"), "{}", result);
+        assert!(result.contains("[2] root:\n    2 <varint> = 5"), "{}", result);
+        
+        std::fs::write(&path, b"\x05\x08\x01").unwrap();
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.parse_file_mmap(&path, "root").unwrap());
+        assert_eq!(result, "[0] (truncated message: expected 5 bytes, 2 available)");
+        assert_eq!(parser.warnings, ["truncated message 0: expected 5 bytes, 2 available"]);
+        std::fs::write(&path, b"").unwrap();
+        assert_eq!(Parser::new().parse_file_mmap(&path, "root").unwrap(), "");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_max_depth() {
+        // 12层嵌套的 1 { 1 { ... 2: 1 } }，按schema中的递归类型展开
+        let mut payload = b"\x10\x01".to_vec();
+        for _ in 0..12 {
+            let mut outer = vec![0x0a, payload.len() as u8];
+            outer.extend(&payload);
+            payload = outer;
+        }
+        let schema = "[Node]\n1 = [\"Node\", \"child\"]\n";
+        
+        let mut parser = Parser::new();
+        parser.load_schema(schema).unwrap();
+        let result = parser.parse_message(&payload, "Node").unwrap();
+        assert!(!result.contains("recursion depth exceeded"), "{}", result);
+        assert_eq!(result.matches("child = Node:").count(), 12);
+        
+        let mut parser = Parser::new().with_max_depth(5);
+        parser.load_schema(schema).unwrap();
+        let result = parser.parse_message(&payload, "Node").unwrap();
+        assert!(result.contains("recursion depth exceeded"), "{}", result);
+    }
+
+    #[test]
+    fn test_lenient_truncated_nested_message() {
+        // 1 { 1: 1, 2: 2, 3: "ab..."（声明5字节，只有2字节） }, 2: 150
+        let payload = b"\x0a\x08\x08\x01\x10\x02\x1a\x05ab\x10\x96\x01";
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(!result.contains("<truncated>"), "{}", result);
+        
+        parser.options.lenient = true;
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("1 <chunk> = message:\n        1 <varint> = 1\n        2 <varint> = 2\n        <truncated> (4 bytes)\n    2 <varint> = 150"), "{}", result);
+    }
+
+    #[test]
+    fn test_chunk_interpretation_order() {
+        // "\n\x08POKECOIN"既是嵌套消息{1: "POKECOIN"}，也满足文本的判断
+        let payload = b"\x72\x0a\x0a\x08POKECOIN";
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("14 <chunk> = message:\n        1 <chunk> = \"POKECOIN\""), "{}", result);
+        
+        parser.options.chunk_interpretation_order = vec![ChunkKind::String, ChunkKind::Message];
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("14 <chunk> = \"") && !result.contains("message"), "{}", result);
+        
+        // 只凭猜测判断的chunk默认先显示为字符串
+        let data = b"\x0a\x08POKECOIN".repeat(15);
+        assert!(strip_ansi(&ChunkHandler.parse(&data, "chunk").unwrap()).starts_with("\"\n\x08POKECOIN"));
+        let options = ParseOptions {
+            chunk_interpretation_order: vec![ChunkKind::Message, ChunkKind::String, ChunkKind::Bytes],
+            ..ParseOptions::default()
+        };
+        assert_eq!(ChunkHandler.parse_with_options(&data, "chunk", &options).unwrap(), "message (150 bytes)");
+        
+        parser.options.chunk_interpretation_order = vec![ChunkKind::Bytes];
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("14 <chunk> = bytes (10)"), "{}", result);
+    }
+
+    #[test]
+    fn test_trace() {
+        // "abcd"可以是字符串，也可能是消息：0x61是field 12的64bit字段，但数据不足8字节
+        let payload = b"\x0a\x04abcd\x12\x04\x08\x01\x10\x02";
+        let mut parser = Parser::new();
+        parser.parse_message(payload, "root").unwrap();
+        assert!(parser.trace.is_empty());
+        
+        parser.options.trace = true;
+        let output = parser.parse_message(payload, "root").unwrap();
+        assert_eq!(parser.trace, [
+            "1: <chunk> (4 bytes), type chunk",
+            "1: nested message rejected: ChildExceedsParent (0 fields, 0 weird values, control chars not found)",
+            "2: <chunk> (4 bytes), type chunk",
+            "2.1: <varint> (1 bytes), type varint",
+            "2.2: <varint> (1 bytes), type varint",
+            "2: nested message accepted (2 fields, 0 weird values, control chars found)",
+        ]);
+        // 跟踪记录不影响输出
+        assert!(!output.contains("nested message"), "{}", output);
+    }
+
+    #[test]
+    fn test_packed_schema_type() {
+        let mut parser = Parser::new();
+        parser.load_schema("[root]\n1 = [\"int32 repeated\", \"ids\"]\n2 = [\"fixed32 repeated\", \"masks\"]\n").unwrap();
+        let payload = b"\x0a\x05\x01\x02\x96\x01\x03\x12\x08\x01\x00\x00\x00\x00\x01\x00\x00";
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("1 ids = [1, 2, 150, 3]"), "{}", result);
+        assert!(result.contains("2 masks = [1, 256]"), "{}", result);
+        
+        let result = strip_ansi(&parser.parse_message(b"\x12\x04\x01\x00\x00\x00\x12\x03\x01\x02\x03", "root").unwrap());
+        // 无法完整拆分的chunk不参与合并，各自显示
+        assert!(result.contains("2 masks (2 items) = [\n        [1]\n        bytes (3)"), "{}", result);
+        
+        // group中同一field number的字段属于group，不合并到外层
+        let grouped = Payload::new()
+            .field(1).bytes(b"\x01\x02")
+            .field(5).group(Payload::new().field(1).varint(7))
+            .build();
+        let result = strip_ansi(&parser.parse_message(&grouped, "root").unwrap());
+        assert!(result.contains("1 ids = [1, 2]"), "{}", result);
+        assert!(result.contains("1 <varint> = 7"), "{}", result);
+        
+        // 旧的`packed X`写法与`X repeated`相同
+        parser.load_schema("[root]\n1 = [\"packed int32\", \"ids\"]\n").unwrap();
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("1 ids = [1, 2, 150, 3]"), "{}", result);
+    }
+
+    #[test]
+    fn test_parse_delimited_messages() {
+        let mut parser = Parser::new();
+        let mut user = HashMap::new();
+        user.insert(1, ("string".into(), "name".into()));
+        let mut order = HashMap::new();
+        order.insert(1, ("uint64".into(), "amount".into()));
+        parser.types.insert("User".to_string(), user);
+        parser.types.insert("Order".to_string(), order);
+        
+        let data = b"\x05\x0a\x03abc\x03\x08\x96\x01";
+        let result = parser.parse_delimited_messages(data, &["User", "Order"]).unwrap();
+        assert!(result.contains("[0] User:"), "{}", result);
+        assert!(result.contains("name = "), "{}", result);
+        assert!(result.contains("[1] Order:"), "{}", result);
+        assert!(result.contains("amount = "), "{}", result);
+        
+        // 最后一条消息被截断
+        let result = strip_ansi(&parser.parse_delimited_messages(&data[..8], &["User", "Order"]).unwrap());
+        assert!(result.contains("[0] User:"), "{}", result);
+        assert!(result.ends_with("[1] (truncated message: expected 3 bytes, 1 available)"), "{}", result);
+        assert_eq!(parser.warnings, ["truncated message 1: expected 3 bytes, 1 available"]);
+        // 长度前缀被截断
+        assert!(parser.parse_delimited_messages(b"\x05\x0a\x03abc\x96", &["User"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_grpc_frames() {
+        let mut parser = Parser::new();
+        let data = b"\x00\x00\x00\x00\x03\x08\x96\x01\x01\x00\x00\x00\x02\x1f\x8b\x00\x00\x00\x00\x00";
+        let result = strip_ansi(&parser.parse_grpc_frames(data, &["root"]).unwrap());
+        assert!(result.starts_with("[0] (3 bytes) root:\n    1 <varint> = 150"), "{}", result);
+        assert!(result.contains("[1] (2 bytes) (compressed)"), "{}", result);
+        assert!(result.contains("[2] (0 bytes) root:"), "{}", result);
+        assert_eq!(parser.warnings, ["frame 1 is compressed and cannot be decoded"]);
+        
+        // 长度和帧头被截断
+        let result = strip_ansi(&parser.parse_grpc_frames(&data[..7], &["root"]).unwrap());
+        assert_eq!(result, "[0] (truncated frame: expected 3 bytes, 2 available)");
+        let result = strip_ansi(&parser.parse_grpc_frames(&data[..10], &["root"]).unwrap());
+        assert!(result.ends_with("[1] (truncated frame: expected 5 header bytes, 2 available)"), "{}", result);
+        assert_eq!(parser.warnings, ["truncated frame 1: expected 5 header bytes, 2 available"]);
+        
+        // 标志不是0或1的帧不再解析
+        let result = strip_ansi(&parser.parse_grpc_frames(b"\x00\x00\x00\x00\x00\x08\x00\x00\x00\x02\x08\x01", &["root"]).unwrap());
+        assert_eq!(result, "[0] (0 bytes) root:\n    empty\n[1] (invalid frame: unknown flag 0x08)");
+        assert_eq!(parser.warnings, ["invalid frame 1: unknown flag 0x08"]);
+    }
+
+    #[test]
+    fn test_negative_enum() {
+        // -1按照int32编码，符号扩展为10字节的varint
+        let payload = b"\x08\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01";
+        
+        let mut parser = Parser::new();
+        parser.types.get_mut("root").unwrap()
+            .insert(1, ("enum".into(), "state".into()));
+        let result = parser.parse_message(payload, "root").unwrap();
+        assert!(result.contains("-1"), "{}", result);
+        assert!(!result.contains("18446744073709551615"), "{}", result);
+        
+        let mut parser = Parser::new();
+        parser.register_enum("State", &[(-1, "INVALID"), (0, "IDLE")], false);
+        parser.types.get_mut("root").unwrap()
+            .insert(1, ("State".into(), "state".into()));
+        let result = parser.parse_message(payload, "root").unwrap();
+        assert!(result.contains("(INVALID)"), "{}", result);
+        
+        // 没有符号扩展的5字节编码同样按int32解释
+        let result = strip_ansi(&parser.parse_message(b"\x08\xff\xff\xff\xff\x0f", "root").unwrap());
+        assert!(result.contains("state = -1 (INVALID)"), "{}", result);
+        parser.types.get_mut("root").unwrap().insert(1, ("enum".into(), "state".into()));
+        let result = parser.render_fields(&decode_message(b"\x08\xff\xff\xff\xff\x0f").unwrap().fields, "root", OutputFormat::Json).unwrap();
+        assert!(result.contains("\"value\": -1"), "{}", result);
+    }
+
+    #[test]
+    fn test_render_fields() {
+        let fields = vec![
+            Field { number: 1, wire_type: WireType::Varint, type_name: "varint".to_string(), raw: vec![0x96, 0x01], range: 0..3, message: None },
+            Field { number: 2, wire_type: WireType::Chunk, type_name: "chunk".to_string(), raw: b"abc".to_vec(), range: 3..8, message: None },
+        ];
+        
+        let mut parser = Parser::new();
+        let result = parser.render_fields(&fields, "root", OutputFormat::Text).unwrap();
+        let expected = parser.parse_message(b"\x08\x96\x01\x12\x03abc", "root").unwrap();
+        assert_eq!(result, expected);
+        assert!(result.contains("\"abc\""), "{}", result);
+    }
+
+    #[test]
+    fn test_no_nested() {
+        let payload = b"\x0a\x03\x08\x96\x01";
+        
+        let mut parser = Parser::new();
+        let result = parser.parse_message(payload, "root").unwrap();
+        assert!(result.contains("message:"), "{}", result);
+        
+        let mut parser = Parser::new();
+        parser.options.no_nested = true;
+        let result = parser.parse_message(payload, "root").unwrap();
+        assert!(!result.contains("message"), "{}", result);
+        assert!(result.contains("bytes (3)"), "{}", result);
+    }
+
+    #[test]
+    fn test_nested_parse_limit() {
+        // 嵌套消息长度为164字节，超过了以前固定的100字节
+        let name = "x".repeat(80);
+        let payload = Payload::new()
+            .field(1).message(Payload::new().field(1).string(&name).field(2).string(&name))
+            .build();
+        
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(result.contains("message:"), "{}", result);
+        assert!(result.contains(&format!("2 <chunk> = \"{}\"", name)), "{}", result);
+        
+        // 字段较多的嵌套消息同样展开
+        let mut inner = Payload::new();
+        for number in 1..=6 {
+            inner = inner.field(number).string(&format!("value {:0>15}", number));
+        }
+        let many_fields = Payload::new().field(1).message(inner).field(2).varint(1).build();
+        assert!(many_fields.len() > 100);
+        let result = strip_ansi(&Parser::new().parse_message(&many_fields, "root").unwrap());
+        assert!(result.contains("1 <chunk> = message:\n        1 <chunk> = \"value 000000000000001\""), "{}", result);
+        assert!(result.contains("\n        6 <chunk> = \"value 000000000000006\"\n    2 <varint> = 1"), "{}", result);
+        
+        let mut parser = Parser::new().with_nested_parse_limit(100);
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(!result.contains("message:"), "{}", result);
+        assert!(result.contains("1 <chunk> = \""), "{}", result);
+        
+        // 字符串的内容不影响是否接受嵌套消息
+        let payload = Payload::new().field(1).message(Payload::new().field(1).string("the cart is empty").field(2).varint(1)).build();
+        let result = strip_ansi(&Parser::new().parse_message(&payload, "root").unwrap());
+        assert!(result.contains("1 <chunk> = message:\n        1 <chunk> = \"the cart is empty\""), "{}", result);
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let mut parser = Parser::new();
+        parser.options.strict = true;
+        let result = parser.parse_message(b"\x0a\x03abc\x12\x03\x08\x96\x01", "root").unwrap();
+        assert!(!result.contains("\"abc\""), "{}", result);
+        assert!(!result.contains("message"), "{}", result);
+        assert_eq!(result.matches("bytes (3)").count(), 2, "{}", result);
+        
+        // 有schema的字段仍然按声明的类型显示
+        parser.types.get_mut("root").unwrap()
+            .insert(1, ("string".into(), "name".into()));
+        let result = parser.parse_message(b"\x0a\x03abc", "root").unwrap();
+        assert!(result.contains("\"abc\""), "{}", result);
+        
+        // 只有schema中声明为消息的chunk按消息解析
+        let mut parser = Parser::new().with_strict(true);
+        parser.load_schema("[root]\n2 = [\"Inner\", \"inner\"]\n[Inner]\n1 = \"value\"\n").unwrap();
+        let payload = b"\x12\x03\x08\x96\x01\x1a\x03\x08\x96\x01";
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("2 inner = Inner:\n        1 value = 150"), "{}", result);
+        assert!(result.contains("3 <chunk> = bytes (3)"), "{}", result);
+        let chunks = parser.extract_chunks(payload, "root").unwrap();
+        assert_eq!(chunks, [(vec![3], b"\x08\x96\x01".to_vec())]);
+        
+        // 也不把group猜测为MessageSet
+        let result = strip_ansi(&parser.parse_message(b"\x0b\x10\x07\x1a\x02\x08\x01\x0c", "root").unwrap());
+        assert!(!result.contains("MessageSet"), "{}", result);
+    }
+
+    #[test]
+    fn test_nested_guess_and_parse_agree() {
+        // 前三个字段看起来正常，但第四个字段被截断，整个chunk不能作为消息
+        let payload = b"\x0a\x07\x08\x01\x10\x02\x18\x03\x20";
+        let mut parser = Parser::new();
+        let result = parser.parse_message(payload, "root").unwrap();
+        assert!(!result.contains("message:"), "{}", result);
+        // 较短的chunk检查全部字段，猜测同样因为第四个字段被截断而不认为是消息
+        assert!(strip_ansi(&result).contains("<chunk> = bytes (7)"), "{}", result);
+        
+        // 第一个字段就不像消息时不会继续解析后面的数据
+        let mut guess = MessageGuess::default();
+        let mut parser = Parser::new();
+        assert!(parser.parse_fields(b"POKECOIN\xff", "message", 1, Some(&mut guess)).is_err());
+        assert!(guess.is_rejected());
+    }
+
+    #[test]
+    fn test_allow_field_zero() {
+        let payload = b"\x00\x05\x08\x96\x01";
+        
+        let mut parser = Parser::new();
+        assert!(matches!(parser.parse_message(payload, "root"), Err(core::Error::InvalidFieldNumber)));
+        
+        parser.options.allow_field_zero = true;
+        let result = parser.parse_message(payload, "root").unwrap();
+        let result = strip_ansi(&result);
+        assert!(result.contains("0 <varint> = 5"), "{}", result);
+        assert!(result.contains("1 <varint> = 150"), "{}", result);
+    }
+
+    #[test]
+    fn test_zero_padding() {
+        let mut payload = crate::PROTOBUF_EXAMPLE.to_vec();
+        payload.extend([0, 0, 0, 0]);
+        
+        let mut parser = Parser::new();
+        assert!(parser.parse_message(&payload, "root").is_err());
+        
+        parser.options.allow_zero_padding = true;
+        let result = parser.parse_message(&payload, "root").unwrap();
+        assert!(result.contains("kotlin46"), "{}", result);
+        assert!(result.contains("STARDUST"), "{}", result);
+        assert!(result.contains("(4 bytes of zero padding)"), "{}", result);
+        
+        // 最后一个字段的值为0时不能被当作填充
+        let result = strip_ansi(&parser.parse_message(b"\x08\x00", "root").unwrap());
+        assert!(result.contains("1 <varint> = 0"), "{}", result);
+        assert!(!result.contains("padding"), "{}", result);
+    }
+
+    #[test]
+    fn test_field_postprocessor() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        
+        // {1: {2: "abc"}, 3: "def"}
+        let nested = Message { fields: vec![
+            Field { number: 2, wire_type: WireType::Chunk, type_name: "chunk".to_string(), raw: b"abc".to_vec(), range: 2..7, message: None },
+        ] };
+        let fields = vec![
+            Field { number: 1, wire_type: WireType::Chunk, type_name: "message".to_string(), raw: b"\x12\x03abc".to_vec(), range: 0..7, message: Some(nested) },
+            Field { number: 3, wire_type: WireType::Chunk, type_name: "chunk".to_string(), raw: b"def".to_vec(), range: 7..12, message: None },
+        ];
+        
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let visited = order.clone();
+        let mut parser = Parser::new();
+        parser.set_field_postprocessor(Box::new(move |field| {
+            visited.borrow_mut().push(field.number);
+            if field.wire_type == WireType::Chunk && field.message.is_none() {
+                field.raw.make_ascii_uppercase();
+            }
+        }));
+        
+        let result = strip_ansi(&parser.render_fields(&fields, "root", OutputFormat::Text).unwrap());
+        assert!(result.contains("\"ABC\""), "{}", result);
+        assert!(result.contains("\"DEF\""), "{}", result);
+        assert_eq!(*order.borrow(), vec![2, 1, 3]);
+
+        // 文本输出同样经过postprocessor
+        order.borrow_mut().clear();
+        let data = Payload::new()
+            .field(1).message(Payload::new().field(2).string("abc"))
+            .field(3).string("def")
+            .build();
+        let result = strip_ansi(&parser.parse_message(&data, "root").unwrap());
+        assert!(result.contains("\"ABC\""), "{}", result);
+        assert!(result.contains("\"DEF\""), "{}", result);
+        assert_eq!(*order.borrow(), vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn test_message_set() {
+        // {1: group {2: 12345, 3: {1: 150}}, 1: group {2: 7, 3: {2: "abc"}}}
+        let data = b"\x0b\x10\xb9\x60\x1a\x03\x08\x96\x01\x0c\x0b\x10\x07\x1a\x05\x12\x03abc\x0c";
+        let result = strip_ansi(&Parser::new().parse_message(data, "root").unwrap());
+        assert!(result.contains("1 <group> (2 items) = [\n        MessageSet[12345]:\n            1 <varint> = 150"), "{}", result);
+        assert!(result.contains("\n        MessageSet[7]:\n            2 <chunk> = \"abc\""), "{}", result);
+        assert!(!result.contains("startgroup"), "{}", result);
+        
+        // 不符合MessageSet约定的group仍然按普通group输出
+        let result = strip_ansi(&Parser::new().parse_message(b"\x0b\x08\x01\x0c", "root").unwrap());
+        assert!(result.contains("1 <group> = group:\n        1 <varint> = 1"), "{}", result);
+    }
+
+    #[test]
+    fn test_groups() {
+        // proto2: repeated group Result = 1 { string url = 2; string title = 3; }
+        let data = Payload::new()
+            .field(1)
+            .group(Payload::new().field(2).string("a.b").field(3).string("hi"))
+            .group(Payload::new().field(2).string("c"))
+            .field(4).varint(5)
+            .build();
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.parse_message(&data, "root").unwrap());
+        assert_eq!(result.matches("\n        group:").count(), 2, "{}", result);
+        assert!(result.contains("1 <group> (2 items) = [\n        group:\n            2 <chunk> = \"a.b\"\n            3 <chunk> = \"hi\"\n        group:"), "{}", result);
+        assert!(result.ends_with("\n    ]\n    4 <varint> = 5"), "{}", result);
+        
+        parser.load_schema("[root]\n1 = [\"Result\", \"result\"]\n[Result]\n2 = [\"string\", \"url\"]").unwrap();
+        let result = strip_ansi(&parser.parse_message(&data, "root").unwrap());
+        assert!(result.contains("1 result (2 items) = [\n        Result:\n            2 url = \"a.b\""), "{}", result);
+        
+        // 嵌套的group
+        let data = Payload::new().field(1).group(Payload::new().field(2).group(Payload::new().field(1).varint(1))).build();
+        let result = strip_ansi(&Parser::new().parse_message(&data, "root").unwrap());
+        assert!(result.contains("1 <group> = group:\n        2 <group> = group:\n            1 <varint> = 1"), "{}", result);
+        
+        // 没有结束标记，以及结束标记的field number不匹配
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.parse_message(b"\x0b\x10\x02", "root").unwrap());
+        assert!(result.contains("1 <startgroup> = (unterminated group)\n    2 <varint> = 2"), "{}", result);
+        assert_eq!(parser.warnings, ["unterminated group 1 in root"]);
+        let result = strip_ansi(&parser.parse_message(b"\x0b\x08\x01\x14", "root").unwrap());
+        assert!(result.contains("2 <endgroup> = (end of group without start)"), "{}", result);
+        
+        // 猜测为嵌套消息失败时，尝试中产生的警告不会保留
+        let data = Payload::new().field(1).bytes(b"\x08\x01\x14\xff\xff").build();
+        let result = strip_ansi(&parser.parse_message(&data, "root").unwrap());
+        assert!(!result.contains("group"), "{}", result);
+        assert!(parser.warnings.is_empty(), "{:?}", parser.warnings);
+    }
+
+    #[test]
+    fn test_extract_chunks() {
+        let mut parser = Parser::new();
+        let chunks = parser.extract_chunks(crate::PROTOBUF_EXAMPLE, "root").unwrap();
+        assert_eq!(chunks, vec![
+            (vec![2], b"kotlin46".to_vec()),
+            (vec![7], b"\x00\x01\x03\x04\x07".to_vec()),
+            (vec![8], b"".to_vec()),
+            (vec![14, 1], b"POKECOIN".to_vec()),
+            (vec![14, 1], b"STARDUST".to_vec()),
+        ]);
+        
+        parser.options.extract_nested_messages = true;
+        let chunks = parser.extract_chunks(crate::PROTOBUF_EXAMPLE, "root").unwrap();
+        assert_eq!(chunks.len(), 7);
+        assert_eq!(chunks[3], (vec![14], b"\n\x08POKECOIN".to_vec()));
+        assert_eq!(chunks[4], (vec![14, 1], b"POKECOIN".to_vec()));
+    }
+
+    #[test]
+    fn test_non_canonical_varints() {
+        // 150使用了5字节的过长编码
+        let payload = b"\x08\x96\x81\x80\x80\x00\x10\x01";
+        let mut parser = Parser::new();
+        assert!(parser.parse_message(payload, "root").is_err());
+        
+        parser.options.non_canonical_varints = NonCanonicalVarints::Warn;
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("1 <varint> = 150 (non-canonical encoding)"), "{}", result);
+        
+        parser.options.non_canonical_varints = NonCanonicalVarints::Accept;
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("1 <varint> = 150"), "{}", result);
+        assert!(!result.contains("non-canonical"), "{}", result);
+    }
+
+    #[test]
+    fn test_tree_output() {
+        // {1: 150, 2: {1: 1, 2: "abc"}, 3: "xyz"}
+        let message = Message { fields: vec![
+            Field { number: 1, wire_type: WireType::Varint, type_name: "varint".to_string(), raw: vec![0x96, 0x01], range: 0..3, message: None },
+            Field { number: 2, wire_type: WireType::Chunk, type_name: "message".to_string(), raw: b"\x08\x01\x12\x03abc".to_vec(), range: 3..12, message: Some(Message { fields: vec![
+                Field { number: 1, wire_type: WireType::Varint, type_name: "varint".to_string(), raw: vec![0x01], range: 5..7, message: None },
+                Field { number: 2, wire_type: WireType::Chunk, type_name: "chunk".to_string(), raw: b"abc".to_vec(), range: 7..12, message: None },
+            ] }) },
+            Field { number: 3, wire_type: WireType::Chunk, type_name: "chunk".to_string(), raw: b"xyz".to_vec(), range: 12..17, message: None },
+        ] };
+        
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.render_fields(&message.fields, "root", OutputFormat::Tree).unwrap());
+        if !cfg!(feature = "no-color") {
+            assert_eq!(result, "root:\n\
+                ├── 1 <varint> = 150\n\
+                ├── 2 <chunk> = message\n\
+                │   ├── 1 <varint> = 1\n\
+                │   └── 2 <chunk> = \"abc\"\n\
+                └── 3 <chunk> = \"xyz\"");
+        }
+        
+        parser.options.ascii_only = true;
+        let result = strip_ansi(&parser.render_fields(&message.fields, "root", OutputFormat::Tree).unwrap());
+        assert_eq!(result, "root:\n\
+            +-- 1 <varint> = 150\n\
+            +-- 2 <chunk> = message\n\
+            |   +-- 1 <varint> = 1\n\
+            |   \\-- 2 <chunk> = \"abc\"\n\
+            \\-- 3 <chunk> = \"xyz\"");
+    }
+
+    #[test]
+    fn test_timestamp_delta() {
+        // {1: 30, 2: 1700000000}，基准字段出现在偏移字段之后
+        let payload = b"\x08\x1e\x10\x80\xe2\xcf\xaa\x06";
+        let mut parser = Parser::new();
+        parser.types.get_mut("root").unwrap()
+            .insert(1, ("timestamp_delta base=field2".into(), "sent_at".into()));
+        
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("sent_at = +30 (2023-11-14T22:13:50Z)"), "{}", result);
+        
+        let message = decode_message(payload).unwrap();
+        let result = strip_ansi(&parser.render_fields(&message.fields, "root", OutputFormat::Text).unwrap());
+        assert!(result.contains("sent_at = +30 (2023-11-14T22:13:50Z)"), "{}", result);
+        
+        let result = strip_ansi(&parser.parse_message(b"\x08\x1e", "root").unwrap());
+        assert!(result.contains("sent_at = +30 (base field not found)"), "{}", result);
+    }
+
+    #[test]
+    fn test_json_output() {
+        let mut parser = Parser::new();
+        let message = decode_message(crate::PROTOBUF_EXAMPLE).unwrap();
+        let json = parser.render_fields(&message.fields, "root", OutputFormat::Json).unwrap();
+        assert!(json.starts_with("[{\"field\": 1, \"wire_type\": 0, \"type\": \"varint\", \"value\": 1469046243471}, "), "{}", json);
+        assert!(json.contains("{\"field\": 7, \"wire_type\": 2, \"type\": \"bytes\", \"value\": \"0001030407\"}"), "{}", json);
+        assert!(json.contains("{\"field\": 14, \"wire_type\": 2, \"type\": \"message\", \"value\": [{\"field\": 1, \"wire_type\": 2, \"type\": \"string\", \"value\": \"POKECOIN\"}]}"), "{}", json);
+        
+        // 超过2^53的整数输出为字符串，schema中的类型和字段名
+        parser.load_schema("[root]\n1 = [\"sint64\", \"delta\"]\n2 = [\"string\", \"text\"]\n").unwrap();
+        let message = decode_message(b"\x08\x03\x12\x03a\"\n\x19\xff\xff\xff\xff\xff\xff\xff\xff").unwrap();
+        let json = parser.render_fields(&message.fields, "root", OutputFormat::Json).unwrap();
+        assert_eq!(json, concat!(
+            "[{\"field\": 1, \"name\": \"delta\", \"wire_type\": 0, \"type\": \"sint64\", \"value\": -2}, ",
+            "{\"field\": 2, \"name\": \"text\", \"wire_type\": 2, \"type\": \"string\", \"value\": \"a\\\"\\n\"}, ",
+            "{\"field\": 3, \"wire_type\": 1, \"type\": \"64bit\", \"value\": \"18446744073709551615\"}]",
+        ));
+        
+        // 修饰词与文本输出一致
+        parser.load_schema("[root]\n1 = [\"fixed32 be epoch_s\", \"time\"]\n").unwrap();
+        let message = decode_message(b"\x0d\x65\x53\xf1\x00").unwrap();
+        let json = parser.render_fields(&message.fields, "root", OutputFormat::Json).unwrap();
+        assert!(json.contains("\"type\": \"fixed32\", \"value\": 1700000000}"), "{}", json);
+    }
+
+    #[test]
+    fn test_protoscope_output() {
+        let mut parser = Parser::new();
+        let message = decode_message(crate::PROTOBUF_EXAMPLE).unwrap();
+        let output = parser.render_fields(&message.fields, "root", OutputFormat::Protoscope).unwrap();
+        assert!(output.starts_with("1: 1469046243471\n2: {\"kotlin46\"}\n7: {`0001030407`}\n8: {}\n9: 250\n10: 1128792064i32\n"), "{}", output);
+        assert!(output.ends_with("14: {\n  1: {\"STARDUST\"}\n  2: 100\n}"), "{}", output);
+        
+        // schema中的有符号类型、packed字段和group
+        parser.load_schema("[root]\n1 = [\"sint32\", \"delta\"]\n2 = [\"sint32 repeated\", \"deltas\"]\n4 = [\"sfixed32\", \"offset\"]\n").unwrap();
+        let payload = b"\x08\x03\x12\x02\x03\x04\x1b\x25\xff\xff\xff\xff\x1c\x2c";
+        let message = decode_message(payload).unwrap();
+        let output = parser.render_fields(&message.fields, "root", OutputFormat::Protoscope).unwrap();
+        assert_eq!(output, "1: -2z  # delta\n2: {-2z 2z}  # deltas\n3: !{\n  4: -1i32  # offset\n}\n5:EGROUP");
+        
+        // 超过max_depth的嵌套消息不再展开
+        let payload = Payload::new().field(1).message(Payload::new().field(1).message(Payload::new().field(1).varint(1).field(2).varint(2))).build();
+        let message = decode_message(&payload).unwrap();
+        let mut parser = Parser::new().with_max_depth(1);
+        let output = parser.render_fields(&message.fields, "root", OutputFormat::Protoscope).unwrap();
+        assert_eq!(output, "1: {\n  1: {`08011002`}\n}");
+    }
+
+    #[test]
+    fn test_html_output() {
+        // {1: 150, 2: {1: 1, 2: "a<b>"}, 3: {2: "x&y"}}
+        let payload = b"\x08\x96\x01\x12\x07\x08\x01\x12\x03a<b\x1a\x05\x12\x03x&y";
+        let mut parser = Parser::new();
+        parser.types.get_mut("root").unwrap()
+            .insert(1, ("uint64".into(), "<id>".into()));
+        let mut message = decode_message(payload).unwrap();
+        message.fields[1].message = Some(decode_message(b"\x08\x01\x12\x03a<b").unwrap());
+        message.fields[2].message = Some(decode_message(b"\x12\x03x&y").unwrap());
+        
+        let html = parser.render_fields(&message.fields, "root", OutputFormat::Html).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"), "{}", html);
+        assert!(html.ends_with("</html>"), "{}", html);
+        assert!(!html.contains('\x1b'), "{}", html);
+        assert!(html.contains("<span class=\"name\">&lt;id&gt;</span> = <span class=\"value wt-varint\">150</span>"), "{}", html);
+        assert!(html.contains("&quot;a&lt;b&quot;"), "{}", html);
+        assert!(html.contains("&quot;x&amp;y&quot;"), "{}", html);
+        assert_eq!(html.matches("<details").count(), 2);
+        assert_eq!(html.matches("</details>").count(), 2);
+        assert_eq!(html.matches("<div").count(), html.matches("</div>").count());
+    }
+
+    #[test]
+    fn test_wire_type_stats() {
+        let mut parser = Parser::new();
+        parser.parse_message(crate::PROTOBUF_EXAMPLE, "root").unwrap();
+        assert_eq!(parser.stats.count(WireType::Varint), 3);
+        assert_eq!(parser.stats.count(WireType::Chunk), 7);
+        assert_eq!(parser.stats.count(WireType::Bit32), 1);
+        assert_eq!(parser.stats.count(WireType::Bit64), 0);
+        assert_eq!(parser.stats.total_bytes, crate::PROTOBUF_EXAMPLE.len());
+        assert_eq!(parser.stats.max_depth, 1);
+        
+        // 每次解析重新统计
+        parser.parse_message(b"\x08\x01", "root").unwrap();
+        assert_eq!(parser.stats.count(WireType::Varint), 1);
+        assert_eq!(parser.stats.count(WireType::Chunk), 0);
+        assert_eq!(parser.stats.max_depth, 0);
+    }
+
+    #[test]
+    fn test_expand_collapsed_path() {
+        // {1: {2: {3: {4: 150}}}}
+        let payload = b"\x0a\x07\x12\x05\x1a\x03\x20\x96\x01";
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("4 <varint> = 150"), "{}", result);
+        
+        parser.options.max_display_depth = Some(1);
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("2 <chunk> = message (5 bytes, collapsed: expand path 1.2)"), "{}", result);
+        assert!(!result.contains("150"), "{}", result);
+        
+        let expanded = strip_ansi(&parser.expand_path(payload, &[(1, 0), (2, 0)]).unwrap().unwrap());
+        assert!(expanded.contains("3 <chunk> = message:"), "{}", expanded);
+        assert!(expanded.contains("4 <varint> = 150"), "{}", expanded);
+        
+        // 展开后的偏移量仍然是在整个输入中的位置
+        parser.options.show_offsets = true;
+        let expanded = strip_ansi(&parser.expand_path(payload, &[(1, 0), (2, 0)]).unwrap().unwrap());
+        assert!(expanded.contains("@0x4-0x9 3 <chunk> = message:"), "{}", expanded);
+        assert!(expanded.contains("@0x6-0x9 4 <varint> = 150"), "{}", expanded);
+        parser.options.show_offsets = false;
+        
+        // 展开后的消息仍然受显示深度限制，提示的是完整路径
+        let expanded = strip_ansi(&parser.expand_path(payload, &[(1, 0)]).unwrap().unwrap());
+        assert!(expanded.contains("collapsed: expand path 1.2.3"), "{}", expanded);
+        
+        assert_eq!(parser.expand_path(payload, &[(1, 0), (5, 0)]).unwrap(), None);
+        
+        // 重复的子消息用下标区分，提示中的路径可以直接用来展开
+        let item = |value| Payload::new().field(2).message(Payload::new().field(3).message(Payload::new().field(4).varint(value)));
+        let payload = Payload::new().field(1).message(item(1).field(5).varint(7).raw(&item(150).build())).build();
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(result.contains("collapsed: expand path 1.2)"), "{}", result);
+        assert!(result.contains("collapsed: expand path 1.2[1])"), "{}", result);
+        let path = parse_expand_path("1.2[1]").unwrap();
+        assert_eq!(path, [(1, 0), (2, 1)]);
+        assert_eq!(format_expand_path(&path), "1.2[1]");
+        let expanded = strip_ansi(&parser.expand_path(&payload, &path).unwrap().unwrap());
+        assert!(expanded.contains("4 <varint> = 150"), "{}", expanded);
+        assert_eq!(parser.expand_path(&payload, &[(1, 0), (2, 2)]).unwrap(), None);
+        assert_eq!(parse_expand_path("1.2["), None);
+        assert_eq!(parse_expand_path("1.x"), None);
+    }
+
+    #[test]
+    fn test_repeated_any() {
+        let any_entry = |type_url: &str, value: &[u8]| {
+            let mut any = vec![0x0a, type_url.len() as u8];
+            any.extend(type_url.as_bytes());
+            any.extend([0x12, value.len() as u8]);
+            any.extend(value);
+            let mut entry = vec![0x0a, any.len() as u8];
+            entry.extend(any);
+            entry
+        };
+        let mut payload = any_entry("type.googleapis.com/demo.User", b"\x0a\x03abc");
+        payload.extend(any_entry("type.googleapis.com/shop.Order", b"\x08\x96\x01"));
+        payload.extend(any_entry("type.googleapis.com/demo.Unknown", b"\x08\x01"));
+        
+        let mut parser = Parser::new();
+        parser.types.get_mut("root").unwrap()
+            .insert(1, ("any".into(), "events".into()));
+        parser.types.insert("demo.User".to_string(), HashMap::from([(1, ("string".into(), "name".into()))]));
+        parser.types.insert("Order".to_string(), HashMap::from([(1, ("uint64".into(), "amount".into()))]));
+        
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(result.contains("1 events (3 items) = [\n        Any<type.googleapis.com/demo.User>:\n            1 name = \"abc\""), "{}", result);
+        assert!(result.contains("\n        Any<type.googleapis.com/shop.Order>:\n            1 amount = 150"), "{}", result);
+        assert!(result.contains("\n        Any<type.googleapis.com/demo.Unknown> (unknown type):\n            1 <varint> = 1"), "{}", result);
+    }
+
+    #[test]
+    fn test_child_exceeds_parent() {
+        // field 1 = {2: 声明长度10，但父消息只剩3字节}，后面的field 3属于顶层消息
+        let payload = b"\x0a\x05\x12\x0a\x08\x96\x01\x18\x01\x18\x01\x18\x01\x18\x01";
+        let mut parser = Parser::new();
+        
+        let mut cursor = Cursor::new(&payload[2..7]);
+        let (_, wire_type) = read_identifier(&mut cursor).unwrap().unwrap();
+        assert!(matches!(parser.read_field_value(&mut cursor, wire_type, 1), Err(core::Error::ChildExceedsParent)));
+        assert!(matches!(parser.expand_path(payload, &[(1, 0), (2, 0)]), Err(core::Error::ChildExceedsParent)));
+        
+        // 不会读到父消息之外的兄弟字段
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("1 <chunk> = bytes (5)"), "{}", result);
+        assert!(result.contains("3 <varint> (4 items) = ["), "{}", result);
+        assert_eq!(result.lines().filter(|line| *line == "        1").count(), 4, "{}", result);
+    }
+
+    #[test]
+    fn test_show_wire_details() {
+        // 200字节的chunk需要2字节的长度前缀
+        let mut payload = b"\x0a\xc8\x01".to_vec();
+        payload.extend([b'a'; 200]);
+        payload.extend(b"\x12\x03abc");
+        
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(!result.contains("prefix"), "{}", result);
+        
+        parser.options.show_wire_details = true;
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(result.contains("1 <chunk> [len=200 (2-byte prefix)] = "), "{}", result);
+        assert!(result.contains("2 <chunk> [len=3 (1-byte prefix)] = \"abc\""), "{}", result);
+    }
+
+    #[test]
+    fn test_wire_type_mismatches() {
+        let mut parser = Parser::new();
+        parser.load_schema("[root]\n1 = [\"string\", \"name\"]\n").unwrap();
+        
+        // field 1声明为string但是varint，field 2先后为varint和chunk，嵌套消息中的field 3同样检查
+        let payload = Payload::new()
+            .field(1).varint(5)
+            .field(2).varint(1).string("abc")
+            .field(4).message(Payload::new().field(3).varint(1).fixed32(2))
+            .build();
+        parser.parse_message(&payload, "root").unwrap();
+        let mismatches: Vec<(&str, u32)> = parser.wire_type_mismatches.iter().map(|(t, n)| (t.as_str(), *n)).collect();
+        assert_eq!(mismatches, [("message", 3), ("root", 1), ("root", 2)]);
+        assert_eq!(parser.warnings, [
+            "wire type mismatch for field 1 in root",
+            "wire type mismatch for field 2 in root",
+            "wire type mismatch for field 3 in message",
+        ]);
+        
+        parser.parse_message(b"\x10\x01", "root").unwrap();
+        assert!(parser.wire_type_mismatches.is_empty());
+        assert!(parser.warnings.is_empty());
+
+        // ISO代码可以是数字（varint）也可以是字母（chunk），repeated时字母代码不按packed拆分
+        parser.load_schema("[root]\n3 = [\"iso_country repeated\", \"countries\"]\n").unwrap();
+        let payload = Payload::new().field(3).string("JP").string("USA").build();
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(result.contains("\"JP\" (Japan)"), "{}", result);
+        assert!(result.contains("\"USA\" (United States)"), "{}", result);
+        assert!(parser.wire_type_mismatches.is_empty());
+        let result = strip_ansi(&parser.parse_message(&Payload::new().field(3).varint(840).build(), "root").unwrap());
+        assert!(result.contains("840 (United States)"), "{}", result);
+        assert!(parser.wire_type_mismatches.is_empty());
+    }
+
+    #[test]
+    fn test_group_repeated_fields() {
+        // {3: "a", 3: 5, 3: "b", 4: 1, 3: "c"}，只有连续出现的合并
+        let payload = Payload::new()
+            .field(3).string("a").varint(5).string("b")
+            .field(4).varint(1)
+            .field(3).string("c")
+            .build();
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert_eq!(result, "root:\n    \
+            3 <chunk> (3 items) = [\n        \
+                \"a\"\n        \
+                <varint> = 5 (wire type mismatch)\n        \
+                \"b\"\n    \
+            ]\n    \
+            4 <varint> = 1\n    \
+            3 <chunk> = \"c\"");
+        
+        parser.options.show_offsets = true;
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(result.contains("3 <chunk> (3 items) = [\n        @0x0-0x3 \"a\"\n        @0x3-0x5 <varint> = 5"), "{}", result);
+    }
+
+    #[test]
+    fn test_show_offsets() {
+        // {1: 150, 2: {1: "abc"}, 3 <group> {1: 1}}
+        let payload = Payload::new()
+            .field(1).varint(150)
+            .field(2).message(Payload::new().field(1).string("abc"))
+            .field(3).group(Payload::new().field(1).varint(1))
+            .build();
+        let mut parser = Parser::new();
+        parser.options.show_offsets = true;
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(result.contains("@0x0-0x3 1 <varint> = 150"), "{}", result);
+        assert!(result.contains("@0x3-0xa 2 <chunk> = message:\n        @0x5-0xa 1 <chunk> = \"abc\""), "{}", result);
+        assert!(result.contains("@0xa-0xe 3 <group> = group:\n        @0xb-0xd 1 <varint> = 1"), "{}", result);
+        
+        // 流式输出和多条消息中的位置同样相对于整个输入
+        let mut out = Vec::new();
+        parser.write_message(&mut &payload[..], "root", &mut out, false).unwrap();
+        assert!(strip_ansi(&String::from_utf8(out).unwrap()).contains("@0x5-0xa 1 <chunk> = \"abc\""));
+        let result = strip_ansi(&parser.parse_delimited_messages(b"\x02\x08\x01\x02\x08\x02", &["root"]).unwrap());
+        assert!(result.contains("@0x4-0x6 1 <varint> = 2"), "{}", result);
+    }
+    
+    #[test]
+    fn test_deprecated_field() {
+        let mut parser = Parser::new();
+        parser.load_schema("[root]\n1 = [\"int32 deprecated\", \"old_id\"]\n2 = \"id\"\n").unwrap();
+        
+        let result = strip_ansi(&parser.parse_message(b"\x10\x02", "root").unwrap());
+        assert!(!result.contains("deprecated"), "{}", result);
+        assert!(parser.warnings.is_empty());
+        
+        let result = strip_ansi(&parser.parse_message(b"\x08\x01\x10\x02\x08\x03", "root").unwrap());
+        assert!(result.contains("1 old_id (deprecated) = 1"), "{}", result);
+        assert!(result.contains("2 id = 2"), "{}", result);
+        assert_eq!(parser.warnings, vec!["deprecated field old_id (1) found in root".to_string()]);
+    }
+    
+    #[test]
+    fn test_overridden_scalar_field() {
+        let mut parser = Parser::new();
+        parser.load_schema("[root]\n1 = [\"uint32\", \"id\"]\n2 = [\"uint32 repeated\", \"tags\"]\n3 = [\"string\", \"name\"]\n").unwrap();
+        
+        // id出现两次，tags和只出现一次的name不做标注
+        let result = strip_ansi(&parser.parse_message(b"\x08\x01\x10\x05\x1a\x01a\x10\x06\x08\x02", "root").unwrap());
+        assert!(result.contains("1 id = 1 (overridden)"), "{}", result);
+        assert!(result.contains("1 id = 2 (effective)"), "{}", result);
+        assert!(result.contains("2 tags = 5\n"), "{}", result);
+        assert!(result.contains("2 tags = 6\n"), "{}", result);
+        assert!(result.contains("3 name = \"a\"\n"), "{}", result);
+        
+        let result = strip_ansi(&parser.parse_message(b"\x08\x01", "root").unwrap());
+        assert!(!result.contains("effective"), "{}", result);
+    }
+    
+    #[test]
+    fn test_map_fields() {
+        let mut parser = Parser::new();
+        parser.load_schema("[root]\n1 = [\"map<string,string>\", \"labels\"]\n2 = [\"map<int32,message>\", \"children\"]\n").unwrap();
+        
+        // labels: {"env": "prod", "team": "core"}
+        let payload = Payload::new()
+            .field(1)
+            .message(Payload::new().field(1).string("env").field(2).string("prod"))
+            .message(Payload::new().field(1).string("team").field(2).string("core"))
+            .field(2)
+            .message(Payload::new().field(1).varint(7).field(2).message(Payload::new().field(1).string("abc").field(2).varint(3)))
+            .build();
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(result.contains("1 labels (2 items) = [\n        \"env\" => \"prod\"\n        \"team\" => \"core\"\n    ]"), "{}", result);
+        assert!(result.contains("2 children = 7 => message:\n        1 <chunk> = \"abc\"\n        2 <varint> = 3"), "{}", result);
+        
+        // 没有schema时，每一项都是{1: key, 2: value}的repeated字段猜测为map
+        let mut parser = Parser::new();
+        let payload = Payload::new()
+            .field(1)
+            .message(Payload::new().field(1).string("a").field(2).varint(1))
+            .message(Payload::new().field(1).string("b").field(2).varint(2))
+            .build();
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(result.contains("1 <map> (2 items) = [\n        \"a\" => 1\n        \"b\" => 2\n    ]"), "{}", result);
+        
+        // key重复或者只出现一次时不是map
+        let payload = Payload::new()
+            .field(1).message(Payload::new().field(1).varint(1).field(2).varint(2))
+            .message(Payload::new().field(1).varint(1).field(2).varint(3))
+            .field(2).message(Payload::new().field(1).string("x").field(2).varint(2))
+            .build();
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(!result.contains("<map>"), "{}", result);
+    }
+
+    #[test]
+    fn test_merge_packed_and_unpacked() {
+        let mut parser = Parser::new();
+        parser.load_schema("[root]\n1 = [\"sint32 repeated\", \"deltas\"]\n2 = [\"fixed32 repeated\", \"ids\"]\n").unwrap();
+        
+        // deltas: packed [1, -1]，然后是非packed的2和packed的[-2]；ids: 非packed的7，然后packed的[8]
+        let payload = b"\x0a\x02\x02\x01\x15\x07\x00\x00\x00\x08\x04\x0a\x01\x03\x12\x04\x08\x00\x00\x00";
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("1 deltas = [1, -1, 2, -2]"), "{}", result);
+        assert!(result.contains("2 ids = [7, 8]"), "{}", result);
+        assert_eq!(result.lines().count(), 3, "{}", result);
+        assert!(parser.wire_type_mismatches.is_empty());
+        
+        // 没有packed的出现时仍然逐行显示
+        let result = strip_ansi(&parser.parse_message(b"\x08\x02\x08\x04", "root").unwrap());
+        assert!(result.contains("1 deltas (2 items) = [\n        1\n        2\n    ]"), "{}", result);
+        assert!(!result.contains("deltas = ["), "{}", result);
+    }
+    
+    #[test]
+    fn test_map_with_message_values() {
+        let mut parser = Parser::new();
+        parser.load_schema("[root]\n1 = [\"map<string, User>\", \"users\"]\n[User]\n1 = [\"string\", \"name\"]\n2 = [\"uint32\", \"age\"]\n").unwrap();
+        
+        // {"alice": {name: "Alice", age: 30}, "bob": {name: "Bob"}}
+        let payload = b"\x0a\x12\x0a\x05alice\x12\x09\x0a\x05Alice\x10\x1e\x0a\x0c\x0a\x03bob\x12\x05\x0a\x03Bob";
+        let result = strip_ansi(&parser.parse_message(payload, "root").unwrap());
+        assert!(result.contains("1 users (2 items) = [\n        \"alice\" => User:\n            1 name = \"Alice\"\n            2 age = 30"), "{}", result);
+        assert!(result.contains("\n        \"bob\" => User:\n            1 name = \"Bob\""), "{}", result);
+        
+        // 直接声明为消息类型的字段同样按schema解析
+        parser.load_schema("[root]\n2 = [\"User\", \"owner\"]\n").unwrap();
+        let result = strip_ansi(&parser.parse_message(b"\x12\x05\x0a\x03Eve", "root").unwrap());
+        assert!(result.contains("2 owner = User:\n        1 name = \"Eve\""), "{}", result);
+    }
+    
+    #[test]
+    fn test_well_known_duration() {
+        let mut parser = Parser::new();
+        parser.load_schema("[root]\n1 = [\"google.protobuf.Duration\", \"timeout\"]\n").unwrap();
+        let payload = Payload::new()
+            .field(1).message(Payload::new().field(1).varint(3).field(2).varint(500_000_000))
+            .build();
+        let result = strip_ansi(&parser.parse_message(&payload, "root").unwrap());
+        assert!(result.contains("1 timeout = 3.500s google.protobuf.Duration:\n        1 seconds = 3\n        2 nanos = 500000000"), "{}", result);
+    }
+    
+    #[test]
+    fn test_parse_grpc_status() {
+        // code = 5, message = "user not found",
+        // details = [Any<type.googleapis.com/google.rpc.ErrorInfo>{reason = "NO_USER", domain = "demo"}]
+        let mut payload = b"\x08\x05\x12\x0euser not found".to_vec();
+        let error_info = b"\x0a\x07NO_USER\x12\x04demo";
+        let type_url = b"type.googleapis.com/google.rpc.ErrorInfo";
+        payload.extend([0x1a, (type_url.len() + error_info.len() + 4) as u8, 0x0a, type_url.len() as u8]);
+        payload.extend(type_url);
+        payload.extend([0x12, error_info.len() as u8]);
+        payload.extend(error_info);
+        
+        let mut parser = Parser::new();
+        let result = strip_ansi(&parser.parse_grpc_status(&payload).unwrap());
+        assert!(result.starts_with("google.rpc.Status:"), "{}", result);
+        assert!(result.contains("1 code = 5 (NOT_FOUND)"), "{}", result);
+        assert!(result.contains("2 message = \"user not found\""), "{}", result);
+        assert!(result.contains("details = Any<type.googleapis.com/google.rpc.ErrorInfo>:"), "{}", result);
+        assert!(result.contains("1 reason = \"NO_USER\""), "{}", result);
+        
+        // grpc-status-details-bin中常见的无填充base64
+        let result = strip_ansi(&parser.parse_grpc_status(b"CAUSDnVzZXIgbm90IGZvdW5k").unwrap());
+        assert!(result.contains("1 code = 5 (NOT_FOUND)"), "{}", result);
+        assert!(result.contains("2 message = \"user not found\""), "{}", result);
+    }
+    
+    #[test]
+    fn test_write_message_streaming() {
+        let mut payload = Vec::new();
+        for i in 0..2000u32 {
+            payload.extend([0x08]);
+            payload.extend(encode_varint(i as u64));
+            payload.extend(b"\x12\x05hello\x1a\x03\x08\x96\x01");
+        }
+        
+        let mut parser = Parser::new();
+        let expected = parser.parse_message(&payload, "root").unwrap();
+        let mut output = Vec::new();
+        parser.write_message(&mut payload.as_slice(), "root", &mut output, false).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), format!("{}\n", expected));
+        assert_eq!(parser.stats.count(WireType::Varint), 2000 * 2);
+        assert_eq!(parser.stats.total_bytes, payload.len());
+        
+        // 每个字段前是它自己的原始字节
+        let mut output = Vec::new();
+        parser.write_message(&mut &payload[..14], "root", &mut output, true).unwrap();
+        let output = strip_ansi(&String::from_utf8(output).unwrap());
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[1], format!("    # 0000   08 00{}  ..", "   ".repeat(22)));
+        assert_eq!(lines[2], "    1 <varint> = 0");
+        assert!(lines[3].starts_with("    # 0000   12 05 68 65 6C 6C 6F "), "{}", output);
+        assert_eq!(lines[4], "    2 <chunk> = \"hello\"");
+        
+        let mut output = Vec::new();
+        assert!(parser.write_message(&mut &b"\x0a\x05ab"[..], "root", &mut output, false).is_err());
+    }
+    
+    #[test]
+    fn test_parse_to_tree() {
+        // {1: 150, 2: {1: 1, 2: "abc"}, 3: "xyz"}
+        let payload = b"\x08\x96\x01\x12\x07\x08\x01\x12\x03abc\x1a\x03xyz";
+        let mut parser = Parser::new();
+        parser.load_schema("[root]\n1 = [\"sint32\", \"delta\"]\n3 = [\"string\", \"name\"]\n").unwrap();
+        
+        let message = parser.parse_to_tree(payload, "root").unwrap();
+        let types: Vec<(u32, &str)> = message.fields.iter().map(|f| (f.number, f.type_name.as_str())).collect();
+        assert_eq!(types, [(1, "sint32"), (2, "message"), (3, "string")]);
+        assert_eq!(message.fields[0].raw, b"\x96\x01");
+        
+        let nested = message.fields[1].message.as_ref().unwrap();
+        let types: Vec<(u32, &str)> = nested.fields.iter().map(|f| (f.number, f.type_name.as_str())).collect();
+        assert_eq!(types, [(1, "varint"), (2, "chunk")]);
+        assert_eq!(nested.fields[1].raw, b"abc");
+        assert_eq!(message.fields[1].range, 3..12);
+        assert_eq!(nested.fields[1].range, 7..12);
+        
+        // 与文本输出使用同一棵树
+        let result = parser.render_fields(&message.fields, "root", OutputFormat::Text).unwrap();
+        assert!(strip_ansi(&result).contains("1 delta = 75"), "{}", result);
+        
+        parser.options.no_nested = true;
+        assert!(parser.parse_to_tree(payload, "root").unwrap().fields[1].message.is_none());
+        
+        // 嵌套层数受max_depth限制
+        let payload = Payload::new()
+            .field(2).message(Payload::new().field(2).message(Payload::new().field(1).varint(1).field(2).varint(2)))
+            .build();
+        let mut parser = Parser::new().with_max_depth(1);
+        let message = parser.parse_to_tree(&payload, "root").unwrap();
+        let nested = message.fields[0].message.as_ref().unwrap();
+        assert!(nested.fields[0].message.is_none());
+        parser.max_depth = 2;
+        let message = parser.parse_to_tree(&payload, "root").unwrap();
+        assert!(message.fields[0].find_all(2)[0].message.is_some());
+        
+        // schema中声明为消息的字段按声明的类型展开
+        let mut parser = Parser::new();
+        parser.load_schema("[root]\n2 = [\"User\", \"user\"]\n[User]\n1 = [\"string\", \"name\"]\n").unwrap();
+        let payload = Payload::new().field(2).message(Payload::new().field(1).string("ab")).build();
+        let message = parser.parse_to_tree(&payload, "root").unwrap();
+        assert_eq!(message.fields[0].type_name, "User");
+        let children = message.fields[0].message.as_ref().unwrap();
+        assert_eq!(children.fields[0].type_name, "string");
+        let result = strip_ansi(&parser.render_fields(&message.fields, "root", OutputFormat::Text).unwrap());
+        assert!(result.contains("1 name = \"ab\""), "{}", result);
+    }
+    
+    #[test]
+    fn test_parse_message_with_len() {
+        let message = b"\x08\x96\x01\x12\x03abc";
+        let mut parser = Parser::new();
+        
+        // 末尾只有StartGroup、group没有结束、多余的EndGroup都不算在消息内
+        for trailing in [&b""[..], b"\x00\x00", b"\xff\xff\xff", b"\x12\x10short", b"\x1b", b"\x1b\x08\x01", b"\x1c"] {
+            let data = [&message[..], trailing].concat();
+            let (result, consumed) = parser.parse_message_with_len(&data, "root").unwrap();
+            assert_eq!(consumed, message.len());
+            assert_eq!(result, parser.parse_message(message, "root").unwrap());
+        }
+        
+        // 完整的group照常计入
+        let data = [&message[..], b"\x1b\x08\x01\x1c\xff"].concat();
+        assert_eq!(parser.parse_message_with_len(&data, "root").unwrap().1, message.len() + 4);
     }
 }
@@ -0,0 +1,592 @@
+use crate::core::is_valid_field_number;
+use crate::well_known::WELL_KNOWN_TYPES;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// 指定schema文件路径的环境变量
+pub const SCHEMA_ENV: &str = "PROTOBUF_INSPECTOR_SCHEMA";
+
+/// 消息名 -> field number -> (类型, 字段名)，与`Parser::types`的结构一致
+///
+/// 类型和字段名使用`Arc<str>`，解析时可以直接借出或共享，不需要逐个字段复制
+pub type Schema = HashMap<String, HashMap<u32, (Arc<str>, Arc<str>)>>;
+
+/// schema文件的错误，行号从1开始
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    Syntax(usize),
+    InvalidFieldNumber(usize),
+    FieldOutsideMessage(usize),
+    /// 没有`option allow_alias = true;`时，enum中出现了重复的值
+    DuplicateEnumValue(usize),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Syntax(line) => write!(f, "syntax error at line {}", line),
+            SchemaError::InvalidFieldNumber(line) => write!(f, "invalid field number at line {}", line),
+            SchemaError::FieldOutsideMessage(line) => write!(f, "field outside of a [message] section at line {}", line),
+            SchemaError::DuplicateEnumValue(line) => write!(f, "duplicate enum value without allow_alias at line {}", line),
+        }
+    }
+}
+
+/// 启动时需要加载的schema文件
+///
+/// 优先使用`$PROTOBUF_INSPECTOR_SCHEMA`，其次是`~/.protobuf-inspector.toml`；
+/// 后者不存在时返回`None`
+pub fn default_schema_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(SCHEMA_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    let path = PathBuf::from(home).join(".protobuf-inspector.toml");
+    path.exists().then_some(path)
+}
+
+/// 解析TOML格式的schema，只支持下面这个子集：
+///
+/// ```toml
+/// # 每个消息一个section
+/// [root]
+/// 1 = "id"                  # 只指定字段名，类型按线类型推断
+/// 2 = ["fixed32 be", "ts"]  # [类型, 字段名]
+/// ```
+pub fn parse_schema(text: &str) -> Result<Schema, SchemaError> {
+    let mut schema = Schema::new();
+    let mut current = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(section) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            let section = section.trim();
+            let name = match section.strip_prefix('"') {
+                Some(_) => read_string(section).ok_or(SchemaError::Syntax(line_number))?.0,
+                None => section.to_string(),
+            };
+            if name.is_empty() {
+                return Err(SchemaError::Syntax(line_number));
+            }
+            schema.entry(name.clone()).or_default();
+            current = Some(name);
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').ok_or(SchemaError::Syntax(line_number))?;
+        let number = key.trim().parse::<u32>()
+            .ok()
+            .filter(|&n| is_valid_field_number(n))
+            .ok_or(SchemaError::InvalidFieldNumber(line_number))?;
+        let (field_type, name) = parse_field(value.trim()).ok_or(SchemaError::Syntax(line_number))?;
+        let message = current.as_ref().ok_or(SchemaError::FieldOutsideMessage(line_number))?;
+        schema.get_mut(message).unwrap().insert(number, (field_type.into(), name.into()));
+    }
+
+    Ok(schema)
+}
+
+/// `"name"`或`["type", "name"]`
+fn parse_field(value: &str) -> Option<(String, String)> {
+    if let Some(items) = value.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        let (field_type, rest) = read_string(items.trim_start())?;
+        let rest = rest.trim_start().strip_prefix(',')?;
+        let (name, rest) = read_string(rest.trim_start())?;
+        if !rest.trim().is_empty() {
+            return None;
+        }
+        return Some((normalize_packed(field_type)?, name));
+    }
+
+    let (name, rest) = read_string(value)?;
+    rest.trim().is_empty().then(|| ("message".to_string(), name))
+}
+
+/// 旧的`packed int32`写法等同于`int32 repeated`，只写`packed`而没有元素类型时视为错误
+fn normalize_packed(field_type: String) -> Option<String> {
+    let mut words = field_type.split_whitespace();
+    if words.next() != Some("packed") {
+        return Some(field_type);
+    }
+    let element = words.collect::<Vec<_>>().join(" ");
+    (!element.is_empty()).then(|| format!("{} repeated", element))
+}
+
+/// 读取开头的双引号字符串，返回内容和剩余部分
+fn read_string(text: &str) -> Option<(String, &str)> {
+    let mut chars = text.strip_prefix('"')?.char_indices();
+    let mut result = String::new();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '"' => return Some((result, &text[offset + 2..])),
+            '\\' => result.push(match chars.next()?.1 {
+                'n' => '\n',
+                't' => '\t',
+                c @ ('"' | '\\') => c,
+                _ => return None,
+            }),
+            c => result.push(c),
+        }
+    }
+    None
+}
+
+/// 去掉字符串外的`#`注释
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (offset, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..offset],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// `.proto`中的enum定义：名称和按声明顺序排列的值
+pub type EnumDefinition = (String, Vec<(i64, String)>);
+
+/// 只读取`.proto`文件中的`enum Name { NAME = N; }`定义，忽略其他内容。
+/// 嵌套在message中的enum同样只使用它自己的名称
+pub fn parse_enum_proto(text: &str) -> Result<Vec<EnumDefinition>, SchemaError> {
+    let tokens = tokenize_proto(text);
+    let mut enums = Vec::new();
+    let mut index = 0;
+    
+    while index < tokens.len() {
+        let is_enum = tokens[index].0 == "enum"
+            && tokens.get(index + 2).is_some_and(|(t, _)| t == "{");
+        if !is_enum {
+            index += 1;
+            continue;
+        }
+        
+        let name = tokens[index + 1].0.clone();
+        index += 3;
+        let mut values: Vec<(i64, String, usize)> = Vec::new();
+        let mut allow_alias = false;
+        
+        loop {
+            let Some((token, line)) = tokens.get(index) else {
+                return Err(SchemaError::Syntax(tokens.last().map_or(1, |(_, line)| *line)));
+            };
+            // 读到下一个`;`为止
+            let statement_end = tokens[index..].iter()
+                .position(|(t, _)| t == ";" || t == "}")
+                .map(|offset| index + offset);
+            match token.as_str() {
+                "}" => {
+                    index += 1;
+                    break;
+                }
+                ";" => index += 1,
+                "option" | "reserved" => {
+                    let end = statement_end
+                        .filter(|&end| tokens[end].0 == ";")
+                        .ok_or(SchemaError::Syntax(*line))?;
+                    let statement: Vec<&str> = tokens[index..end].iter().map(|(t, _)| t.as_str()).collect();
+                    if statement == ["option", "allow_alias", "=", "true"] {
+                        allow_alias = true;
+                    }
+                    index = end + 1;
+                }
+                _ => {
+                    let end = statement_end.ok_or(SchemaError::Syntax(*line))?;
+                    if tokens[end].0 != ";" || end < index + 3 || tokens[index + 1].0 != "=" {
+                        return Err(SchemaError::Syntax(*line));
+                    }
+                    // 值后面可以跟`[deprecated = true]`之类的选项
+                    let value = parse_proto_integer(&tokens[index + 2].0).ok_or(SchemaError::Syntax(*line))?;
+                    values.push((value, token.clone(), *line));
+                    index = end + 1;
+                }
+            }
+        }
+        
+        if !allow_alias {
+            for (i, (value, _, line)) in values.iter().enumerate() {
+                if values[..i].iter().any(|(v, _, _)| v == value) {
+                    return Err(SchemaError::DuplicateEnumValue(*line));
+                }
+            }
+        }
+        enums.push((name, values.into_iter().map(|(value, name, _)| (value, name)).collect()));
+    }
+    
+    Ok(enums)
+}
+
+/// `.proto`中的标量类型，与同名的内置类型对应
+const PROTO_SCALARS: &[&str] = &[
+    "double", "float", "int32", "int64", "uint32", "uint64", "sint32", "sint64",
+    "fixed32", "fixed64", "sfixed32", "sfixed64", "bool", "string", "bytes",
+];
+
+/// 读取`.proto`文件中的message定义，生成与`parse_schema`结构相同的schema
+///
+/// 嵌套的message以`Outer.Inner`命名，oneof中的字段直接属于所在的message，
+/// enum、service和各种option会被跳过。字段引用的类型由内向外按作用域查找，
+/// 找不到的类型（例如来自import的文件）使用`message`，按猜测展开
+pub fn parse_message_proto(text: &str) -> Result<Schema, SchemaError> {
+    let tokens = tokenize_proto(text);
+    let enums: Vec<String> = parse_enum_proto(text)?.into_iter().map(|(name, _)| name).collect();
+    let line_at = |index: usize| tokens.get(index).or(tokens.last()).map_or(1, |(_, line)| *line);
+    let statement_end = |index: usize| tokens[index..].iter()
+        .position(|(t, _)| t == ";")
+        .map(|offset| index + offset)
+        .ok_or(SchemaError::Syntax(line_at(index)));
+    
+    let mut messages = Vec::new();
+    // (所在的message, field number, 字段名, 是否repeated, 是否废弃, map的key类型, 类型)
+    let mut fields = Vec::new();
+    // 每一层大括号对应的message，oneof为None
+    let mut scopes: Vec<Option<String>> = Vec::new();
+    let mut package = None;
+    let mut index = 0;
+    
+    while index < tokens.len() {
+        let token = tokens[index].0.as_str();
+        let opens_block = tokens.get(index + 2).is_some_and(|(t, _)| t == "{");
+        let current = scopes.iter().rev().find_map(Clone::clone);
+        match token {
+            ";" => index += 1,
+            "}" => {
+                scopes.pop().ok_or(SchemaError::Syntax(line_at(index)))?;
+                index += 1;
+            }
+            "message" if opens_block => {
+                let name = match &current {
+                    Some(outer) => format!("{}.{}", outer, tokens[index + 1].0),
+                    None => tokens[index + 1].0.clone(),
+                };
+                messages.push(name.clone());
+                scopes.push(Some(name));
+                index += 3;
+            }
+            "oneof" if opens_block && current.is_some() => {
+                scopes.push(None);
+                index += 3;
+            }
+            "enum" | "service" | "extend" => index = skip_proto_block(&tokens, index)?,
+            "package" if scopes.is_empty() => {
+                let end = statement_end(index)?;
+                package = tokens.get(index + 1).filter(|_| end == index + 2).map(|(t, _)| t.clone());
+                index = end + 1;
+            }
+            "syntax" | "edition" | "import" | "option" | "reserved" | "extensions" => index = statement_end(index)? + 1,
+            _ => {
+                let line = tokens[index].1;
+                let message = current.ok_or(SchemaError::FieldOutsideMessage(line))?;
+                let end = statement_end(index)?;
+                let mut statement: Vec<&str> = tokens[index..end].iter().map(|(t, _)| t.as_str()).collect();
+                
+                let repeated = statement.first() == Some(&"repeated");
+                if matches!(statement.first(), Some(&("repeated" | "optional" | "required"))) {
+                    statement.remove(0);
+                }
+                let (key_type, statement) = match statement.as_slice() {
+                    ["map", "<", key, ",", value, ">", rest @ ..] => (Some(key.to_string()), [&[*value], rest].concat()),
+                    _ => (None, statement),
+                };
+                // 字段后面可以跟`[packed = false]`之类的选项
+                let [field_type, name, "=", number, rest @ ..] = statement.as_slice() else {
+                    return Err(SchemaError::Syntax(line));
+                };
+                if !rest.is_empty() && (rest.len() < 2 || rest[0] != "[" || rest[rest.len() - 1] != "]") {
+                    return Err(SchemaError::Syntax(line));
+                }
+                let deprecated = !rest.is_empty() && rest[1..rest.len() - 1]
+                    .split(|t| *t == ",")
+                    .any(|option| option == ["deprecated", "=", "true"]);
+                let number = parse_proto_integer(number)
+                    .and_then(|n| u32::try_from(n).ok())
+                    .filter(|&n| is_valid_field_number(n))
+                    .ok_or(SchemaError::InvalidFieldNumber(line))?;
+                fields.push((message, number, name.to_string(), repeated, deprecated, key_type, field_type.to_string()));
+                index = end + 1;
+            }
+        }
+    }
+    if !scopes.is_empty() {
+        return Err(SchemaError::Syntax(line_at(tokens.len())));
+    }
+    
+    let mut schema: Schema = messages.iter().map(|name| (name.clone(), HashMap::new())).collect();
+    for (message, number, name, repeated, deprecated, key_type, field_type) in fields {
+        let resolve = |name: &str| resolve_proto_type(name, &message, package.as_deref(), &messages, &enums);
+        let mut field_type = match key_type {
+            Some(key_type) => format!("map<{}, {}>", resolve(&key_type), resolve(&field_type)),
+            None if repeated => format!("{} repeated", resolve(&field_type)),
+            None => resolve(&field_type),
+        };
+        // `[deprecated = true]`对应schema中的`deprecated`修饰词
+        if deprecated {
+            field_type.push_str(" deprecated");
+        }
+        schema.get_mut(&message).unwrap().insert(number, (field_type.into(), name.into()));
+    }
+    Ok(schema)
+}
+
+/// 跳过`enum Name { ... }`这样的块（包括其中嵌套的块），返回块之后的位置
+fn skip_proto_block(tokens: &[(String, usize)], index: usize) -> Result<usize, SchemaError> {
+    let mut depth = 0;
+    for (offset, (token, _)) in tokens[index..].iter().enumerate() {
+        match token.as_str() {
+            "{" => depth += 1,
+            "}" if depth == 1 => return Ok(index + offset + 1),
+            "}" => depth -= 1,
+            _ => {}
+        }
+    }
+    Err(SchemaError::Syntax(tokens.last().map_or(1, |(_, line)| *line)))
+}
+
+/// 把字段引用的类型名转换为schema中的类型：标量保持不变，message从`scope`开始
+/// 由内向外查找，enum只使用它自己的名称
+fn resolve_proto_type(name: &str, scope: &str, package: Option<&str>, messages: &[String], enums: &[String]) -> String {
+    if PROTO_SCALARS.contains(&name) {
+        return name.to_string();
+    }
+    let mut name = name.trim_start_matches('.');
+    if name == "google.protobuf.Any" {
+        return "any".to_string();
+    }
+    if WELL_KNOWN_TYPES.iter().any(|(known, _)| *known == name) {
+        return name.to_string();
+    }
+    if let Some(package) = package {
+        name = name.strip_prefix(package).and_then(|n| n.strip_prefix('.')).unwrap_or(name);
+    }
+    
+    let mut scope = scope;
+    loop {
+        let candidate = if scope.is_empty() { name.to_string() } else { format!("{}.{}", scope, name) };
+        if messages.contains(&candidate) {
+            return candidate;
+        }
+        if scope.is_empty() {
+            break;
+        }
+        scope = scope.rsplit_once('.').map_or("", |(outer, _)| outer);
+    }
+    
+    let last = name.rsplit('.').next().unwrap_or(name);
+    if enums.iter().any(|e| e == last) {
+        return last.to_string();
+    }
+    "message".to_string()
+}
+
+/// 十进制、十六进制或八进制整数，可以带负号
+fn parse_proto_integer(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// 把`.proto`拆成单词和符号，去掉注释，每个token带有所在的行号
+fn tokenize_proto(text: &str) -> Vec<(String, usize)> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '"' | '\'' => {
+                let mut literal = String::from(c);
+                for next in chars.by_ref() {
+                    literal.push(next);
+                    if next == c {
+                        break;
+                    }
+                }
+                tokens.push((literal, line));
+            }
+            c if c.is_alphanumeric() || matches!(c, '_' | '.' | '-') => {
+                let mut word = String::from(c);
+                while let Some(next) = chars.next_if(|&c| c.is_alphanumeric() || matches!(c, '_' | '.')) {
+                    word.push(next);
+                }
+                tokens.push((word, line));
+            }
+            c => tokens.push((c.to_string(), line)),
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::strip_ansi;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_load_schema() {
+        let config = r#"
+            # 登录接口的返回值
+            [root]
+            1 = "user_id"
+            2 = ["string", "nickname # 不是注释"]
+
+            ["Order"]
+            1 = ["uint64", "amount"]
+        "#;
+        let mut parser = Parser::new();
+        parser.load_schema(config).unwrap();
+        assert_eq!(parser.types["Order"][&1], ("uint64".into(), "amount".into()));
+
+        let result = parser.parse_message(b"\x08\x96\x01\x12\x03abc", "root").unwrap();
+        assert!(result.contains("user_id = "), "{}", result);
+        assert!(result.contains("nickname # 不是注释 = "), "{}", result);
+
+        assert_eq!(parse_schema("[root]\n1 = [\"string\"]"), Err(SchemaError::Syntax(2)));
+        assert_eq!(parse_schema("[root]\n1 = [\"packed\", \"ids\"]"), Err(SchemaError::Syntax(2)));
+        assert_eq!(parse_schema("[root]\n1 = [\"packed fixed32 be\", \"ids\"]").unwrap()["root"][&1].0, "fixed32 be repeated".into());
+        assert_eq!(parse_schema("[root]\n19000 = \"x\""), Err(SchemaError::InvalidFieldNumber(2)));
+        assert_eq!(parse_schema("1 = \"x\""), Err(SchemaError::FieldOutsideMessage(1)));
+    }
+
+    #[test]
+    fn test_load_enum_proto() {
+        let proto = r#"
+            syntax = "proto3";
+            
+            message Task {
+                /* 任务状态 */
+                enum State {
+                    option allow_alias = true;
+                    STATE_UNKNOWN = 0;
+                    STATE_RUNNING = 1;
+                    STATE_STARTED = 1 [deprecated = true]; // 旧名称
+                    STATE_FAILED = -1;
+                    reserved 2, 3;
+                }
+                State state = 1;
+            }
+            
+            enum Priority { LOW = 0; HIGH = 0x10; }
+        "#;
+        let mut parser = Parser::new();
+        parser.load_enum_proto(proto).unwrap();
+        parser.types.get_mut("root").unwrap()
+            .insert(1, ("State".into(), "state".into()));
+        parser.types.get_mut("root").unwrap()
+            .insert(2, ("Priority".into(), "priority".into()));
+        
+        let result = parser.parse_message(b"\x08\x01\x10\x10", "root").unwrap();
+        assert!(result.contains("(STATE_RUNNING)"), "{}", result);
+        assert!(result.contains("(HIGH)"), "{}", result);
+        
+        let enums = parse_enum_proto(proto).unwrap();
+        assert_eq!(enums[0].1[3], (-1, "STATE_FAILED".to_string()));
+        
+        let duplicate = "enum E {\n  A = 1;\n  B = 1;\n}";
+        assert_eq!(parse_enum_proto(duplicate), Err(SchemaError::DuplicateEnumValue(3)));
+        assert_eq!(parse_enum_proto("enum E { A = x; }"), Err(SchemaError::Syntax(1)));
+    }
+
+    #[test]
+    fn test_load_message_proto() {
+        let proto = r#"
+            syntax = "proto3";
+            package shop.v1;
+            import "google/protobuf/any.proto";
+            
+            message Order {
+                message Item {
+                    string sku = 1;
+                    uint32 count = 2;
+                }
+                enum Status { UNKNOWN = 0; PAID = 1; }
+                reserved 9 to 11;
+                
+                uint64 id = 1;
+                repeated Item items = 2;
+                map<string, Item> extras = 3 [deprecated = true];
+                oneof payment {
+                    string card = 4;
+                    .shop.v1.Order.Item voucher = 5;
+                }
+                Status status = 6;
+                google.protobuf.Any detail = 7;
+                google.protobuf.Duration timeout = 9;
+                other.External external = 8;
+            }
+        "#;
+        let schema = parse_message_proto(proto).unwrap();
+        let field = |message: &str, number: u32| {
+            let (field_type, name) = &schema[message][&number];
+            (&**field_type, &**name)
+        };
+        assert_eq!(field("Order.Item", 1), ("string", "sku"));
+        assert_eq!(field("Order", 2), ("Order.Item repeated", "items"));
+        assert_eq!(field("Order", 3), ("map<string, Order.Item> deprecated", "extras"));
+        assert_eq!(field("Order", 5), ("Order.Item", "voucher"));
+        assert_eq!(field("Order", 6), ("Status", "status"));
+        assert_eq!(field("Order", 7), ("any", "detail"));
+        assert_eq!(field("Order", 9), ("google.protobuf.Duration", "timeout"));
+        assert_eq!(field("Order", 8), ("message", "external"));
+        
+        let mut parser = Parser::new();
+        parser.load_proto(proto).unwrap();
+        let item = b"\x0a\x02ab\x10\x03";
+        let data = [b"\x08\x07\x12\x06".as_slice(), item, b"\x12\x06", item, b"\x30\x01"].concat();
+        let result = parser.parse_message(&data, "Order").unwrap();
+        assert!(strip_ansi(&result).contains("2 items (2 items) = [\n        Order.Item:"), "{}", result);
+        assert_eq!(result.matches("sku = ").count(), 2, "{}", result);
+        assert!(result.contains("(PAID)"), "{}", result);
+        
+        // 废弃的字段标注(deprecated)并给出警告
+        let result = strip_ansi(&parser.parse_message(&[b"\x1a\x0b\x0a\x01k\x12\x06".as_slice(), item].concat(), "Order").unwrap());
+        assert!(result.contains("extras (deprecated) = \"k\" => Order.Item:"), "{}", result);
+        assert_eq!(parser.warnings, ["deprecated field extras (3) found in Order"]);
+        let schema = parse_message_proto("message A { int32 x = 1 [packed = false, deprecated = true]; int32 y = 2 [deprecated = false]; }").unwrap();
+        assert_eq!(&*schema["A"][&1].0, "int32 deprecated");
+        assert_eq!(&*schema["A"][&2].0, "int32");
+        
+        assert_eq!(parse_message_proto("message A { string x = 0; }"), Err(SchemaError::InvalidFieldNumber(1)));
+        assert_eq!(parse_message_proto("string x = 1;"), Err(SchemaError::FieldOutsideMessage(1)));
+        assert_eq!(parse_message_proto("message A {\n  string x 1;\n}"), Err(SchemaError::Syntax(2)));
+        assert_eq!(parse_message_proto("message A {\n  string x = 1;"), Err(SchemaError::Syntax(2)));
+    }
+}
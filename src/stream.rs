@@ -0,0 +1,485 @@
+use crate::core::is_valid_field_number;
+use crate::guesser::guess_is_message;
+use crate::types::WireType;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+/// 在一段数据上顺序读取，读取失败时位置不变
+#[derive(Debug, Clone)]
+pub struct DataReader<'a> {
+    input: &'a [u8],
+    /// 下一次要读取的数据的索引
+    cursor: usize,
+}
+
+impl<'a> DataReader<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self { input, cursor: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn remains(&self) -> usize {
+        self.input.len() - self.cursor
+    }
+
+    pub fn eof(&self) -> bool {
+        self.remains() == 0
+    }
+
+    pub fn read1(&mut self) -> Option<u8> {
+        let byte = *self.input.get(self.cursor)?;
+        self.cursor += 1;
+        Some(byte)
+    }
+
+    pub fn peek(&self, length: usize) -> Option<&'a [u8]> {
+        self.input.get(self.cursor..self.cursor.checked_add(length)?)
+    }
+
+    pub fn read(&mut self, length: usize) -> Option<&'a [u8]> {
+        let data = self.peek(length)?;
+        self.cursor += length;
+        Some(data)
+    }
+
+    pub fn skip(&mut self, length: usize) -> Option<()> {
+        self.read(length).map(|_| ())
+    }
+
+    pub fn seek(&mut self, position: usize) -> Option<()> {
+        (position <= self.input.len()).then(|| self.cursor = position)
+    }
+
+    /// 读取剩余的全部数据
+    pub fn rest(&mut self) -> &'a [u8] {
+        let data = &self.input[self.cursor..];
+        self.cursor = self.input.len();
+        data
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// 读取varint时数据结束
+    VarintEof,
+    /// 读取定长数据时数据结束，还需要的字节数
+    Eof(usize),
+    InvalidData,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TaggedData {
+    ChunkMetadata { length: u64 },
+    Others, // 数字和SGROUP/EGROUP标记
+}
+
+/// 正在读取的消息：在当前这段数据中的起始位置和长度，根消息没有长度
+#[derive(Debug, Clone)]
+struct MessageContext {
+    start: usize,
+    length: Option<usize>,
+}
+
+impl MessageContext {
+    fn new(start: usize, length: Option<usize>) -> Self {
+        Self { start, length }
+    }
+}
+
+/// 跨越多段数据保存的解析状态，用`ProtobufParser::with_context`在下一段数据上继续解析
+#[derive(Debug, Clone)]
+pub struct ParserContext {
+    messages: Vec<MessageContext>,
+    /// 上一段数据末尾不完整的字段，从tag开始
+    partial: Vec<u8>,
+    /// 跨越多段数据的嵌套消息补全后解出的chunk，还没有返回
+    pending: VecDeque<Vec<u8>>,
+    /// 根消息格式错误，之后的数据不再解析
+    invalid: bool,
+}
+
+impl Default for ParserContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParserContext {
+    pub fn new() -> Self {
+        Self {
+            messages: vec![MessageContext::new(0, None)],
+            partial: Vec::new(),
+            pending: VecDeque::new(),
+            invalid: false,
+        }
+    }
+
+    /// 准备读取下一段数据（例如下一个HTTP body块），`reader`中剩余的数据是一个不完整的字段
+    ///
+    /// 嵌套消息只有完整地出现在一段数据中时才会进入，所以这时只剩下根消息，
+    /// 它没有长度限制，不需要调整位置
+    fn prepare_for_next_slice(&mut self, reader: &mut DataReader) {
+        self.partial.extend_from_slice(reader.rest());
+        self.messages.truncate(1);
+        self.messages[0].start = 0;
+    }
+}
+
+/// 在按片段到达的数据中依次找出叶子chunk，也就是不是嵌套消息的chunk（字符串、bytes等）
+///
+/// 完整出现在一段数据中的chunk直接引用这段数据；跨越了片段边界的字段会被缓存，
+/// 补全后返回复制的数据。例如从socket读取时：
+///
+/// ```
+/// use protobuf_inspector_rs::stream::{DataReader, ParserContext, ProtobufParser};
+///
+/// let mut context = ParserContext::new();
+/// for piece in [&b"\x0a\x03a"[..], b"bc\x12\x01d"] {
+///     let mut parser = ProtobufParser::with_context(DataReader::new(piece), context);
+///     for chunk in parser.by_ref() {
+///         println!("{:?}", chunk);
+///     }
+///     context = parser.into_context();
+/// }
+/// ```
+pub struct ProtobufParser<'a> {
+    reader: DataReader<'a>,
+    context: ParserContext,
+}
+
+impl<'a> ProtobufParser<'a> {
+    pub fn new(reader: DataReader<'a>) -> Self {
+        Self::with_context(reader, ParserContext::new())
+    }
+
+    /// 从上一段数据结束时的状态继续解析
+    pub fn with_context(reader: DataReader<'a>, context: ParserContext) -> Self {
+        Self { reader, context }
+    }
+
+    /// 当前这段数据读完之后取出状态，用于解析下一段数据
+    pub fn into_context(self) -> ParserContext {
+        self.context
+    }
+
+    /// 到目前为止的数据是否恰好在字段的边界结束，并且没有格式错误
+    pub fn is_complete(&self) -> bool {
+        self.context.partial.is_empty() && !self.context.invalid && self.context.messages.len() == 1
+    }
+
+    fn next_token(&mut self) -> Option<Cow<'a, [u8]>> {
+        read_next_token_raw(&mut self.reader, &mut self.context)
+    }
+}
+
+impl<'a> Iterator for ProtobufParser<'a> {
+    type Item = Cow<'a, [u8]>;
+    fn next(&mut self) -> Option<Cow<'a, [u8]>> {
+        self.next_token()
+    }
+}
+
+fn read_varint(reader: &mut DataReader) -> Result<u64, Error> {
+    let mut result: u64 = 0;
+    let mut pos = 0;
+    loop {
+        let x: u8 = reader.read1().ok_or(Error::VarintEof)?;
+        let bits = (x & 0b01111111u8) as u64;
+        result |= bits << (pos * 7);
+        pos += 1;
+        if x & 0b10000000u8 == 0 {
+            break;
+        }
+        if pos == 10 {
+            return Err(Error::InvalidData);
+        }
+    }
+    Ok(result)
+}
+
+fn read_tag(reader: &mut DataReader) -> Result<(u32, WireType), Error> {
+    let tag_varint = read_varint(reader)?;
+    let field_number: u32 = (tag_varint >> 3).try_into().map_err(|_| Error::InvalidData)?;
+    let wire_type = WireType::from_u8((tag_varint & 0b111) as u8).ok_or(Error::InvalidData)?;
+
+    if !is_valid_field_number(field_number) {
+        return Err(Error::InvalidData);
+    }
+    Ok((field_number, wire_type))
+}
+
+fn read_tagged_data(reader: &mut DataReader) -> Result<TaggedData, Error> {
+    let (_field_number, wire_type) = read_tag(reader)?;
+    match wire_type {
+        WireType::StartGroup | WireType::EndGroup => Ok(TaggedData::Others),
+        WireType::Varint => {
+            let _ = read_varint(reader)?;
+            Ok(TaggedData::Others)
+        }
+        WireType::Bit32 => {
+            reader.skip(4).ok_or_else(|| Error::Eof(4 - reader.remains()))?;
+            Ok(TaggedData::Others)
+        }
+        WireType::Bit64 => {
+            reader.skip(8).ok_or_else(|| Error::Eof(8 - reader.remains()))?;
+            Ok(TaggedData::Others)
+        }
+        WireType::Chunk => {
+            let length = read_varint(reader)?;
+            Ok(TaggedData::ChunkMetadata { length })
+        }
+    }
+}
+
+/// 找到当前消息中的下一个chunk，返回字段的起始位置和chunk的长度。
+/// 数据在字段中间结束时回到字段的起始位置，chunk超出当前消息时视为格式错误
+fn find_next_chunk(reader: &mut DataReader, message_context: &MessageContext) -> Result<Option<(usize, usize)>, Error> {
+    while !reader.eof() {
+        let field_start = reader.position();
+        if let Some(message_length) = message_context.length {
+            let current_length = field_start - message_context.start;
+            if current_length == message_length {
+                break; // 当前message读取完毕，没有找到chunk
+            }
+            if current_length > message_length {
+                return Err(Error::InvalidData); // 已经读取的长度超出期望的message长度
+            }
+        }
+        match read_tagged_data(reader) {
+            Ok(TaggedData::ChunkMetadata { length }) => {
+                let length: usize = length.try_into().map_err(|_| Error::InvalidData)?;
+                let message_end = message_context.length.map(|l| message_context.start + l);
+                if message_end.is_some_and(|end| reader.position().saturating_add(length) > end) {
+                    return Err(Error::InvalidData);
+                }
+                return Ok(Some((field_start, length)));
+            }
+            Ok(TaggedData::Others) => {}
+            Err(Error::InvalidData) => return Err(Error::InvalidData),
+            Err(e) => {
+                reader.seek(field_start);
+                return Err(e);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// 完整字段（从tag开始）的长度，数据还不足以确定时返回`None`
+fn field_len(data: &[u8]) -> Result<Option<usize>, Error> {
+    let mut reader = DataReader::new(data);
+    match read_tagged_data(&mut reader) {
+        Ok(TaggedData::ChunkMetadata { length }) => {
+            let length: usize = length.try_into().map_err(|_| Error::InvalidData)?;
+            reader.position().checked_add(length).map(Some).ok_or(Error::InvalidData)
+        }
+        Ok(TaggedData::Others) => Ok(Some(reader.position())),
+        Err(Error::InvalidData) => Err(Error::InvalidData),
+        Err(_) => Ok(None),
+    }
+}
+
+/// 一个完整的chunk中的所有叶子chunk；猜测为消息但无法完整解析时，整个chunk就是叶子
+fn leaf_chunks(chunk: &[u8]) -> Vec<Vec<u8>> {
+    if guess_is_message(chunk) != Ok(true) {
+        return vec![chunk.to_vec()];
+    }
+    let mut parser = ProtobufParser::new(DataReader::new(chunk));
+    let leaves: Vec<Vec<u8>> = parser.by_ref().map(Cow::into_owned).collect();
+    if parser.is_complete() { leaves } else { vec![chunk.to_vec()] }
+}
+
+/// 用新一段数据补全上一段数据末尾的字段，补全后返回`true`
+fn complete_partial(reader: &mut DataReader, parser_context: &mut ParserContext) -> bool {
+    // 先补全tag和长度，它们最多只有20字节
+    let length = loop {
+        match field_len(&parser_context.partial) {
+            Ok(Some(length)) => break length,
+            Ok(None) => match reader.read1() {
+                Some(byte) => parser_context.partial.push(byte),
+                None => return false,
+            },
+            Err(_) => {
+                parser_context.invalid = true;
+                return false;
+            }
+        }
+    };
+
+    let needed = length - parser_context.partial.len();
+    let available = reader.read(needed.min(reader.remains())).unwrap_or_default();
+    parser_context.partial.extend_from_slice(available);
+    if parser_context.partial.len() < length {
+        return false;
+    }
+
+    let field = std::mem::take(&mut parser_context.partial);
+    let mut field_reader = DataReader::new(&field);
+    if let Ok(TaggedData::ChunkMetadata { .. }) = read_tagged_data(&mut field_reader) {
+        parser_context.pending.extend(leaf_chunks(field_reader.rest()));
+    }
+    true
+}
+
+/// 被猜测为消息的chunk无法完整解析，实际上是一个普通bytearray，整体作为叶子返回
+fn reject_message<'a>(reader: &mut DataReader<'a>, parser_context: &mut ParserContext) -> Option<&'a [u8]> {
+    let message_context = parser_context.messages.pop()?;
+    reader.seek(message_context.start)?;
+    reader.read(message_context.length?)
+}
+
+fn read_next_token_raw<'a>(reader: &mut DataReader<'a>, parser_context: &mut ParserContext) -> Option<Cow<'a, [u8]>> {
+    if let Some(chunk) = parser_context.pending.pop_front() {
+        return Some(Cow::Owned(chunk));
+    }
+    if parser_context.invalid {
+        return None;
+    }
+    if !parser_context.partial.is_empty() {
+        if !complete_partial(reader, parser_context) {
+            return None;
+        }
+        return read_next_token_raw(reader, parser_context);
+    }
+
+    loop {
+        let is_root = parser_context.messages.len() == 1;
+        let message_context = parser_context.messages.last()?;
+        match find_next_chunk(reader, message_context) {
+            Ok(Some((field_start, length))) => {
+                let Some(chunk) = reader.peek(length) else {
+                    // chunk跨越了这段数据的末尾，留到下一段数据中补全
+                    reader.seek(field_start);
+                    break;
+                };
+                if guess_is_message(chunk).unwrap_or(false) {
+                    let new_message_context = MessageContext::new(reader.position(), Some(length));
+                    parser_context.messages.push(new_message_context);
+                    continue;
+                }
+                reader.skip(length);
+                return Some(Cow::Borrowed(chunk));
+            }
+            // message遍历完毕，没有更多chunk
+            Ok(None) if is_root => break,
+            Ok(None) => {
+                parser_context.messages.pop();
+            }
+            // 当前chunk作为message读取发生错误
+            Err(_) if !is_root => return reject_message(reader, parser_context).map(Cow::Borrowed),
+            // 根message存在格式错误
+            Err(Error::InvalidData) => {
+                parser_context.invalid = true;
+                return None;
+            }
+            // 数据在字段中间结束
+            Err(_) => break,
+        }
+    }
+    parser_context.prepare_for_next_slice(reader);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PROTOBUF_EXAMPLE;
+
+    macro_rules! assert_datareader {
+        ($fn:expr, $bytes:expr, $expected:pat) => {
+            let bytes = $bytes;
+            let mut data = DataReader::new($bytes);
+            let result = $fn(&mut data);
+            assert!(matches!(result, $expected), "{result:?} {bytes:?}");
+        };
+        ($fn:expr, $bytes:expr, $expected:pat, $($arg:expr),*) => {
+            let bytes = $bytes;
+            let mut data = DataReader::new($bytes);
+            let result = $fn(&mut data, $($arg),*);
+            assert!(matches!(result, $expected), "{result:?} {bytes:?}");
+        };
+    }
+
+    #[test]
+    fn test_read_varint() {
+        assert_datareader!(read_varint, b"\x01", Ok(1));
+        assert_datareader!(read_varint, b"\x96\x01", Ok(150));
+        assert_datareader!(read_varint, b"\xb5\x81\xd5\xc8\x06", Ok(1763000501));
+    }
+
+    #[test]
+    fn test_read_tag() {
+        assert_datareader!(read_tag, b"\x0a", Ok((1, WireType::Chunk)));
+        assert_datareader!(read_tag, b"\x18", Ok((3, WireType::Varint)));
+    }
+
+    #[test]
+    fn test_read_tagged_data() {
+        assert_datareader!(read_tagged_data, b"\x18\xb5\x81\xd5\xc8\x06", Ok(TaggedData::Others));
+        assert_datareader!(
+            read_tagged_data,
+            b"\x0a\x07\x53\x55\x43\x43\x45\x53\x53",
+            Ok(TaggedData::ChunkMetadata { length: 7 })
+        );
+        assert_datareader!(read_tagged_data, b"\x0a\x00", Ok(TaggedData::ChunkMetadata { length: 0 }));
+        assert_datareader!(read_tagged_data, &PROTOBUF_EXAMPLE[..7], Ok(TaggedData::Others));
+    }
+
+    #[test]
+    fn test_find_next_chunk() {
+        let message_context = MessageContext::new(0, None);
+        assert_datareader!(find_next_chunk, b"\x0a\x07\x53\x55\x43\x43\x45\x53\x53", Ok(Some((0, 7))), &message_context);
+        assert_datareader!(find_next_chunk, PROTOBUF_EXAMPLE, Ok(Some((7, 8))), &message_context);
+        // 在读取完一个TaggedData时EoF
+        assert_datareader!(find_next_chunk, &PROTOBUF_EXAMPLE[..3], Err(Error::VarintEof), &message_context);
+        assert_datareader!(find_next_chunk, &PROTOBUF_EXAMPLE[..6], Err(Error::VarintEof), &message_context);
+        // 在读取完一个TaggedData之后EoF: 报告当前message中找不到chunk
+        assert_datareader!(find_next_chunk, &PROTOBUF_EXAMPLE[..7], Ok(None), &message_context);
+        // chunk超出了所在的message
+        assert_datareader!(find_next_chunk, b"\x0a\x07abc", Err(Error::InvalidData), &MessageContext::new(0, Some(5)));
+    }
+
+    #[test]
+    fn test_parser() {
+        let mut parser = ProtobufParser::new(DataReader::new(PROTOBUF_EXAMPLE));
+        assert_eq!(parser.next_token().as_deref(), Some(b"kotlin46" as &[u8]));
+        assert_eq!(parser.next_token().as_deref(), Some(b"\x00\x01\x03\x04\x07" as &[u8]));
+        assert_eq!(parser.next_token().as_deref(), Some(b"" as &[u8]));
+
+        // b"\x0a\x08POKECOIN"有三种合法的解析结果：
+        // {1: {10: 79, 9:SGROUP, 8: 8.443537e08i32} }、{1: "POKECOIN"}和b"\x0a\x08POKECOIN"，
+        // 猜测逻辑选择第二种
+        assert_eq!(parser.next_token().as_deref(), Some(b"POKECOIN" as &[u8]));
+        assert_eq!(parser.next_token().as_deref(), Some(b"STARDUST" as &[u8]));
+        assert_eq!(parser.next_token(), None);
+        assert!(parser.is_complete());
+    }
+
+    #[test]
+    fn test_parser_split_slices() {
+        let expected: Vec<Vec<u8>> = ProtobufParser::new(DataReader::new(PROTOBUF_EXAMPLE))
+            .map(Cow::into_owned)
+            .collect();
+        assert_eq!(expected.len(), 5);
+
+        for split in 0..=PROTOBUF_EXAMPLE.len() {
+            let mut chunks = Vec::new();
+            let mut context = ParserContext::new();
+            for piece in [&PROTOBUF_EXAMPLE[..split], &PROTOBUF_EXAMPLE[split..]] {
+                let mut parser = ProtobufParser::with_context(DataReader::new(piece), context);
+                chunks.extend(parser.by_ref().map(Cow::into_owned));
+                context = parser.into_context();
+            }
+            assert_eq!(chunks, expected, "split at {}", split);
+            assert!(ProtobufParser::with_context(DataReader::new(b""), context).is_complete(), "split at {}", split);
+        }
+
+        // 数据在字段中间结束
+        let mut parser = ProtobufParser::new(DataReader::new(&PROTOBUF_EXAMPLE[..10]));
+        assert_eq!(parser.next(), None);
+        assert!(!parser.is_complete());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::core::{encode_tag, encode_varint, zigzag_encode};
+
+/// 测试中用来构造protobuf数据，总是使用最短编码，例如
+/// `Payload::new().field(1).varint(150).field(2).string("x").build()`
+///
+/// `field`之后可以连续写入多个值，它们使用同一个field number
+#[derive(Debug, Clone, Default)]
+pub struct Payload {
+    data: Vec<u8>,
+    number: Option<u32>,
+}
+
+impl Payload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 之后写入的值使用的field number
+    pub fn field(mut self, number: u32) -> Self {
+        self.number = Some(number);
+        self
+    }
+
+    fn tag(&mut self, wire_type: u8) {
+        let number = self.number.expect("field() must be called before writing a value");
+        self.data.extend(encode_tag(number, wire_type));
+    }
+
+    pub fn varint(mut self, value: u64) -> Self {
+        self.tag(0);
+        self.data.extend(encode_varint(value));
+        self
+    }
+
+    /// sint32/sint64，使用zigzag编码
+    pub fn sint(self, value: i64) -> Self {
+        self.varint(zigzag_encode(value))
+    }
+
+    pub fn fixed32(mut self, value: u32) -> Self {
+        self.tag(5);
+        self.data.extend(value.to_le_bytes());
+        self
+    }
+
+    pub fn fixed64(mut self, value: u64) -> Self {
+        self.tag(1);
+        self.data.extend(value.to_le_bytes());
+        self
+    }
+
+    pub fn bytes(mut self, value: &[u8]) -> Self {
+        self.tag(2);
+        self.data.extend(encode_varint(value.len() as u64));
+        self.data.extend(value);
+        self
+    }
+
+    pub fn string(self, value: &str) -> Self {
+        self.bytes(value.as_bytes())
+    }
+
+    pub fn message(self, message: Payload) -> Self {
+        self.bytes(&message.build())
+    }
+
+    /// StartGroup、group的内容和同一field number的EndGroup
+    pub fn group(mut self, group: Payload) -> Self {
+        self.tag(3);
+        self.data.extend(group.build());
+        self.tag(4);
+        self
+    }
+
+    /// 直接追加字节，用于构造不完整或不合法的数据
+    pub fn raw(mut self, data: &[u8]) -> Self {
+        self.data.extend(data);
+        self
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_builder() {
+        let nested = Payload::new().field(1).varint(1);
+        let data = Payload::new()
+            .field(1).varint(150).varint(1)
+            .field(2).string("abc")
+            .field(3).message(nested.clone())
+            .field(4).sint(-2)
+            .field(5).fixed32(1)
+            .field(6).fixed64(2)
+            .field(7).group(nested)
+            .raw(b"\xff")
+            .build();
+        assert_eq!(data, b"\x08\x96\x01\x08\x01\x12\x03abc\x1a\x02\x08\x01\x20\x03\
+            \x2d\x01\x00\x00\x00\x31\x02\x00\x00\x00\x00\x00\x00\x00\x3b\x08\x01\x3c\xff");
+        assert_eq!(zigzag_encode(i64::MIN), u64::MAX);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::core::{self, encode_tag, encode_varint, is_valid_field_number, read_bounded_value, read_identifier};
+use crate::formatter::indent;
+use crate::guesser::guess_is_message;
+use crate::parser::DEFAULT_MAX_DEPTH;
+use crate::types::WireType;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::ops::Range;
+
+/// 解析后的单个字段
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub number: u32,
+    pub wire_type: WireType,
+    /// 解析时确定的类型：schema中的类型，没有schema时为线类型的名称，嵌套消息为`message`
+    pub type_name: String,
+    /// 字段值的原始字节，chunk不包含长度前缀，group为空
+    pub raw: Vec<u8>,
+    /// 整个字段（从tag到值的末尾）在最外层输入中的位置，嵌套消息中的字段同样相对于最外层输入
+    pub range: Range<usize>,
+    /// chunk被识别为嵌套消息时的解析结果
+    pub message: Option<Message>,
+}
+
+/// 按出现顺序排列的字段列表
+///
+/// 字段树始终保持数据中的原始顺序（扁平表示），这样不会丢失信息，
+/// 也能原样重新编码。需要按repeated字段处理时使用`grouped`得到合并后的视图。
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Message {
+    pub fields: Vec<Field>,
+}
+
+/// `Message::grouped`返回的字段视图
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupedField<'a> {
+    Single(&'a Field),
+    /// 同一field number多次出现且都是嵌套消息，位置取第一次出现的位置
+    Repeated { number: u32, messages: Vec<&'a Message> },
+}
+
+impl Message {
+    /// 把重复出现的嵌套消息字段合并为一个repeated字段，其他字段保持不变
+    pub fn grouped(&self) -> Vec<GroupedField<'_>> {
+        let mut by_number: BTreeMap<u32, Vec<&Field>> = BTreeMap::new();
+        for field in &self.fields {
+            by_number.entry(field.number).or_default().push(field);
+        }
+        
+        let mut grouped = Vec::new();
+        for field in &self.fields {
+            // 已经在第一次出现的位置合并输出
+            let Some(occurrences) = by_number.get(&field.number) else {
+                continue;
+            };
+            if occurrences.len() > 1 && occurrences.iter().all(|f| f.message.is_some()) {
+                let messages = by_number.remove(&field.number).unwrap_or_default().into_iter()
+                    .filter_map(|f| f.message.as_ref())
+                    .collect();
+                grouped.push(GroupedField::Repeated { number: field.number, messages });
+            } else {
+                grouped.push(GroupedField::Single(field));
+            }
+        }
+        grouped
+    }
+    
+    /// 按出现顺序返回所有field number为`number`的顶层字段，不查找嵌套消息
+    pub fn find_all(&self, number: u32) -> Vec<&Field> {
+        self.fields.iter().filter(|f| f.number == number).collect()
+    }
+    
+    /// 同`find_all`，但同时查找所有嵌套消息，顺序与数据中的位置一致（先外层字段，再其中的嵌套字段）
+    pub fn find_all_recursive(&self, number: u32) -> Vec<&Field> {
+        let mut found = Vec::new();
+        for field in &self.fields {
+            if field.number == number {
+                found.push(field);
+            }
+            found.extend(field.find_all_recursive(number));
+        }
+        found
+    }
+    
+    /// 生成构造当前字段树的Rust代码，可以直接粘贴到测试中
+    pub fn to_rust_literal(&self) -> String {
+        if self.fields.is_empty() {
+            return "Message { fields: vec![] }".to_string();
+        }
+        
+        let fields: Vec<String> = self.fields.iter()
+            .map(|field| format!("{},", field.to_rust_literal()))
+            .collect();
+        format!("Message {{\n    fields: vec![\n{}\n    ],\n}}", indent(&fields.join("\n"), Some("        ")))
+    }
+    
+    /// 后序遍历所有字段：先处理嵌套消息中的字段，再处理包含它的字段
+    pub fn visit_fields_mut(&mut self, visitor: &mut dyn FnMut(&mut Field)) {
+        for field in &mut self.fields {
+            if let Some(message) = &mut field.message {
+                message.visit_fields_mut(visitor);
+            }
+            visitor(field);
+        }
+    }
+    
+    /// 将varint统一为最短编码，字段顺序保持不变；`range`随之更新为重新编码后的位置
+    pub fn normalize(&mut self) {
+        for field in &mut self.fields {
+            field.normalize();
+        }
+        self.relocate(0);
+    }
+    
+    /// 按当前的原始字节重新计算各个字段的位置，`base`为消息本身的起始位置
+    fn relocate(&mut self, base: usize) {
+        let mut offset = base;
+        for field in &mut self.fields {
+            let mut header_len = encode_tag(field.number, field.wire_type as u8).len();
+            if field.wire_type == WireType::Chunk {
+                header_len += encode_varint(field.raw.len() as u64).len();
+            }
+            if let Some(message) = &mut field.message {
+                message.relocate(offset + header_len);
+            }
+            field.range = offset..offset + header_len + field.raw.len();
+            offset = field.range.end;
+        }
+    }
+}
+
+impl Field {
+    /// 在这个字段的嵌套消息中查找，见`Message::find_all`；不是嵌套消息时返回空列表
+    pub fn find_all(&self, number: u32) -> Vec<&Field> {
+        self.message.as_ref().map_or_else(Vec::new, |m| m.find_all(number))
+    }
+    
+    /// 在这个字段的嵌套消息中递归查找，见`Message::find_all_recursive`
+    pub fn find_all_recursive(&self, number: u32) -> Vec<&Field> {
+        self.message.as_ref().map_or_else(Vec::new, |m| m.find_all_recursive(number))
+    }
+    
+    pub fn to_rust_literal(&self) -> String {
+        let message = match &self.message {
+            Some(message) => format!("Some({})", message.to_rust_literal()),
+            None => "None".to_string(),
+        };
+        format!("Field {{ number: {}, wire_type: WireType::{:?}, type_name: {:?}.to_string(), raw: b\"{}\".to_vec(), range: {:?}, message: {} }}",
+            self.number, self.wire_type, self.type_name, self.raw.escape_ascii(), self.range, message)
+    }
+    
+    pub fn normalize(&mut self) {
+        if self.wire_type == WireType::Varint {
+            self.raw = canonical_varint(&self.raw);
+        }
+        if let Some(message) = &mut self.message {
+            // 嵌套消息的原始字节同样需要重新编码
+            message.normalize();
+            self.raw = message.encode();
+        }
+    }
+}
+
+impl Message {
+    /// 按字段顺序重新编码为protobuf数据，对于规范编码的输入`decode_message(&m.encode())`与`m`相同
+    ///
+    /// varint总是使用最短编码，无法保留原始数据中过长的编码。
+    /// 嵌套消息按`message`重新编码，修改其中的字段会反映到结果中；其他chunk原样写入`raw`。
+    /// 编码时不使用也不更新`range`，修改字段后可以重新解码或调用`normalize`得到新的位置
+    pub fn encode(&self) -> Vec<u8> {
+        self.fields.iter().flat_map(Field::encode).collect()
+    }
+}
+
+impl Field {
+    /// 编码这个字段（tag和值），规则见`Message::encode`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = encode_tag(self.number, self.wire_type as u8);
+        match (&self.message, self.wire_type) {
+            (Some(message), WireType::Chunk) => {
+                let value = message.encode();
+                buf.extend(encode_varint(value.len() as u64));
+                buf.extend(value);
+            }
+            (_, WireType::Chunk) => {
+                buf.extend(encode_varint(self.raw.len() as u64));
+                buf.extend(&self.raw);
+            }
+            (_, WireType::Varint) => buf.extend(canonical_varint(&self.raw)),
+            _ => buf.extend(&self.raw),
+        }
+        buf
+    }
+}
+
+/// 这里不检查编码是否规范，过长的编码也按照数值还原为最短编码
+fn canonical_varint(raw: &[u8]) -> Vec<u8> {
+    let value = raw.iter().rev().fold(0u64, |acc, &b| (acc << 7) | (b & 0x7F) as u64);
+    encode_varint(value)
+}
+
+/// 不依赖schema地把数据解析为字段树，嵌套消息最多展开`DEFAULT_MAX_DEPTH`层
+pub fn decode_message(data: &[u8]) -> Result<Message, core::Error> {
+    decode_message_with_max_depth(data, DEFAULT_MAX_DEPTH)
+}
+
+/// 与`decode_message`相同，嵌套消息最多展开`max_depth`层，更深的chunk保持原样
+pub fn decode_message_with_max_depth(data: &[u8], max_depth: usize) -> Result<Message, core::Error> {
+    decode_message_with_depth(data, 0, max_depth, 0)
+}
+
+/// `base`为`data`在最外层输入中的位置
+fn decode_message_with_depth(data: &[u8], depth: usize, max_depth: usize, base: usize) -> Result<Message, core::Error> {
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    
+    while offset < data.len() {
+        let (mut field, next) = decode_field_with_depth(data, offset, depth)?;
+        offset = next;
+        field.range = base + field.range.start..base + next;
+        
+        if field.wire_type == WireType::Chunk && depth < max_depth
+            && guess_is_message(&field.raw) == Ok(true) {
+            let value_start = field.range.end - field.raw.len();
+            field.message = decode_message_with_depth(&field.raw, depth + 1, max_depth, value_start).ok();
+            if field.message.is_some() {
+                field.type_name = "message".to_string();
+            }
+        }
+        fields.push(field);
+    }
+    
+    Ok(Message { fields })
+}
+
+/// 从`offset`开始读取一个字段（tag和值），返回字段和下一个字段的位置
+///
+/// 只做最底层的解码：不使用schema，也不尝试把chunk解析为嵌套消息，
+/// 可以用来实现自定义的遍历方式。字段的`range`相对于`data`，`offset`已经到达数据末尾时返回`Eof`
+pub fn decode_field(data: &[u8], offset: usize) -> Result<(Field, usize), core::Error> {
+    decode_field_with_depth(data, offset, 0)
+}
+
+fn decode_field_with_depth(data: &[u8], offset: usize, depth: usize) -> Result<(Field, usize), core::Error> {
+    let mut cursor = Cursor::new(data);
+    cursor.set_position(offset as u64);
+    let (number, wire_type) = read_identifier(&mut cursor)?.ok_or(core::Error::Eof)?;
+    if !is_valid_field_number(number) {
+        return Err(core::Error::InvalidFieldNumber);
+    }
+    let wire_type = WireType::from_u8(wire_type).ok_or(core::Error::InvalidWireType(wire_type))?;
+    let raw = match wire_type {
+        WireType::StartGroup | WireType::EndGroup => Vec::new(),
+        _ => read_bounded_value(&mut cursor, wire_type as u8, depth)?,
+    };
+    
+    let end = cursor.position() as usize;
+    let field = Field { number, wire_type, type_name: wire_type.name().to_string(), raw, range: offset..end, message: None };
+    Ok((field, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::Payload;
+
+    #[test]
+    fn test_normalize() {
+        // 150分别使用最短编码和过长编码，后面跟着一个嵌套消息
+        let canonical = decode_message(b"\x08\x96\x01\x12\x03\x18\x96\x01").unwrap();
+        let overlong = decode_message(b"\x08\x96\x81\x80\x00\x12\x03\x18\x96\x01").unwrap();
+        assert_ne!(canonical, overlong);
+        
+        let mut normalized = overlong.clone();
+        normalized.normalize();
+        assert_eq!(canonical, normalized);
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        // {1: 150, 2: {3: "abc", 4: 1.0f}, 5 <startgroup>, 6: 1, 5 <endgroup>, 7: "x"}
+        let data = b"\x08\x96\x01\x12\x0a\x1a\x03abc\x25\x00\x00\x80\x3f\x2b\x30\x01\x2c\x3a\x01x";
+        let message = decode_message(data).unwrap();
+        assert!(message.fields[1].message.is_some());
+        assert_eq!(message.encode(), data);
+        assert_eq!(decode_message(&message.encode()).unwrap(), message);
+        
+        // 修改嵌套消息中的字段后重新编码，外层的长度随之更新
+        let mut modified = message.clone();
+        modified.fields[1].message.as_mut().unwrap().fields[0].raw = b"abcdef".to_vec();
+        let encoded = modified.encode();
+        assert_eq!(&encoded[3..6], b"\x12\x0d\x1a");
+        let decoded = decode_message(&encoded).unwrap();
+        assert_eq!(decoded.fields[1].find_all(3)[0].raw, b"abcdef");
+        assert_eq!(decoded.fields[5].raw, b"x");
+        
+        // 过长的varint重新编码后变为最短编码
+        let overlong = decode_message(b"\x08\x96\x81\x80\x00").unwrap();
+        assert_eq!(overlong.encode(), b"\x08\x96\x01");
+    }
+
+    #[test]
+    fn test_grouped_repeated_messages() {
+        // {1: 1, 2: {1: 1}, 2: {1: 2}, 3: 5, 2: {1: 3}}
+        let message = decode_message(b"\x08\x01\x12\x02\x08\x01\x12\x02\x08\x02\x18\x05\x12\x02\x08\x03").unwrap();
+        assert_eq!(message.fields.len(), 5);
+        
+        let grouped = message.grouped();
+        assert_eq!(grouped.len(), 3);
+        assert!(matches!(grouped[0], GroupedField::Single(Field { number: 1, .. })));
+        match &grouped[1] {
+            GroupedField::Repeated { number, messages } => {
+                assert_eq!(*number, 2);
+                let values: Vec<&[u8]> = messages.iter().map(|m| m.fields[0].raw.as_slice()).collect();
+                assert_eq!(values, [b"\x01", b"\x02", b"\x03"]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(grouped[2], GroupedField::Single(Field { number: 3, .. })));
+        
+        // 有一次不是嵌套消息时各自保留
+        let message = decode_message(b"\x12\x02\x08\x01\x10\x01\x12\x02\x08\x02\x10\x02").unwrap();
+        let grouped = message.grouped();
+        assert_eq!(grouped.len(), 4);
+        assert!(grouped.iter().all(|field| matches!(field, GroupedField::Single(_))));
+    }
+
+    #[test]
+    fn test_find_all() {
+        // {1: 1, 2: {1: 2}, 1: 3, 3: 5}
+        let message = decode_message(b"\x08\x01\x12\x02\x08\x02\x08\x03\x18\x05").unwrap();
+        let raws = |fields: Vec<&Field>| fields.iter().map(|f| f.raw.clone()).collect::<Vec<_>>();
+        
+        assert_eq!(raws(message.find_all(1)), [b"\x01", b"\x03"]);
+        assert_eq!(raws(message.find_all_recursive(1)), [b"\x01", b"\x02", b"\x03"]);
+        assert_eq!(raws(message.fields[1].find_all(1)), [b"\x02"]);
+        assert!(message.find_all(4).is_empty());
+        assert!(message.fields[0].find_all(1).is_empty());
+    }
+
+    #[test]
+    fn test_decode_max_depth() {
+        // 12层嵌套的{1: {1: ... {1: 1, 2: 2}}}
+        let mut payload = Payload::new().field(1).varint(1).field(2).varint(2);
+        for _ in 0..12 {
+            payload = Payload::new().field(1).message(payload);
+        }
+        let data = payload.build();
+        let depth = |message: &Message| {
+            let mut depth = 0;
+            let mut current = message;
+            while let Some(nested) = current.fields[0].message.as_ref() {
+                depth += 1;
+                current = nested;
+            }
+            depth
+        };
+        assert_eq!(depth(&decode_message(&data).unwrap()), 12);
+        assert_eq!(depth(&decode_message_with_max_depth(&data, 3).unwrap()), 3);
+    }
+
+    #[test]
+    fn test_to_rust_literal() {
+        let message = decode_message(b"\x08\x96\x01\x12\x02\x08\x01").unwrap();
+        let expected = r#"Message {
+    fields: vec![
+        Field { number: 1, wire_type: WireType::Varint, type_name: "varint".to_string(), raw: b"\x96\x01".to_vec(), range: 0..3, message: None },
+        Field { number: 2, wire_type: WireType::Chunk, type_name: "message".to_string(), raw: b"\x08\x01".to_vec(), range: 3..7, message: Some(Message {
+            fields: vec![
+                Field { number: 1, wire_type: WireType::Varint, type_name: "varint".to_string(), raw: b"\x01".to_vec(), range: 5..7, message: None },
+            ],
+        }) },
+    ],
+}"#;
+        assert_eq!(message.to_rust_literal(), expected);
+        
+        // 生成的代码可以构造出相同的字段树
+        let rebuilt = Message {
+            fields: vec![
+                Field { number: 1, wire_type: WireType::Varint, type_name: "varint".to_string(), raw: b"\x96\x01".to_vec(), range: 0..3, message: None },
+                Field { number: 2, wire_type: WireType::Chunk, type_name: "message".to_string(), raw: b"\x08\x01".to_vec(), range: 3..7, message: Some(Message {
+                    fields: vec![
+                        Field { number: 1, wire_type: WireType::Varint, type_name: "varint".to_string(), raw: b"\x01".to_vec(), range: 5..7, message: None },
+                    ],
+                }) },
+            ],
+        };
+        assert_eq!(message, rebuilt);
+    }
+
+    #[test]
+    fn test_decode_field() {
+        // {1: 150, 2: "abc", 3 <startgroup>, 3 <endgroup>}
+        let data = b"\x08\x96\x01\x12\x03abc\x1b\x1c";
+        let mut offset = 0;
+        let mut fields = Vec::new();
+        while offset < data.len() {
+            let (field, next) = decode_field(data, offset).unwrap();
+            assert!(next > offset);
+            fields.push((field.number, field.wire_type, field.raw));
+            offset = next;
+        }
+        assert_eq!(fields, [
+            (1, WireType::Varint, b"\x96\x01".to_vec()),
+            (2, WireType::Chunk, b"abc".to_vec()),
+            (3, WireType::StartGroup, Vec::new()),
+            (3, WireType::EndGroup, Vec::new()),
+        ]);
+        
+        assert_eq!(decode_field(data, 3).unwrap().0.range, 3..8);
+        
+        // 嵌套消息不展开
+        assert_eq!(decode_field(b"\x12\x02\x08\x01", 0).unwrap().0.message, None);
+        assert!(matches!(decode_field(data, data.len()), Err(core::Error::Eof)));
+        assert!(matches!(decode_field(b"\x12\x05ab", 0), Err(core::Error::Eof)));
+    }
+}
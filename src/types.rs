@@ -1,5 +1,5 @@
-use crate::core::{parse_varint_bytes, zigzag_decode};
-use crate::formatter::{foreground, foreground_bold};
+use crate::core::{encode_varint, parse_varint_bytes, read_packed_varints, zigzag_decode};
+use crate::formatter::{dim, foreground, foreground_bold, format_epoch_seconds};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum WireType {
@@ -23,11 +23,220 @@ impl WireType {
             _ => None,
         }
     }
+    
+    /// 没有schema时字段显示的类型名
+    pub fn name(self) -> &'static str {
+        match self {
+            WireType::Varint => "varint",
+            WireType::Bit64 => "64bit",
+            WireType::Chunk => "chunk",
+            WireType::StartGroup => "startgroup",
+            WireType::EndGroup => "endgroup",
+            WireType::Bit32 => "32bit",
+        }
+    }
+}
+
+/// 一次解析中各线类型的字段数量等统计，用于快速发现异常数据
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WireTypeStats {
+    /// 按线类型的值索引
+    pub counts: [usize; 6],
+    /// 输入数据的总字节数
+    pub total_bytes: usize,
+    /// 最深的嵌套层数，只有顶层消息时为0
+    pub max_depth: usize,
+    /// 第一个到达`max_depth`的字段的路径（从外到内的field number）
+    pub max_depth_path: Vec<u32>,
+}
+
+impl WireTypeStats {
+    pub fn count(&self, wire_type: WireType) -> usize {
+        self.counts[wire_type as usize]
+    }
+    
+    /// 所有层级的字段总数，group的结束标记不计入
+    pub fn field_count(&self) -> usize {
+        self.counts.iter().sum::<usize>() - self.count(WireType::EndGroup)
+    }
+    
+    pub fn record(&mut self, wire_type: WireType, depth: usize) {
+        self.counts[wire_type as usize] += 1;
+        self.max_depth = self.max_depth.max(depth);
+    }
+}
+
+/// 32bit/64bit字段可以显示的解读方式，除了十六进制外都带有`u32=`、`f64=`等标签
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FixedInterpretation {
+    Hex,
+    Unsigned,
+    Signed,
+    Float,
+}
+
+impl FixedInterpretation {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hex" => Some(FixedInterpretation::Hex),
+            "unsigned" => Some(FixedInterpretation::Unsigned),
+            "signed" => Some(FixedInterpretation::Signed),
+            "float" => Some(FixedInterpretation::Float),
+            _ => None,
+        }
+    }
+}
+
+/// 没有schema的chunk可能的解读方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChunkKind {
+    Message,
+    String,
+    Bytes,
+}
+
+impl ChunkKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "message" => Some(ChunkKind::Message),
+            "string" => Some(ChunkKind::String),
+            "bytes" => Some(ChunkKind::Bytes),
+            _ => None,
+        }
+    }
+}
+
+/// 字段值中出现非最短编码的varint时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NonCanonicalVarints {
+    /// 视为解析错误
+    #[default]
+    Error,
+    /// 正常解码，并在字段后面标注`(non-canonical encoding)`
+    Warn,
+    /// 正常解码，不做任何标注
+    Accept,
+}
+
+/// 解析选项，同时会传递给类型处理器
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// 没有被当作消息或文本、显示为bytes的chunk额外显示按packed varint解析的结果
+    pub show_packed_varints: bool,
+    /// 既不是文本也不是消息的chunk长度是4或8的整数倍时，额外显示按packed fixed32/fixed64解析的结果
+    pub show_packed_fixed: bool,
+    /// 既不是文本也不是消息的chunk能拆成至少4个数值都在合理范围内的f32/f64时，
+    /// 标注`possible f32[N]`并显示前几个值，适合识别音频、传感器等采样数据
+    pub show_float_arrays: bool,
+    /// 不尝试把chunk识别为嵌套消息，只显示为字符串或bytes
+    pub no_nested: bool,
+    /// 严格模式：没有schema的chunk只显示为原始bytes，不做任何猜测
+    ///
+    /// 只有schema中声明为消息（或map、Any等）的chunk才会按消息解析，也不识别MessageSet和map
+    pub strict: bool,
+    /// 宽松模式：嵌套消息的最后一个字段不完整时（例如外层数据被截断），仍然显示能够解析的部分
+    pub lenient: bool,
+    /// 允许field number为0的字段，用于分析带有哨兵字节的私有格式
+    pub allow_field_zero: bool,
+    /// 32bit/64bit字段按顺序显示的解读方式
+    pub fixed_interpretations: Vec<FixedInterpretation>,
+    /// 把顶层消息末尾全部为0x00的字节识别为填充，而不是field 0
+    pub allow_zero_padding: bool,
+    /// `Parser::extract_chunks`是否同时返回被识别为嵌套消息的chunk
+    pub extract_nested_messages: bool,
+    /// varint超过这个位数时改为科学计数法显示，后面附上精确的十六进制值
+    pub max_integer_digits: Option<usize>,
+    /// 字段值中非最短编码的varint的处理方式，tag和长度前缀始终要求最短编码
+    pub non_canonical_varints: NonCanonicalVarints,
+    /// 只输出ASCII字符，例如树形输出的连接线使用`|`和`+--`
+    pub ascii_only: bool,
+    /// 超过这个深度的嵌套消息只显示为折叠的摘要，可以用`Parser::expand_path`展开
+    pub max_display_depth: Option<usize>,
+    /// 显示wire层面的细节，例如chunk的长度前缀占用了几个字节
+    pub show_wire_details: bool,
+    /// 在每个字段的行首标注它在输入中占用的字节范围，例如`@0x1a-0x22`
+    pub show_offsets: bool,
+    /// sint32/sint64在解码后的值后面附上原始的无符号varint，例如`-5 (raw 9)`
+    pub show_zigzag_raw: bool,
+    /// 在包含`\0`的string后面标注`(contains NUL)`，这通常说明字段其实是bytes
+    pub flag_nul_in_strings: bool,
+    /// 把解析器的判断过程（使用的类型、是否按嵌套消息解析等）记录到`Parser::trace`
+    pub trace: bool,
+    /// chunk同时可以解读为多种类型时按顺序选择第一个可行的，`Bytes`总是可行，
+    /// 列表中没有`Bytes`时也会作为最后的选择
+    ///
+    /// 默认为空，使用内置的顺序：能够完整解析的嵌套消息优先于字符串，只凭猜测判断时字符串优先
+    pub chunk_interpretation_order: Vec<ChunkKind>,
+}
+
+impl ParseOptions {
+    /// 实际使用的解读顺序，`parsed_message`表示嵌套消息能够完整解析而不只是猜测
+    pub fn chunk_order(&self, parsed_message: bool) -> &[ChunkKind] {
+        match (self.chunk_interpretation_order.is_empty(), parsed_message) {
+            (false, _) => &self.chunk_interpretation_order,
+            (true, true) => &[ChunkKind::Message, ChunkKind::String, ChunkKind::Bytes],
+            (true, false) => &[ChunkKind::String, ChunkKind::Message, ChunkKind::Bytes],
+        }
+    }
+    
+    /// 按`chunk_interpretation_order`，chunk是否需要尝试解析为嵌套消息：
+    /// 排在`Message`之前的解读已经可行时不再尝试
+    pub fn allows_nested_message(&self, data: &[u8]) -> bool {
+        for kind in self.chunk_order(true) {
+            match kind {
+                ChunkKind::Message => return true,
+                ChunkKind::String if std::str::from_utf8(data).is_ok_and(is_likely_text) => return false,
+                ChunkKind::String => {}
+                ChunkKind::Bytes => return false,
+            }
+        }
+        false
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            show_packed_varints: false,
+            show_packed_fixed: false,
+            show_float_arrays: false,
+            no_nested: false,
+            strict: false,
+            lenient: false,
+            allow_field_zero: false,
+            allow_zero_padding: false,
+            extract_nested_messages: false,
+            max_integer_digits: None,
+            non_canonical_varints: NonCanonicalVarints::Error,
+            ascii_only: false,
+            max_display_depth: None,
+            show_wire_details: false,
+            show_offsets: false,
+            show_zigzag_raw: false,
+            flag_nul_in_strings: false,
+            trace: false,
+            chunk_interpretation_order: Vec::new(),
+            fixed_interpretations: vec![
+                FixedInterpretation::Hex,
+                FixedInterpretation::Signed,
+                FixedInterpretation::Float,
+            ],
+        }
+    }
 }
 
 pub trait TypeHandler {
     fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error>;
     fn wire_type(&self) -> WireType;
+    
+    /// 数据中的线类型是否符合这个类型，默认只接受`wire_type`，可以同时接受多种编码的类型需要覆盖
+    fn accepts_wire_type(&self, wire_type: WireType) -> bool {
+        wire_type == self.wire_type()
+    }
+    
+    fn parse_with_options(&self, data: &[u8], type_name: &str, _options: &ParseOptions) -> Result<String, crate::core::Error> {
+        self.parse(data, type_name)
+    }
 }
 
 pub struct VarintHandler;
@@ -35,10 +244,23 @@ pub struct Bit32Handler;
 pub struct Bit64Handler;
 pub struct ChunkHandler;
 
+/// 按`max_integer_digits`格式化无符号整数，例如`1.235e18 (0x112210F47DE98115)`
+fn format_unsigned(val: u64, options: &ParseOptions) -> String {
+    let decimal = val.to_string();
+    match options.max_integer_digits {
+        Some(limit) if decimal.len() > limit => format!("{:.3e} (0x{:X})", val as f64, val),
+        _ => decimal,
+    }
+}
+
 impl TypeHandler for VarintHandler {
-    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        self.parse_with_options(data, type_name, &ParseOptions::default())
+    }
+    
+    fn parse_with_options(&self, data: &[u8], _type_name: &str, options: &ParseOptions) -> Result<String, crate::core::Error> {
         let val = parse_varint_bytes(data)?;
-        Ok(foreground_bold(3, &val.to_string()).to_string())
+        Ok(foreground_bold(3, &format_unsigned(val, options)).to_string())
     }
     
     fn wire_type(&self) -> WireType {
@@ -47,14 +269,26 @@ impl TypeHandler for VarintHandler {
 }
 
 impl TypeHandler for Bit32Handler {
-    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        self.parse_with_options(data, type_name, &ParseOptions::default())
+    }
+    
+    fn parse_with_options(&self, data: &[u8], _type_name: &str, options: &ParseOptions) -> Result<String, crate::core::Error> {
         if data.len() != 4 {
             return Err(crate::core::Error::Eof);
         }
         let signed = i32::from_le_bytes([data[0], data[1], data[2], data[3]]);
         let unsigned = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
         let floating = f32::from_le_bytes([data[0], data[1], data[2], data[3]]);
-        Ok(format!("0x{:08X} / {} / {:+#?}", unsigned, signed, floating))
+        let parts: Vec<String> = options.fixed_interpretations.iter()
+            .map(|interpretation| match interpretation {
+                FixedInterpretation::Hex => format!("0x{:08X}", unsigned),
+                FixedInterpretation::Unsigned => format!("u32={}", unsigned),
+                FixedInterpretation::Signed => format!("i32={}", signed),
+                FixedInterpretation::Float => format!("f32={:?}", floating),
+            })
+            .collect();
+        Ok(parts.join(", "))
     }
     
     fn wire_type(&self) -> WireType {
@@ -63,7 +297,11 @@ impl TypeHandler for Bit32Handler {
 }
 
 impl TypeHandler for Bit64Handler {
-    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        self.parse_with_options(data, type_name, &ParseOptions::default())
+    }
+    
+    fn parse_with_options(&self, data: &[u8], _type_name: &str, options: &ParseOptions) -> Result<String, crate::core::Error> {
         if data.len() != 8 {
             return Err(crate::core::Error::Eof);
         }
@@ -76,7 +314,16 @@ impl TypeHandler for Bit64Handler {
         let floating = f64::from_le_bytes([
             data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]
         ]);
-        Ok(format!("0x{:016X} / {} / {:+#?}", unsigned, signed, floating))
+        let parts: Vec<String> = options.fixed_interpretations.iter()
+            .map(|interpretation| match interpretation {
+                FixedInterpretation::Hex => format!("0x{:016X}", unsigned),
+                FixedInterpretation::Unsigned => format!("u64={}", unsigned),
+                FixedInterpretation::Signed => format!("i64={}", signed),
+                // 即protobuf的double
+                FixedInterpretation::Float => format!("f64={:?}", floating),
+            })
+            .collect();
+        Ok(parts.join(", "))
     }
     
     fn wire_type(&self) -> WireType {
@@ -85,35 +332,50 @@ impl TypeHandler for Bit64Handler {
 }
 
 impl TypeHandler for ChunkHandler {
-    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        self.parse_with_options(data, type_name, &ParseOptions::default())
+    }
+    
+    fn parse_with_options(&self, data: &[u8], _type_name: &str, options: &ParseOptions) -> Result<String, crate::core::Error> {
         if data.is_empty() {
             return Ok("empty chunk".to_string());
         }
         
-        // 首先尝试作为字符串显示，对于任何有效的UTF-8都尝试显示
-        if let Ok(s) = std::str::from_utf8(data) {
-            // 只要不是纯控制字符或二进制数据，就显示为字符串
-            if is_likely_text(s) {
-                return Ok(foreground(2, &format!("\"{}\"", s)).to_string());
-            }
+        if options.strict {
+            let hex_dump = crate::formatter::hex_dump(data);
+            return Ok(format!("bytes ({})\n{}", data.len(), crate::formatter::indent(&hex_dump, None)));
         }
         
-        // 使用增强的猜测逻辑决定如何显示所有chunk数据
-        match crate::guesser::guess_is_message(data) {
-            Ok(true) => {
-                // 如果猜测为消息，显示为嵌套消息格式
-                Ok(format!("message ({} bytes)", data.len()))
-            }
-            Ok(false) | Err(_) => {
-                // 如果猜测不是消息或猜测失败，显示为bytes的hex dump
-                let hex_dump = crate::formatter::hex_dump(data);
-                if !data.is_empty() {
-                    Ok(format!("bytes ({})\n{}", data.len(), crate::formatter::indent(&hex_dump, None)))
-                } else {
-                    Ok("bytes (0)".to_string())
-                }
+        for kind in options.chunk_order(false) {
+            match kind {
+                // 只要不是纯控制字符或二进制数据，就显示为字符串
+                ChunkKind::String => if let Ok(s) = std::str::from_utf8(data)
+                    && is_likely_text(s) {
+                    return Ok(foreground(2, &format!("\"{}\"", s)).to_string());
+                },
+                // 使用增强的猜测逻辑判断是否为嵌套消息
+                ChunkKind::Message => if !options.no_nested && crate::guesser::guess_is_message(data) == Ok(true) {
+                    return Ok(format!("message ({} bytes)", data.len()));
+                },
+                ChunkKind::Bytes => break,
             }
         }
+        
+        // 其他解读都不可行时，显示为bytes的hex dump，可以额外给出packed varint的解读
+        let mut packed_note = String::new();
+        if options.show_packed_varints
+            && let Ok(values) = read_packed_varints(data) {
+            let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            packed_note = format!(" (as packed varints: [{}])", values.join(", "));
+        }
+        if options.show_packed_fixed {
+            packed_note.push_str(&packed_fixed_note(data));
+        }
+        if options.show_float_arrays {
+            packed_note.push_str(&float_array_note(data));
+        }
+        let hex_dump = crate::formatter::hex_dump(data);
+        Ok(format!("bytes ({}){}\n{}", data.len(), packed_note, crate::formatter::indent(&hex_dump, None)))
     }
     
     fn wire_type(&self) -> WireType {
@@ -121,7 +383,64 @@ impl TypeHandler for ChunkHandler {
     }
 }
 
-fn is_likely_text(s: &str) -> bool {
+/// packed fixed32/fixed64的解读，只在能拆成2到16个元素时给出，避免对任意数据都附上一长串数字
+fn packed_fixed_note(data: &[u8]) -> String {
+    let mut note = String::new();
+    if data.len().is_multiple_of(4) && (2..=16).contains(&(data.len() / 4)) {
+        let values: Vec<String> = data.chunks(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]).to_string())
+            .collect();
+        note.push_str(&format!(" (as packed fixed32: [{}])", values.join(", ")));
+    }
+    if data.len().is_multiple_of(8) && (2..=16).contains(&(data.len() / 8)) {
+        let values: Vec<String> = data.chunks(8)
+            .map(|c| u64::from_le_bytes(c.try_into().unwrap()).to_string())
+            .collect();
+        note.push_str(&format!(" (as packed fixed64: [{}])", values.join(", ")));
+    }
+    note
+}
+
+/// `float_array_note`要求的最少元素个数，太短的数据按浮点数解读几乎总能成立
+const MIN_FLOAT_ARRAY_LEN: usize = 4;
+
+/// `float_array_note`显示的元素个数
+const FLOAT_ARRAY_PREVIEW: usize = 4;
+
+/// 按f32/f64数组解读chunk，所有值都在合理范围内且不全为0时给出`possible f32[N]`的标注
+fn float_array_note(data: &[u8]) -> String {
+    let f32_values: Vec<f64> = data.chunks_exact(4)
+        .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64)
+        .collect();
+    let f64_values: Vec<f64> = data.chunks_exact(8)
+        .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+        .collect();
+    
+    let mut note = String::new();
+    for (name, size, values) in [("f32", 4, f32_values), ("f64", 8, f64_values)] {
+        if !data.len().is_multiple_of(size) || values.len() < MIN_FLOAT_ARRAY_LEN
+            || !values.iter().all(|&v| is_sane_float(v)) || values.iter().all(|&v| v == 0.0) {
+            continue;
+        }
+        let mut preview: Vec<String> = values.iter()
+            .take(FLOAT_ARRAY_PREVIEW)
+            .map(|v| if size == 4 { format!("{:?}", *v as f32) } else { format!("{:?}", v) })
+            .collect();
+        if values.len() > FLOAT_ARRAY_PREVIEW {
+            preview.push("...".to_string());
+        }
+        note.push_str(&format!(" (possible {}[{}]: [{}])", name, values.len(), preview.join(", ")));
+    }
+    note
+}
+
+/// 采样数据通常是0或者绝对值在1e-6到1e9之间的有限值，随机字节按浮点数解读时很容易超出这个范围
+fn is_sane_float(value: f64) -> bool {
+    value == 0.0 || (1e-6..=1e9).contains(&value.abs())
+}
+
+/// 字符串中控制字符不超过20%时认为是文本
+pub fn is_likely_text(s: &str) -> bool {
     let total = s.len();
     if total == 0 {
         return false;
@@ -146,6 +465,162 @@ fn is_likely_text(s: &str) -> bool {
     true
 }
 
+/// 检查类型名中第一个单词之后的修饰符，例如`fixed32 be epoch_s`
+pub(crate) fn has_modifier(type_name: &str, modifier: &str) -> bool {
+    type_name.split_whitespace().skip(1).any(|m| m == modifier)
+}
+
+fn format_fixed<T: ToString + TryInto<i64> + Copy>(val: T, type_name: &str) -> String {
+    let text = foreground_bold(3, &val.to_string());
+    match val.try_into() {
+        Ok(seconds) if has_modifier(type_name, "epoch_s") => format!("{} ({})", text, format_epoch_seconds(seconds)),
+        _ => text,
+    }
+}
+
+/// `timestamp_delta base=fieldN`：相对于同一消息中field N（秒级时间戳）的偏移，单位为秒
+pub struct TimestampDeltaHandler;
+
+impl TimestampDeltaHandler {
+    /// 从类型名中取出基准字段的field number
+    pub fn base_field(type_name: &str) -> Option<u32> {
+        type_name.split_whitespace()
+            .skip(1)
+            .find_map(|m| m.strip_prefix("base=field")?.parse().ok())
+    }
+    
+    /// 加上基准字段的值后显示为绝对时间，找不到基准字段时只显示偏移
+    pub fn format(&self, data: &[u8], base: Option<i64>) -> Result<String, crate::core::Error> {
+        let delta = parse_varint_bytes(data)? as i64;
+        let text = foreground_bold(3, &format!("{:+}", delta));
+        Ok(match base.and_then(|base| base.checked_add(delta)) {
+            Some(timestamp) => format!("{} ({})", text, format_epoch_seconds(timestamp)),
+            None => format!("{} (base field not found)", text),
+        })
+    }
+}
+
+impl TypeHandler for TimestampDeltaHandler {
+    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+        self.format(data, None)
+    }
+    
+    fn wire_type(&self) -> WireType {
+        WireType::Varint
+    }
+}
+
+/// `bitfield [0:16]=width [16:32]=height`：把一个varint按位拆分成多个带名称的值，
+/// 区间为`[起始位:结束位)`，从最低位开始计数，方括号和分隔的逗号可以省略
+pub struct BitfieldHandler;
+
+impl BitfieldHandler {
+    /// 从类型名中取出(起始位, 结束位, 名称)，格式错误时返回`None`
+    pub fn layout(type_name: &str) -> Option<Vec<(u32, u32, &str)>> {
+        type_name.split_whitespace()
+            .skip(1)
+            .map(|spec| {
+                let (range, name) = spec.trim_end_matches(',').split_once('=')?;
+                let range = range.strip_prefix('[').and_then(|r| r.strip_suffix(']')).unwrap_or(range);
+                let (start, end) = range.split_once(':')?;
+                let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+                (start < end && end <= 64 && !name.is_empty()).then_some((start, end, name))
+            })
+            .collect()
+    }
+}
+
+impl TypeHandler for BitfieldHandler {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        let val = parse_varint_bytes(data)?;
+        let layout = Self::layout(type_name).ok_or(crate::core::Error::InvalidVarint)?;
+        let parts: Vec<String> = layout.iter()
+            .map(|&(start, end, name)| {
+                let width = end - start;
+                let mask = if width == 64 { u64::MAX } else { (1u64 << width) - 1 };
+                format!("{} = {}", name, foreground_bold(3, &((val >> start) & mask).to_string()))
+            })
+            .collect();
+        Ok(format!("{} {{{}}}", foreground_bold(3, &val.to_string()), parts.join(", ")))
+    }
+    
+    fn wire_type(&self) -> WireType {
+        WireType::Varint
+    }
+}
+
+/// 把packed的chunk按元素的线类型拆成各个元素的原始字节，无法完整拆分时返回None
+pub fn split_packed(data: &[u8], wire_type: WireType) -> Option<Vec<Vec<u8>>> {
+    match wire_type {
+        WireType::Varint => Some(read_packed_varints(data).ok()?.into_iter().map(encode_varint).collect()),
+        WireType::Bit32 if data.len().is_multiple_of(4) => Some(data.chunks(4).map(<[u8]>::to_vec).collect()),
+        WireType::Bit64 if data.len().is_multiple_of(8) => Some(data.chunks(8).map(<[u8]>::to_vec).collect()),
+        _ => None,
+    }
+}
+
+/// 以chunk形式出现的`int32 repeated`、`fixed32 repeated be`等：把chunk按元素类型拆分为多个值，显示为`[1, 2, 150]`，
+/// 元素类型不能packed或者无法完整拆分时按普通chunk显示
+pub struct PackedHandler;
+
+impl PackedHandler {
+    /// 元素类型及其处理器，只支持可以packed的数值类型
+    pub fn element(type_name: &str) -> Option<(&str, &'static dyn TypeHandler)> {
+        let element_type = type_name.trim();
+        let handler: &'static dyn TypeHandler = match element_type.split_whitespace().next()? {
+            "varint" | "uint32" | "uint64" => &VarintHandler,
+            "int32" => &Int32Handler,
+            "int64" => &Int64Handler,
+            "sint32" => &SInt32Handler,
+            "sint64" => &SInt64Handler,
+            "bool" => &BoolHandler,
+            "fixed32" => &Fixed32Handler,
+            "sfixed32" => &SFixed32Handler,
+            "fixed64" => &Fixed64Handler,
+            "sfixed64" => &SFixed64Handler,
+            "float" => &FloatHandler,
+            "double" => &DoubleHandler,
+            "32bit" => &Bit32Handler,
+            "64bit" => &Bit64Handler,
+            _ => return None,
+        };
+        Some((element_type, handler))
+    }
+    
+    fn parse_elements(data: &[u8], type_name: &str, options: &ParseOptions) -> Option<Vec<String>> {
+        let (element_type, handler) = Self::element(type_name)?;
+        split_packed(data, handler.wire_type())?.iter()
+            .map(|element| handler.parse_with_options(element, element_type, options).ok())
+            .collect()
+    }
+}
+
+impl TypeHandler for PackedHandler {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        self.parse_with_options(data, type_name, &ParseOptions::default())
+    }
+    
+    fn parse_with_options(&self, data: &[u8], type_name: &str, options: &ParseOptions) -> Result<String, crate::core::Error> {
+        match Self::parse_elements(data, type_name, options) {
+            Some(values) => Ok(format!("[{}]", values.join(", "))),
+            None => ChunkHandler.parse_with_options(data, type_name, options),
+        }
+    }
+    
+    fn wire_type(&self) -> WireType {
+        WireType::Chunk
+    }
+}
+
+/// sint32/sint64共用的显示方式
+fn format_zigzag(val: u64, options: &ParseOptions) -> Result<String, crate::core::Error> {
+    let text = foreground_bold(3, &zigzag_decode(val).to_string());
+    if options.show_zigzag_raw {
+        return Ok(format!("{} (raw {})", text, val));
+    }
+    Ok(text)
+}
+
 pub struct SInt32Handler;
 pub struct SInt64Handler;
 pub struct Int32Handler;
@@ -157,16 +632,21 @@ pub struct StringHandler;
 pub struct BytesHandler;
 pub struct FloatHandler;
 pub struct DoubleHandler;
+pub struct Float16Handler;
+pub struct BFloat16Handler;
 pub struct Fixed32Handler;
 pub struct SFixed32Handler;
 pub struct Fixed64Handler;
 pub struct SFixed64Handler;
 
 impl TypeHandler for SInt32Handler {
-    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        self.parse_with_options(data, type_name, &ParseOptions::default())
+    }
+    
+    fn parse_with_options(&self, data: &[u8], _type_name: &str, options: &ParseOptions) -> Result<String, crate::core::Error> {
         let val = parse_varint_bytes(data)?;
-        let decoded = zigzag_decode(val);
-        Ok(foreground_bold(3, &decoded.to_string()).to_string())
+        format_zigzag(val, options)
     }
     
     fn wire_type(&self) -> WireType {
@@ -175,10 +655,13 @@ impl TypeHandler for SInt32Handler {
 }
 
 impl TypeHandler for SInt64Handler {
-    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        self.parse_with_options(data, type_name, &ParseOptions::default())
+    }
+    
+    fn parse_with_options(&self, data: &[u8], _type_name: &str, options: &ParseOptions) -> Result<String, crate::core::Error> {
         let val = parse_varint_bytes(data)?;
-        let decoded = zigzag_decode(val);
-        Ok(foreground_bold(3, &decoded.to_string()).to_string())
+        format_zigzag(val, options)
     }
     
     fn wire_type(&self) -> WireType {
@@ -232,9 +715,13 @@ impl TypeHandler for UInt32Handler {
 }
 
 impl TypeHandler for UInt64Handler {
-    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        self.parse_with_options(data, type_name, &ParseOptions::default())
+    }
+    
+    fn parse_with_options(&self, data: &[u8], _type_name: &str, options: &ParseOptions) -> Result<String, crate::core::Error> {
         let val = parse_varint_bytes(data)?;
-        Ok(foreground_bold(3, &val.to_string()).to_string())
+        Ok(foreground_bold(3, &format_unsigned(val, options)).to_string())
     }
     
     fn wire_type(&self) -> WireType {
@@ -257,10 +744,35 @@ impl TypeHandler for BoolHandler {
 }
 
 impl TypeHandler for StringHandler {
-    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
-        let s = std::str::from_utf8(data)
-            .map_err(|_| crate::core::Error::Eof)?;
-        Ok(foreground(2, &format!("\"{}\"", s)).to_string())
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        self.parse_with_options(data, type_name, &ParseOptions::default())
+    }
+    
+    fn parse_with_options(&self, data: &[u8], type_name: &str, options: &ParseOptions) -> Result<String, crate::core::Error> {
+        // `string:latin1`、`string:gbk`指定非UTF-8的编码
+        let (s, valid, encoding) = match type_name.split_whitespace().next().unwrap_or_default() {
+            "string:latin1" => (crate::charset::decode_latin1(data), true, "latin-1"),
+            "string:gbk" => {
+                let (s, valid) = crate::charset::decode_gbk(data);
+                (s, valid, "gbk")
+            }
+            _ => match std::str::from_utf8(data) {
+                Ok(s) => (s.to_string(), true, "utf-8"),
+                Err(_) => (String::from_utf8_lossy(data).into_owned(), false, "utf-8"),
+            },
+        };
+        // 带有`strict`修饰词时不允许无效的字符
+        if !valid && has_modifier(type_name, "strict") {
+            return Err(crate::core::Error::InvalidString);
+        }
+        let mut text = foreground(2, &format!("\"{}\"", s));
+        if !valid {
+            text = format!("{} {}", text, dim(&format!("(invalid {})", encoding)));
+        }
+        if options.flag_nul_in_strings && s.contains('\0') {
+            return Ok(format!("{} {}", text, foreground(1, "(contains NUL)")));
+        }
+        Ok(text)
     }
     
     fn wire_type(&self) -> WireType {
@@ -320,13 +832,60 @@ impl TypeHandler for DoubleHandler {
     }
 }
 
-impl TypeHandler for Fixed32Handler {
+/// 读取varint中存放的16位浮点数位模式，超过16位时报错
+fn parse_16bit_pattern(data: &[u8]) -> Result<u16, crate::core::Error> {
+    let val = parse_varint_bytes(data)?;
+    u16::try_from(val).map_err(|_| crate::core::Error::InvalidVarint)
+}
+
+/// IEEE-754半精度转换为f32，f32可以精确表示所有半精度数
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1F) as i32;
+    let mantissa = (bits & 0x3FF) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+impl TypeHandler for Float16Handler {
+    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+        let val = half_to_f32(parse_16bit_pattern(data)?);
+        Ok(foreground_bold(3, &format!("{:+#?}", val)).to_string())
+    }
+    
+    fn wire_type(&self) -> WireType {
+        WireType::Varint
+    }
+}
+
+impl TypeHandler for BFloat16Handler {
     fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+        // bfloat16就是f32的高16位
+        let val = f32::from_bits((parse_16bit_pattern(data)? as u32) << 16);
+        Ok(foreground_bold(3, &format!("{:+#?}", val)).to_string())
+    }
+    
+    fn wire_type(&self) -> WireType {
+        WireType::Varint
+    }
+}
+
+impl TypeHandler for Fixed32Handler {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
         if data.len() != 4 {
             return Err(crate::core::Error::Eof);
         }
-        let val = i32::from_le_bytes([data[0], data[1], data[2], data[3]]);
-        Ok(foreground_bold(3, &val.to_string()).to_string())
+        let bytes = [data[0], data[1], data[2], data[3]];
+        let val = if has_modifier(type_name, "be") {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        };
+        Ok(format_fixed(val, type_name))
     }
     
     fn wire_type(&self) -> WireType {
@@ -339,7 +898,7 @@ impl TypeHandler for SFixed32Handler {
         if data.len() != 4 {
             return Err(crate::core::Error::Eof);
         }
-        let val = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        let val = i32::from_le_bytes([data[0], data[1], data[2], data[3]]);
         Ok(foreground_bold(3, &val.to_string()).to_string())
     }
     
@@ -349,14 +908,19 @@ impl TypeHandler for SFixed32Handler {
 }
 
 impl TypeHandler for Fixed64Handler {
-    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
         if data.len() != 8 {
             return Err(crate::core::Error::Eof);
         }
-        let val = i64::from_le_bytes([
+        let bytes = [
             data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]
-        ]);
-        Ok(foreground_bold(3, &val.to_string()).to_string())
+        ];
+        let val = if has_modifier(type_name, "be") {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        };
+        Ok(format_fixed(val, type_name))
     }
     
     fn wire_type(&self) -> WireType {
@@ -369,7 +933,7 @@ impl TypeHandler for SFixed64Handler {
         if data.len() != 8 {
             return Err(crate::core::Error::Eof);
         }
-        let val = u64::from_le_bytes([
+        let val = i64::from_le_bytes([
             data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]
         ]);
         Ok(foreground_bold(3, &val.to_string()).to_string())
@@ -379,3 +943,310 @@ impl TypeHandler for SFixed64Handler {
         WireType::Bit64
     }
 }
+
+/// ISO 3166国家代码或ISO 4217货币代码，可以是varint形式的数字代码，也可以是字母代码字符串
+pub struct IsoCodeHandler {
+    pub by_number: fn(u64) -> Option<&'static str>,
+    pub by_alpha: fn(&str) -> Option<&'static str>,
+}
+
+impl TypeHandler for IsoCodeHandler {
+    fn parse(&self, data: &[u8], _type_name: &str) -> Result<String, crate::core::Error> {
+        // 字母代码是2到3个大写字母，不可能是合法的varint
+        if (2..=3).contains(&data.len()) && data.iter().all(u8::is_ascii_uppercase) {
+            let code = std::str::from_utf8(data).unwrap_or_default();
+            let text = foreground(2, &format!("\"{}\"", code));
+            return Ok(match (self.by_alpha)(code) {
+                Some(name) => format!("{} ({})", text, name),
+                None => text,
+            });
+        }
+        
+        let val = parse_varint_bytes(data)?;
+        let number = foreground_bold(3, &val.to_string());
+        Ok(match (self.by_number)(val) {
+            Some(name) => format!("{} ({})", number, name),
+            None => number,
+        })
+    }
+    
+    fn wire_type(&self) -> WireType {
+        WireType::Varint
+    }
+    
+    /// 数字代码是varint，字母代码是chunk
+    fn accepts_wire_type(&self, wire_type: WireType) -> bool {
+        matches!(wire_type, WireType::Varint | WireType::Chunk)
+    }
+}
+
+/// 带有取值名称的enum，`bitmask`为真时把值拆分为多个标志位
+pub struct EnumHandler {
+    pub values: Vec<(i64, String)>,
+    pub bitmask: bool,
+}
+
+impl EnumHandler {
+    /// 按标志位拆分时，已知的标志按声明顺序列出，没有对应标志的位显示为`+ 0x40 (unknown)`
+    fn describe(&self, val: i64, bitmask: bool) -> Option<String> {
+        if !bitmask || val == 0 {
+            return self.values.iter()
+                .find(|(v, _)| *v == val)
+                .map(|(_, name)| name.clone());
+        }
+        
+        let flags: Vec<&(i64, String)> = self.values.iter()
+            .filter(|(v, _)| *v != 0 && val & v == *v)
+            .collect();
+        let known = flags.iter().fold(0, |bits, (v, _)| bits | v);
+        let names: Vec<&str> = flags.iter().map(|(_, name)| name.as_str()).collect();
+        let unknown = val & !known;
+        match (names.is_empty(), unknown) {
+            (true, 0) => None,
+            (_, 0) => Some(names.join(" | ")),
+            (true, _) => Some(format!("+ 0x{:X} (unknown)", unknown as u64)),
+            (false, _) => Some(format!("{} + 0x{:X} (unknown)", names.join(" | "), unknown as u64)),
+        }
+    }
+}
+
+impl TypeHandler for EnumHandler {
+    fn parse(&self, data: &[u8], type_name: &str) -> Result<String, crate::core::Error> {
+        // enum按int32编码，负数会被符号扩展为10字节的varint；只取低32位，
+        // 这样没有符号扩展的5字节编码（例如0xFFFFFFFF）同样显示为负数
+        let val = parse_varint_bytes(data)? as i32 as i64;
+        let number = foreground_bold(3, &val.to_string());
+        // 普通enum也可以在schema中用`flags`修饰词按标志位显示
+        match self.describe(val, self.bitmask || has_modifier(type_name, "flags")) {
+            Some(name) => Ok(format!("{} ({})", number, name)),
+            // 没有声明任何值的enum（例如schema中直接写`enum`）只显示数值
+            None if self.values.is_empty() => Ok(number),
+            None => Ok(format!("{} (unknown)", number)),
+        }
+    }
+    
+    fn wire_type(&self) -> WireType {
+        WireType::Varint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed32_big_endian_epoch() {
+        // 1700000000 = 0x6553F100，按大端序存储
+        let data = b"\x65\x53\xf1\x00";
+        let result = Fixed32Handler.parse(data, "fixed32 be epoch_s").unwrap();
+        assert!(result.contains("1700000000"), "{}", result);
+        assert!(result.contains("(2023-11-14T22:13:20Z)"), "{}", result);
+        
+        let result = Fixed32Handler.parse(data, "fixed32").unwrap();
+        assert!(!result.contains("1700000000"), "{}", result);
+    }
+
+    #[test]
+    fn test_fixed_interpretations() {
+        let data = b"\x00\x00\x80\x3f";
+        assert_eq!(Bit32Handler.parse(data, "32bit").unwrap(), "0x3F800000, i32=1065353216, f32=1.0");
+        
+        let options = ParseOptions {
+            fixed_interpretations: vec![FixedInterpretation::Float],
+            ..ParseOptions::default()
+        };
+        assert_eq!(Bit32Handler.parse_with_options(data, "32bit", &options).unwrap(), "f32=1.0");
+        
+        let options = ParseOptions {
+            fixed_interpretations: vec![FixedInterpretation::Unsigned, FixedInterpretation::Signed, FixedInterpretation::Float],
+            ..ParseOptions::default()
+        };
+        assert_eq!(Bit32Handler.parse_with_options(b"\x00\x00\x80\xbf", "32bit", &options).unwrap(), "u32=3212836864, i32=-1082130432, f32=-1.0");
+        
+        let data = b"\x00\x00\x00\x00\x00\x00\xf0\x3f";
+        let options = ParseOptions {
+            fixed_interpretations: vec![FixedInterpretation::Unsigned, FixedInterpretation::Float],
+            ..ParseOptions::default()
+        };
+        assert_eq!(Bit64Handler.parse_with_options(data, "64bit", &options).unwrap(), "u64=4607182418800017408, f64=1.0");
+        assert_eq!(Bit64Handler.parse(data, "64bit").unwrap(), "0x3FF0000000000000, i64=4607182418800017408, f64=1.0");
+    }
+
+    #[test]
+    fn test_16bit_floats() {
+        use crate::core::encode_varint;
+        let parse = |handler: &dyn TypeHandler, bits: u64| handler.parse(&encode_varint(bits), "").unwrap();
+        
+        assert_eq!(parse(&Float16Handler, 0x3C00), foreground_bold(3, "+1.0"));
+        assert_eq!(parse(&Float16Handler, 0xC000), foreground_bold(3, "-2.0"));
+        assert_eq!(parse(&Float16Handler, 0x7BFF), foreground_bold(3, "+65504.0"));
+        assert_eq!(parse(&Float16Handler, 0x0001), foreground_bold(3, "+5.9604645e-8"));
+        assert_eq!(parse(&Float16Handler, 0xFC00), foreground_bold(3, "-inf"));
+        assert_eq!(parse(&BFloat16Handler, 0x3F80), foreground_bold(3, "+1.0"));
+        assert_eq!(parse(&BFloat16Handler, 0x4049), foreground_bold(3, "+3.140625"));
+        assert_eq!(parse(&BFloat16Handler, 0xC2F7), foreground_bold(3, "-123.5"));
+        
+        assert!(Float16Handler.parse(&encode_varint(0x10000), "").is_err());
+        assert!(BFloat16Handler.parse(&encode_varint(0x10000), "").is_err());
+    }
+
+    #[test]
+    fn test_max_integer_digits() {
+        let data = crate::core::encode_varint(1234567890123456789);
+        assert_eq!(VarintHandler.parse(&data, "varint").unwrap(), foreground_bold(3, "1234567890123456789"));
+        
+        let options = ParseOptions { max_integer_digits: Some(12), ..ParseOptions::default() };
+        let capped = foreground_bold(3, "1.235e18 (0x112210F47DE98115)");
+        assert_eq!(VarintHandler.parse_with_options(&data, "varint", &options).unwrap(), capped);
+        assert_eq!(UInt64Handler.parse_with_options(&data, "uint64", &options).unwrap(), capped);
+        assert_eq!(VarintHandler.parse_with_options(b"\x96\x01", "varint", &options).unwrap(), foreground_bold(3, "150"));
+    }
+
+    #[test]
+    fn test_iso_codes() {
+        use crate::iso_codes::*;
+        let country = IsoCodeHandler { by_number: country_by_number, by_alpha: country_by_alpha };
+        let currency = IsoCodeHandler { by_number: currency_by_number, by_alpha: currency_by_alpha };
+        
+        // 840 = 0xC8 0x06
+        assert_eq!(country.parse(b"\xc8\x06", "iso_country").unwrap(), format!("{} (United States)", foreground_bold(3, "840")));
+        assert_eq!(country.parse(b"JP", "iso_country").unwrap(), format!("{} (Japan)", foreground(2, "\"JP\"")));
+        assert_eq!(currency.parse(b"EUR", "iso_currency").unwrap(), format!("{} (Euro)", foreground(2, "\"EUR\"")));
+        
+        // 未知的代码只显示原始值
+        assert_eq!(country.parse(b"\x01", "iso_country").unwrap(), foreground_bold(3, "1"));
+        assert_eq!(currency.parse(b"XYZ", "iso_currency").unwrap(), foreground(2, "\"XYZ\""));
+    }
+    
+    #[test]
+    fn test_bitfield() {
+        use crate::core::encode_varint;
+        // width = 1920, height = 1080
+        let value = (1080u64 << 16) | 1920;
+        let result = BitfieldHandler.parse(&encode_varint(value), "bitfield [0:16]=width [16:32]=height").unwrap();
+        assert_eq!(result, format!("{} {{width = {}, height = {}}}",
+            foreground_bold(3, &value.to_string()), foreground_bold(3, "1920"), foreground_bold(3, "1080")));
+        
+        assert_eq!(BitfieldHandler::layout("bitfield 0:1=flag, 1:64=rest"), Some(vec![(0, 1, "flag"), (1, 64, "rest")]));
+        assert!(BitfieldHandler.parse(&encode_varint(value), "bitfield [16:0]=height").is_err());
+        assert!(BitfieldHandler.parse(&encode_varint(value), "bitfield [0:65]=all").is_err());
+    }
+    
+    #[test]
+    fn test_flag_enum_unknown_bits() {
+        let handler = EnumHandler {
+            values: vec![(4, "EXEC".to_string()), (1, "READ".to_string()), (2, "WRITE".to_string())],
+            bitmask: true,
+        };
+        // 按声明顺序列出，0x40没有对应的标志
+        let result = handler.parse(b"\x45", "Permission").unwrap();
+        assert_eq!(result, format!("{} (EXEC | READ + 0x40 (unknown))", foreground_bold(3, "69")));
+        assert_eq!(handler.parse(b"\x40", "Permission").unwrap(), format!("{} (+ 0x40 (unknown))", foreground_bold(3, "64")));
+        assert_eq!(handler.parse(b"\x03", "Permission").unwrap(), format!("{} (READ | WRITE)", foreground_bold(3, "3")));
+        
+        // 普通enum通过flags修饰词按标志位显示
+        let handler = EnumHandler { bitmask: false, ..handler };
+        assert_eq!(handler.parse(b"\x03", "Permission").unwrap(), format!("{} (unknown)", foreground_bold(3, "3")));
+        assert_eq!(handler.parse(b"\x03", "Permission flags").unwrap(), format!("{} (READ | WRITE)", foreground_bold(3, "3")));
+    }
+    
+    #[test]
+    fn test_fixed_signedness() {
+        let data = b"\xff\xff\xff\xff";
+        assert_eq!(Fixed32Handler.parse(data, "fixed32").unwrap(), foreground_bold(3, "4294967295"));
+        assert_eq!(SFixed32Handler.parse(data, "sfixed32").unwrap(), foreground_bold(3, "-1"));
+        
+        let data = b"\xfe\xff\xff\xff\xff\xff\xff\xff";
+        assert_eq!(Fixed64Handler.parse(data, "fixed64").unwrap(), foreground_bold(3, "18446744073709551614"));
+        assert_eq!(SFixed64Handler.parse(data, "sfixed64").unwrap(), foreground_bold(3, "-2"));
+        // 超出i64范围的fixed64不按时间戳显示
+        assert_eq!(Fixed64Handler.parse(data, "fixed64 epoch_s").unwrap(), foreground_bold(3, "18446744073709551614"));
+    }
+    
+    #[test]
+    fn test_packed_fixed_note() {
+        // 1000, 2000, 3000
+        let data = b"\xe8\x03\x00\x00\xd0\x07\x00\x00\xb8\x0b\x00\x00";
+        let options = ParseOptions { show_packed_fixed: true, ..ParseOptions::default() };
+        let result = ChunkHandler.parse_with_options(data, "chunk", &options).unwrap();
+        assert!(result.starts_with("bytes (12) (as packed fixed32: [1000, 2000, 3000])\n"), "{}", result);
+        assert!(!ChunkHandler.parse(data, "chunk").unwrap().contains("packed fixed"));
+        
+        // 只有一个元素时不给出解读
+        let result = ChunkHandler.parse_with_options(b"\xe8\x03\x00\x00", "chunk", &options).unwrap();
+        assert!(!result.contains("packed fixed"), "{}", result);
+    }
+    
+    #[test]
+    fn test_float_array_note() {
+        let options = ParseOptions { show_float_arrays: true, ..ParseOptions::default() };
+        let samples = [0.5f32, -1.25, 3.0, 0.0, 100.5, -0.75];
+        let data: Vec<u8> = samples.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let result = ChunkHandler.parse_with_options(&data, "chunk", &options).unwrap();
+        assert!(result.starts_with("bytes (24) (possible f32[6]: [0.5, -1.25, 3.0, 0.0, ...])\n"), "{}", result);
+        assert!(!ChunkHandler.parse(&data, "chunk").unwrap().contains("possible"));
+        
+        let samples = [1.5f64, -2.0, 1e-3, 42.0];
+        let data: Vec<u8> = samples.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let result = ChunkHandler.parse_with_options(&data, "chunk", &options).unwrap();
+        assert!(result.contains("(possible f64[4]: [1.5, -2.0, 0.001, 42.0])"), "{}", result);
+        assert!(!result.contains("possible f32"), "{}", result);
+        
+        // 元素太少、全为0或者数值不合理时不给出标注
+        for data in [&b"\x00\x00\x80\x3f\x00\x00\x80\x3f"[..], &[0; 32], &[0xff; 32], b"\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\x10"] {
+            let result = ChunkHandler.parse_with_options(data, "chunk", &options).unwrap();
+            assert!(!result.contains("possible"), "{}", result);
+        }
+    }
+    
+    #[test]
+    fn test_packed() {
+        let values = |items: &[&str]| format!("[{}]", items.iter().map(|v| foreground_bold(3, v)).collect::<Vec<_>>().join(", "));
+        
+        // 1, 2, 150, -3（int32的负数占10个字节）
+        let data = b"\x01\x02\x96\x01\xfd\xff\xff\xff\xff\xff\xff\xff\xff\x01";
+        assert_eq!(PackedHandler.parse(data, "int32 repeated").unwrap(), values(&["1", "2", "150", "-3"]));
+        assert_eq!(PackedHandler.parse(b"\x03\x04", "sint32 repeated").unwrap(), values(&["-2", "2"]));
+        
+        let data = b"\x01\x00\x00\x00\x00\x00\x00\x02";
+        assert_eq!(PackedHandler.parse(data, "fixed32 repeated").unwrap(), values(&["1", &0x02000000.to_string()]));
+        assert_eq!(PackedHandler.parse(data, "fixed32 repeated be").unwrap(), values(&["16777216", "2"]));
+        
+        // 长度不是元素大小的整数倍、元素类型不能packed时按普通chunk显示
+        assert!(PackedHandler.parse(b"\x01\x02\x03", "fixed32 repeated").unwrap().starts_with("bytes (3)"));
+        assert!(PackedHandler.parse(b"\x01\x02\x03", "bytes repeated").unwrap().starts_with("bytes (3)"));
+    }
+    
+    #[test]
+    fn test_zigzag_raw() {
+        let options = ParseOptions { show_zigzag_raw: true, ..ParseOptions::default() };
+        let expected = format!("{} (raw 9)", foreground_bold(3, "-5"));
+        assert_eq!(SInt32Handler.parse_with_options(b"\x09", "sint32", &options).unwrap(), expected);
+        assert_eq!(SInt64Handler.parse_with_options(b"\x09", "sint64", &options).unwrap(), expected);
+        assert_eq!(SInt32Handler.parse(b"\x09", "sint32").unwrap(), foreground_bold(3, "-5"));
+    }
+    
+    #[test]
+    fn test_string_encodings() {
+        assert_eq!(StringHandler.parse(b"caf\xe9", "string:latin1").unwrap(), foreground(2, "\"café\""));
+        assert_eq!(StringHandler.parse(b"\xd6\xd0\xce\xc4", "string:gbk").unwrap(), foreground(2, "\"中文\""));
+        assert!(StringHandler.parse(b"\xd6\xd0\xff", "string:gbk strict").is_err());
+    }
+    
+    #[test]
+    fn test_invalid_utf8_string() {
+        let result = StringHandler.parse(b"caf\xe9!", "string").unwrap();
+        assert_eq!(result, format!("{} {}", foreground(2, "\"caf\u{FFFD}!\""), dim("(invalid utf-8)")));
+        assert!(matches!(StringHandler.parse(b"caf\xe9!", "string strict"), Err(crate::core::Error::InvalidString)));
+        assert_eq!(StringHandler.parse(b"cafe", "string strict").unwrap(), foreground(2, "\"cafe\""));
+    }
+    
+    #[test]
+    fn test_string_with_nul() {
+        let options = ParseOptions { flag_nul_in_strings: true, ..ParseOptions::default() };
+        let result = StringHandler.parse_with_options(b"ab\0c", "string", &options).unwrap();
+        assert_eq!(result, format!("{} {}", foreground(2, "\"ab\0c\""), foreground(1, "(contains NUL)")));
+        assert_eq!(StringHandler.parse_with_options(b"abc", "string", &options).unwrap(), foreground(2, "\"abc\""));
+        assert_eq!(StringHandler.parse(b"ab\0c", "string").unwrap(), foreground(2, "\"ab\0c\""));
+    }
+}
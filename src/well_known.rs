@@ -0,0 +1,81 @@
+use crate::core::{parse_varint_bytes, read_identifier, read_value};
+use crate::formatter::{foreground, foreground_bold};
+use crate::grpc::FieldDefinition;
+use std::io::Cursor;
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+/// `google/protobuf`中的常用消息类型：(消息名, 字段列表)
+pub const WELL_KNOWN_TYPES: &[(&str, &[FieldDefinition])] = &[
+    ("google.protobuf.Duration", &[(1, "int64", "seconds"), (2, "int32", "nanos")]),
+];
+
+/// 可以换算为一个值的消息，在原始字段之前显示，例如Duration显示为`3.500s`
+pub fn summarize(type_name: &str, data: &[u8]) -> Option<String> {
+    match type_name {
+        "google.protobuf.Duration" => format_duration(data),
+        _ => None,
+    }
+}
+
+/// 与JSON映射相同，小数部分为0、3、6或9位。负数的seconds和nanos都不大于0，
+/// 两者符号不同或者nanos超出±1e9时标注为无效
+fn format_duration(data: &[u8]) -> Option<String> {
+    let mut cursor = Cursor::new(data);
+    let (mut seconds, mut nanos) = (0i64, 0i64);
+    while let Some((number, wire_type)) = read_identifier(&mut cursor).ok()? {
+        let value = read_value(&mut cursor, wire_type).ok()??;
+        let value = parse_varint_bytes(&value).ok().filter(|_| wire_type == 0)?;
+        match number {
+            1 => seconds = value as i64,
+            2 => nanos = value as i32 as i64,
+            _ => return None,
+        }
+    }
+
+    if nanos.abs() >= NANOS_PER_SECOND || (seconds > 0 && nanos < 0) || (seconds < 0 && nanos > 0) {
+        return Some(foreground(1, &format!("(invalid duration: {}s {}ns)", seconds, nanos)));
+    }
+    let sign = if seconds < 0 || nanos < 0 { "-" } else { "" };
+    let (seconds, nanos) = (seconds.unsigned_abs(), nanos.unsigned_abs());
+    let fraction = match nanos {
+        0 => String::new(),
+        n if n % 1_000_000 == 0 => format!(".{:03}", n / 1_000_000),
+        n if n % 1_000 == 0 => format!(".{:06}", n / 1_000),
+        n => format!(".{:09}", n),
+    };
+    Some(foreground_bold(3, &format!("{}{}{}s", sign, seconds, fraction)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::strip_ansi;
+    use crate::testutil::Payload;
+
+    fn duration(seconds: i64, nanos: i64) -> Option<String> {
+        let data = Payload::new().field(1).varint(seconds as u64).field(2).varint(nanos as u64).build();
+        summarize("google.protobuf.Duration", &data).map(|s| strip_ansi(&s))
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(duration(3, 500_000_000).as_deref(), Some("3.500s"));
+        assert_eq!(duration(1, 0).as_deref(), Some("1s"));
+        assert_eq!(duration(0, 1).as_deref(), Some("0.000000001s"));
+        assert_eq!(duration(0, 1_500).as_deref(), Some("0.000001500s"));
+        assert_eq!(duration(2, 10_000).as_deref(), Some("2.000010s"));
+        // 负数的seconds和nanos符号相同
+        assert_eq!(duration(-1, -500_000_000).as_deref(), Some("-1.500s"));
+        assert_eq!(duration(0, -500_000_000).as_deref(), Some("-0.500s"));
+        assert_eq!(duration(-1, 0).as_deref(), Some("-1s"));
+        // nanos超出范围，或者与seconds符号不同
+        assert_eq!(duration(0, 999_999_999).as_deref(), Some("0.999999999s"));
+        assert_eq!(duration(0, 1_000_000_000).as_deref(), Some("(invalid duration: 0s 1000000000ns)"));
+        assert_eq!(duration(1, -1).as_deref(), Some("(invalid duration: 1s -1ns)"));
+        // 缺少的字段为0，其他字段或线类型说明不是Duration
+        assert_eq!(summarize("google.protobuf.Duration", b"").map(|s| strip_ansi(&s)).as_deref(), Some("0s"));
+        assert_eq!(summarize("google.protobuf.Duration", b"\x18\x01"), None);
+        assert_eq!(summarize("google.protobuf.Duration", b"\x0a\x00"), None);
+    }
+}
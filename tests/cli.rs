@@ -0,0 +1,65 @@
+//! 运行编译好的命令行程序，检查参数的组合
+
+use protobuf_inspector_rs::formatter::strip_ansi;
+use protobuf_inspector_rs::schema::SCHEMA_ENV;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// 写入临时文件，文件名带上进程号避免并行的测试互相覆盖
+fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("protobuf-inspector-cli-{}-{}", std::process::id(), name));
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn test_delimited_segment_types() {
+    let schema = temp_file("schema.toml", b"[User]\n1 = [\"string\", \"name\"]\n[Order]\n1 = [\"uint64\", \"amount\"]\n");
+    // User {name: "abc"}, Order {amount: 150}, 第三条沿用最后一个类型
+    let input = temp_file("segments.bin", b"\x05\x0a\x03abc\x03\x08\x96\x01\x02\x08\x01");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_protobuf-inspector-rs"))
+        .env(SCHEMA_ENV, &schema)
+        .args(["--color=never", "--delimited", "--type", "User", "--type", "Order"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&schema).unwrap();
+    std::fs::remove_file(&input).unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = strip_ansi(&String::from_utf8_lossy(&output.stdout));
+    assert!(stdout.contains("[0] User:\n    1 name = \"abc\""), "{}", stdout);
+    assert!(stdout.contains("[1] Order:\n    1 amount = 150"), "{}", stdout);
+    assert!(stdout.contains("[2] Order:\n    1 amount = 1"), "{}", stdout);
+}
+
+#[test]
+fn test_segment_types_require_framing() {
+    let output = Command::new(env!("CARGO_BIN_EXE_protobuf-inspector-rs"))
+        .args(["--type", "root"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("--type requires --delimited or --grpc"));
+}
+
+#[test]
+fn test_max_depth_in_json() {
+    // {1: {1: {1: 1}}}
+    let input = temp_file("nested.bin", b"\x0a\x04\x0a\x02\x08\x01");
+    let output = Command::new(env!("CARGO_BIN_EXE_protobuf-inspector-rs"))
+        .args(["--format=json", "--max-depth=1"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&input).unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim_end(), concat!(
+        "[{\"field\": 1, \"wire_type\": 2, \"type\": \"message\", \"value\": ",
+        "[{\"field\": 1, \"wire_type\": 2, \"type\": \"bytes\", \"value\": \"0801\"}]}]",
+    ));
+}
@@ -0,0 +1,142 @@
+//! 与`protoc --decode_raw`对比字段结构（field number和线类型）
+//!
+//! 需要`protoc`在PATH中，使用`cargo test --features protoc-tests`运行。
+//! 语料在`tests/protoc_corpus`和`assets`中，新增的样本直接放进目录即可。
+
+use protobuf_inspector_rs::tree::{decode_message, Field};
+use protobuf_inspector_rs::types::WireType;
+
+/// 对比用的字段结构，`children`为`None`表示没有展开
+#[derive(Debug, PartialEq)]
+struct RawField {
+    number: u32,
+    kind: &'static str,
+    children: Option<Vec<RawField>>,
+}
+
+/// 把字段树转换为对比结构，扁平的group字段会被收进对应的group节点
+fn from_fields<'a>(fields: &mut impl Iterator<Item = &'a Field>) -> Vec<RawField> {
+    let mut result = Vec::new();
+    while let Some(field) = fields.next() {
+        let (kind, children) = match field.wire_type {
+            WireType::Varint => ("varint", None),
+            WireType::Bit64 => ("fixed64", None),
+            WireType::Bit32 => ("fixed32", None),
+            WireType::Chunk => ("chunk", field.message.as_ref().map(|m| from_fields(&mut m.fields.iter()))),
+            WireType::StartGroup => ("group", Some(from_fields(fields))),
+            WireType::EndGroup => break,
+        };
+        result.push(RawField { number: field.number, kind, children });
+    }
+    result
+}
+
+/// 解析`protoc --decode_raw`的输出，protoc不区分group和嵌套消息，统一记为`nested`
+fn from_decode_raw<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Vec<RawField> {
+    let mut result = Vec::new();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "}" {
+            break;
+        }
+
+        if let Some(number) = line.strip_suffix(" {") {
+            let children = from_decode_raw(lines);
+            result.push(RawField { number: number.parse().unwrap(), kind: "nested", children: Some(children) });
+            continue;
+        }
+
+        let (number, value) = line.split_once(": ").unwrap_or_else(|| panic!("unexpected line: {}", line));
+        let kind = if value.starts_with('"') {
+            "chunk"
+        } else {
+            match value.strip_prefix("0x") {
+                Some(hex) if hex.len() == 8 => "fixed32",
+                Some(hex) if hex.len() == 16 => "fixed64",
+                Some(_) => panic!("unexpected value: {}", value),
+                None => "varint",
+            }
+        };
+        result.push(RawField { number: number.parse().unwrap(), kind, children: None });
+    }
+    result
+}
+
+/// 两边的field number和线类型必须一致；chunk只在两边都展开时才继续比较
+fn compare(ours: &[RawField], theirs: &[RawField], path: &str) -> Result<(), String> {
+    if ours.len() != theirs.len() {
+        return Err(format!("{}: {} fields vs {} fields from protoc", path, ours.len(), theirs.len()));
+    }
+
+    for (a, b) in ours.iter().zip(theirs) {
+        let field_path = format!("{}/{}", path, a.number);
+        let same_kind = a.kind == b.kind
+            || (b.kind == "nested" && matches!(a.kind, "chunk" | "group"));
+        if a.number != b.number || !same_kind {
+            return Err(format!("{}: {} {} vs {} {} from protoc", field_path, a.number, a.kind, b.number, b.kind));
+        }
+        if let (Some(a_children), Some(b_children)) = (&a.children, &b.children) {
+            compare(a_children, b_children, &field_path)?;
+        } else if a.kind == "group" {
+            return Err(format!("{}: group was not nested in protoc output", field_path));
+        }
+    }
+    Ok(())
+}
+
+fn compare_with_decode_raw(data: &[u8], decode_raw_output: &str) -> Result<(), String> {
+    let message = decode_message(data).map_err(|e| format!("{:?}", e))?;
+    let ours = from_fields(&mut message.fields.iter());
+    let theirs = from_decode_raw(&mut decode_raw_output.lines());
+    compare(&ours, &theirs, "")
+}
+
+#[test]
+fn test_decode_raw_comparison() {
+    // `protoc --decode_raw < tests/protoc_corpus/group.bin`的输出
+    let data = std::fs::read("tests/protoc_corpus/group.bin").unwrap();
+    let output = "1 {\n  1: 1\n  2: \"abc\"\n}\n2: 2\n";
+    assert_eq!(compare_with_decode_raw(&data, output), Ok(()));
+
+    let output = "1 {\n  1: 1\n}\n2: 2\n";
+    assert!(compare_with_decode_raw(&data, output).is_err());
+    let output = "1 {\n  1: 1\n  2: \"abc\"\n}\n2: 0x00000002\n";
+    assert!(compare_with_decode_raw(&data, output).is_err());
+}
+
+#[cfg(feature = "protoc-tests")]
+#[test]
+fn test_corpus_matches_protoc() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut paths: Vec<_> = ["tests/protoc_corpus", "assets"].iter()
+        .flat_map(|dir| std::fs::read_dir(dir).unwrap())
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bin"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let mut failures = Vec::new();
+    for path in paths {
+        let data = std::fs::read(&path).unwrap();
+        let mut protoc = Command::new("protoc")
+            .arg("--decode_raw")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("protoc must be on PATH when the protoc-tests feature is enabled");
+        protoc.stdin.take().unwrap().write_all(&data).unwrap();
+        let output = protoc.wait_with_output().unwrap();
+        assert!(output.status.success(), "protoc failed on {}", path.display());
+
+        if let Err(e) = compare_with_decode_raw(&data, &String::from_utf8_lossy(&output.stdout)) {
+            failures.push(format!("{}: {}", path.display(), e));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}